@@ -0,0 +1,171 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+
+use serde_json::Value;
+
+//Enough of the 1.21.1 report for the tests. Building with BLOCKS_REPORT pointing at the full blocks.json
+//the data generator writes gets every block.
+const DEFAULT_BLOCKS_REPORT: &str = "tests/fixtures/blocks.json";
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=BLOCKS_REPORT");
+
+    let path = env::var("BLOCKS_REPORT").unwrap_or_else(|_| DEFAULT_BLOCKS_REPORT.to_owned());
+    let path = fs::canonicalize(&path)
+        .unwrap_or_else(|error| panic!("Failed to find {}: {}", path, error));
+    println!("cargo:rerun-if-changed={}", path.display());
+
+    let json = fs::read_to_string(&path)
+        .unwrap_or_else(|error| panic!("Failed to read {}: {}", path.display(), error));
+    let report: BTreeMap<String, Value> = serde_json::from_str(&json)
+        .unwrap_or_else(|error| panic!("Invalid block report {}: {}", path.display(), error));
+
+    //Variants follow the order of the state IDs like vanilla's Blocks
+    let mut blocks: Vec<(&String, &Value)> = report.iter().collect();
+    blocks.sort_by_key(|(name, block)| first_state(name, block));
+
+    //Each enum property gets one enum with the values of every block that has it, so e.g. facing
+    //covers both the horizontal and the six directions
+    let mut enums: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for (_, block) in &blocks {
+        let Some(properties) = block.get("properties").and_then(Value::as_object) else {
+            continue;
+        };
+
+        for (name, values) in properties {
+            let values: Vec<&str> = values
+                .as_array()
+                .unwrap_or_else(|| panic!("Values of {} aren't a list", name))
+                .iter()
+                .map(|value| value.as_str().expect("Property values are strings"))
+                .collect();
+            if !is_enum(&values) {
+                continue;
+            }
+
+            let known = enums.entry(name).or_default();
+            for value in values {
+                if !known.contains(&value) {
+                    known.push(value);
+                }
+            }
+        }
+    }
+
+    let mut code = String::new();
+    writeln!(code, "//Generated by build.rs from {}", path.display()).unwrap();
+    writeln!(code).unwrap();
+    writeln!(code, "pub const REPORT: &str = include_str!({:?});", path).unwrap();
+
+    let names: Vec<&str> = blocks.iter().map(|(name, _)| name.as_str()).collect();
+    let variants: Vec<String> = names.iter().map(|name| block_variant(name)).collect();
+    write_enum(&mut code, "BlockKind", &names, &variants);
+
+    for (property, values) in &enums {
+        let variants: Vec<String> = values.iter().map(|value| variant(value)).collect();
+        let name = variant(property);
+        write_enum(&mut code, &name, values, &variants);
+
+        writeln!(code).unwrap();
+        writeln!(code, "impl PropertyValue for {} {{", name).unwrap();
+        writeln!(code, "    fn from_value(value: &str) -> Option<Self> {{").unwrap();
+        writeln!(code, "        Self::from_name(value)").unwrap();
+        writeln!(code, "    }}").unwrap();
+        writeln!(code, "}}").unwrap();
+    }
+
+    let out = PathBuf::from(env::var("OUT_DIR").unwrap()).join("blocks.rs");
+    fs::write(&out, code)
+        .unwrap_or_else(|error| panic!("Failed to write {}: {}", out.display(), error));
+}
+
+fn first_state(name: &str, block: &Value) -> u64 {
+    block
+        .get("states")
+        .and_then(Value::as_array)
+        .and_then(|states| {
+            states
+                .iter()
+                .filter_map(|state| state.get("id")?.as_u64())
+                .min()
+        })
+        .unwrap_or_else(|| panic!("{} has no states", name))
+}
+
+//The same rule BlockRegistry uses: anything but true and false or a run of integers
+fn is_enum(values: &[&str]) -> bool {
+    if values == ["true", "false"] {
+        return false;
+    }
+
+    let ints: Option<Vec<i32>> = values.iter().map(|value| value.parse().ok()).collect();
+    match ints {
+        Some(ints) => !ints.windows(2).all(|pair| pair[1] == pair[0] + 1),
+        None => true,
+    }
+}
+
+//minecraft:oak_log becomes OakLog
+fn block_variant(name: &str) -> String {
+    variant(name.rsplit(':').next().unwrap())
+}
+
+//north_east becomes NorthEast, and values starting with a digit get a leading underscore
+fn variant(name: &str) -> String {
+    let mut variant: String = name
+        .split('_')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                None => String::new(),
+            }
+        })
+        .collect();
+
+    if variant.starts_with(|char: char| char.is_ascii_digit()) {
+        variant.insert(0, '_');
+    }
+
+    variant
+}
+
+//An enum with a name for each variant, and a lookup from those names
+fn write_enum(code: &mut String, name: &str, names: &[&str], variants: &[String]) {
+    writeln!(code).unwrap();
+    writeln!(code, "#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]").unwrap();
+    writeln!(code, "pub enum {} {{", name).unwrap();
+    for variant in variants {
+        writeln!(code, "    {},", variant).unwrap();
+    }
+    writeln!(code, "}}").unwrap();
+
+    writeln!(code).unwrap();
+    writeln!(code, "impl {} {{", name).unwrap();
+    writeln!(code, "    pub fn name(self) -> &'static str {{").unwrap();
+    writeln!(code, "        match self {{").unwrap();
+    for (variant, value) in variants.iter().zip(names) {
+        writeln!(code, "            {}::{} => {:?},", name, variant, value).unwrap();
+    }
+    writeln!(code, "        }}").unwrap();
+    writeln!(code, "    }}").unwrap();
+    writeln!(code).unwrap();
+    writeln!(code, "    pub fn from_name(name: &str) -> Option<Self> {{").unwrap();
+    writeln!(code, "        match name {{").unwrap();
+    for (variant, value) in variants.iter().zip(names) {
+        writeln!(
+            code,
+            "            {:?} => Some({}::{}),",
+            value, name, variant
+        )
+        .unwrap();
+    }
+    writeln!(code, "            _ => None,").unwrap();
+    writeln!(code, "        }}").unwrap();
+    writeln!(code, "    }}").unwrap();
+    writeln!(code, "}}").unwrap();
+}
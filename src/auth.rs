@@ -0,0 +1,291 @@
+#[cfg(test)]
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use md5::Md5;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::types::protocol_struct;
+
+pub const SESSION_SERVER_URL: &str = "https://sessionserver.mojang.com";
+
+#[derive(Debug, Error)]
+pub enum AuthError {
+    #[error("Failed to reach the session server: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("Session server answered with status {0}")]
+    UnexpectedStatus(u16),
+    #[error("Player {0} hasn't joined through the session server")]
+    NotAuthenticated(String),
+}
+
+protocol_struct! {
+    #[derive(Serialize, Deserialize)]
+    ProfileProperty {
+        name: String,
+        value: String,
+        signature: Option<String>,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayerProfile {
+    #[serde(rename = "id")]
+    pub uuid: Uuid,
+    pub name: String,
+    #[serde(default)]
+    pub properties: Vec<ProfileProperty>,
+}
+
+//Verifies that a player is who they claim to be, returning their profile
+#[async_trait]
+pub trait Authenticator: Send + Sync {
+    async fn authenticate(
+        &self,
+        username: &str,
+        server_hash: &str,
+    ) -> Result<PlayerProfile, AuthError>;
+}
+
+//Asks the session server's hasJoined endpoint, as online-mode vanilla servers do
+pub struct HttpAuthenticator {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl HttpAuthenticator {
+    pub fn new() -> Self {
+        Self::with_base_url(SESSION_SERVER_URL)
+    }
+
+    pub fn with_base_url(base_url: &str) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_owned(),
+        }
+    }
+}
+
+impl Default for HttpAuthenticator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Authenticator for HttpAuthenticator {
+    async fn authenticate(
+        &self,
+        username: &str,
+        server_hash: &str,
+    ) -> Result<PlayerProfile, AuthError> {
+        let response = self
+            .client
+            .get(format!("{}/session/minecraft/hasJoined", self.base_url))
+            .query(&[("username", username), ("serverId", server_hash)])
+            .send()
+            .await?;
+
+        match response.status().as_u16() {
+            200 => Ok(response.json().await?),
+            204 => Err(AuthError::NotAuthenticated(username.to_owned())),
+            status => Err(AuthError::UnexpectedStatus(status)),
+        }
+    }
+}
+
+//Trusts whatever name the client sends, deriving the same UUID an offline vanilla server would
+pub struct OfflineAuthenticator;
+
+#[async_trait]
+impl Authenticator for OfflineAuthenticator {
+    async fn authenticate(
+        &self,
+        username: &str,
+        _server_hash: &str,
+    ) -> Result<PlayerProfile, AuthError> {
+        Ok(PlayerProfile {
+            uuid: offline_uuid(username),
+            name: username.to_owned(),
+            properties: Vec::new(),
+        })
+    }
+}
+
+//Stand-in for the session server, only accepting the profiles it was given
+#[cfg(test)]
+#[derive(Default)]
+pub struct InMemoryAuthenticator {
+    profiles: HashMap<String, PlayerProfile>,
+}
+
+#[cfg(test)]
+impl InMemoryAuthenticator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_profile(&mut self, profile: PlayerProfile) {
+        self.profiles.insert(profile.name.clone(), profile);
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl Authenticator for InMemoryAuthenticator {
+    async fn authenticate(
+        &self,
+        username: &str,
+        _server_hash: &str,
+    ) -> Result<PlayerProfile, AuthError> {
+        self.profiles
+            .get(username)
+            .cloned()
+            .ok_or_else(|| AuthError::NotAuthenticated(username.to_owned()))
+    }
+}
+
+//Version 3 UUID of "OfflinePlayer:<name>", matching Java's UUID.nameUUIDFromBytes
+pub fn offline_uuid(username: &str) -> Uuid {
+    let digest = Md5::digest(format!("OfflinePlayer:{}", username).as_bytes());
+    uuid::Builder::from_md5_bytes(digest.into()).into_uuid()
+}
+
+//SHA-1 of the server ID, shared secret and public key, printed as a signed hex number
+//the way Java's BigInteger.toString(16) does
+pub fn server_hash(server_id: &str, shared_secret: &[u8], public_key_der: &[u8]) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(server_id.as_bytes());
+    hasher.update(shared_secret);
+    hasher.update(public_key_der);
+    let mut digest: [u8; 20] = hasher.finalize().into();
+
+    let negative = digest[0] & 0x80 != 0;
+
+    if negative {
+        //Two's complement to get the magnitude
+        let mut carry = true;
+        for byte in digest.iter_mut().rev() {
+            *byte = !*byte;
+            if carry {
+                let (value, overflow) = byte.overflowing_add(1);
+                *byte = value;
+                carry = overflow;
+            }
+        }
+    }
+
+    let hex: String = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+    let hex = hex.trim_start_matches('0');
+
+    if negative {
+        format!("-{}", hex)
+    } else {
+        hex.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn sha1_hex(name: &str) -> String {
+        server_hash(name, &[], &[])
+    }
+
+    #[test]
+    fn test_server_hash() {
+        assert_eq!(sha1_hex("Notch"), "4ed1f46bbe04bc756bcb17c0c7ce3e4632f06a48");
+        assert_eq!(sha1_hex("jeb_"), "-7c9d5b0044c130109a5d7b5fb5c317c02b4e28c1");
+        assert_eq!(sha1_hex("simon"), "88e16a1019277b15d58faf0541e11910eb756f6");
+    }
+
+    #[test]
+    fn test_offline_uuid() {
+        let uuid = offline_uuid("Notch");
+
+        assert_eq!(uuid.to_string(), "b50ad385-829d-3141-a216-7e7d7539ba7f");
+        assert_eq!(uuid.get_version_num(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_authenticator() {
+        let mut authenticator = InMemoryAuthenticator::new();
+        authenticator.add_profile(PlayerProfile {
+            uuid: Uuid::from_u128(1),
+            name: "Steve".to_owned(),
+            properties: Vec::new(),
+        });
+
+        assert_eq!(
+            authenticator.authenticate("Steve", "").await.unwrap().uuid,
+            Uuid::from_u128(1)
+        );
+        assert!(matches!(
+            authenticator.authenticate("Alex", "").await,
+            Err(AuthError::NotAuthenticated(_))
+        ));
+    }
+
+    //Answers a single HTTP request with the given response, returning the request line
+    async fn serve_once(response: String) -> (String, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        let handle = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = vec![0u8; 4096];
+            let size = stream.read(&mut request).await.unwrap();
+            stream.write_all(response.as_bytes()).await.unwrap();
+
+            String::from_utf8_lossy(&request[..size])
+                .lines()
+                .next()
+                .unwrap()
+                .to_owned()
+        });
+
+        (url, handle)
+    }
+
+    #[tokio::test]
+    async fn test_http_authenticator() {
+        let body = r#"{"id":"069a79f444e94726a5befca90e38aaf5","name":"Notch","properties":[{"name":"textures","value":"e30=","signature":"c2ln"}]}"#;
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        let (url, handle) = serve_once(response).await;
+
+        let profile = HttpAuthenticator::with_base_url(&url)
+            .authenticate("Notch", "-7c9d5b")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            handle.await.unwrap(),
+            "GET /session/minecraft/hasJoined?username=Notch&serverId=-7c9d5b HTTP/1.1"
+        );
+        assert_eq!(profile.uuid, Uuid::from_u128(0x069a79f444e94726a5befca90e38aaf5));
+        assert_eq!(profile.properties[0].signature.as_deref(), Some("c2ln"));
+    }
+
+    #[tokio::test]
+    async fn test_http_authenticator_not_joined() {
+        let (url, _handle) =
+            serve_once("HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n".to_owned()).await;
+
+        assert!(matches!(
+            HttpAuthenticator::with_base_url(&url)
+                .authenticate("Notch", "abc")
+                .await,
+            Err(AuthError::NotAuthenticated(_))
+        ));
+    }
+}
@@ -0,0 +1,560 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::chunk::PaletteMapping;
+use crate::nbt::{Compound, Tag};
+
+//A BlockKind for every block of the report build.rs was given, an enum for each property whose values
+//are names, and that report itself. Code only ever names a few of the blocks and values.
+#[allow(dead_code)]
+mod generated {
+    use super::PropertyValue;
+
+    include!(concat!(env!("OUT_DIR"), "/blocks.rs"));
+}
+
+pub use generated::*;
+
+#[derive(Debug, Error)]
+pub enum BlockRegistryError {
+    #[error("Failed to access {path}: {source}")]
+    Io { path: PathBuf, source: io::Error },
+    #[error("Invalid block report: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Invalid block {block}: {reason}")]
+    InvalidBlock { block: String, reason: String },
+}
+
+//A block as the vanilla data generator's blocks.json report describes it
+#[derive(Debug, Deserialize)]
+struct BlockReport {
+    //Sorted by name, which is also the order vanilla combines them into states in
+    #[serde(default)]
+    properties: BTreeMap<String, Vec<String>>,
+    states: Vec<StateReport>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StateReport {
+    id: u32,
+    #[serde(default)]
+    default: bool,
+    #[serde(default)]
+    properties: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PropertyKind {
+    Bool,
+    //Every integer from min to max
+    Int { min: i32, max: i32 },
+    //Named values like the axis of a log
+    Enum,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Property {
+    pub name: String,
+    pub kind: PropertyKind,
+    //In the order their states are numbered in
+    pub values: Vec<String>,
+}
+
+impl Property {
+    //The report only lists the values, so the kind is told by what they look like
+    fn new(name: String, values: Vec<String>) -> Self {
+        let ints: Option<Vec<i32>> = values.iter().map(|value| value.parse().ok()).collect();
+
+        let kind = if values == ["true", "false"] {
+            PropertyKind::Bool
+        } else {
+            match ints {
+                Some(ints) if ints.windows(2).all(|pair| pair[1] == pair[0] + 1) => {
+                    PropertyKind::Int {
+                        min: ints[0],
+                        max: ints[ints.len() - 1],
+                    }
+                }
+                _ => PropertyKind::Enum,
+            }
+        };
+
+        Self { name, kind, values }
+    }
+
+    pub fn index_of(&self, value: &str) -> Option<usize> {
+        self.values.iter().position(|known| known == value)
+    }
+}
+
+//What the values of a property can be read as: bool, i32 or the property's generated enum
+pub trait PropertyValue: Sized + PartialEq {
+    fn from_value(value: &str) -> Option<Self>;
+}
+
+impl PropertyValue for bool {
+    fn from_value(value: &str) -> Option<Self> {
+        value.parse().ok()
+    }
+}
+
+impl PropertyValue for i32 {
+    fn from_value(value: &str) -> Option<Self> {
+        value.parse().ok()
+    }
+}
+
+//A block and the range of state IDs its property combinations take
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub kind: BlockKind,
+    pub properties: Vec<Property>,
+    pub first_state: u32,
+    pub default_state: u32,
+}
+
+impl Block {
+    pub fn state_count(&self) -> u32 {
+        self.properties
+            .iter()
+            .map(|property| property.values.len() as u32)
+            .product()
+    }
+
+    pub fn states(&self) -> Range<u32> {
+        self.first_state..self.first_state + self.state_count()
+    }
+
+    //Like vanilla, the last property changes fastest between consecutive state IDs
+    fn state_id(&self, indices: &[usize]) -> u32 {
+        let offset = self
+            .properties
+            .iter()
+            .zip(indices)
+            .fold(0, |offset, (property, &index)| {
+                offset * property.values.len() + index
+            });
+        self.first_state + offset as u32
+    }
+
+    fn value_indices(&self, state: u32) -> Vec<usize> {
+        let mut offset = (state - self.first_state) as usize;
+        let mut indices = vec![0; self.properties.len()];
+
+        for (index, property) in indices.iter_mut().zip(&self.properties).rev() {
+            *index = offset % property.values.len();
+            offset /= property.values.len();
+        }
+
+        indices
+    }
+}
+
+//One state of a block, with the value of each of its properties
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockState<'a> {
+    pub id: u32,
+    pub block: &'a Block,
+}
+
+impl<'a> BlockState<'a> {
+    //Checking and changing states is for block updates, which the world doesn't have yet
+    #[allow(dead_code)]
+    pub fn is_default(&self) -> bool {
+        self.id == self.block.default_state
+    }
+
+    pub fn properties(&self) -> impl Iterator<Item = (&'a str, &'a str)> {
+        let block = self.block;
+        block
+            .properties
+            .iter()
+            .zip(block.value_indices(self.id))
+            .map(|(property, index)| (property.name.as_str(), property.values[index].as_str()))
+    }
+
+    pub fn value(&self, name: &str) -> Option<&'a str> {
+        self.properties()
+            .find(|(property, _)| *property == name)
+            .map(|(_, value)| value)
+    }
+
+    //Like vanilla's getValue, e.g. state.get::<Axis>("axis")
+    #[allow(dead_code)]
+    pub fn get<T: PropertyValue>(&self, name: &str) -> Option<T> {
+        self.value(name).and_then(T::from_value)
+    }
+
+    //The state with one property changed, like vanilla's setValue
+    #[allow(dead_code)]
+    pub fn with<T: PropertyValue>(&self, name: &str, value: T) -> Option<u32> {
+        let position = self
+            .block
+            .properties
+            .iter()
+            .position(|property| property.name == name)?;
+        let mut indices = self.block.value_indices(self.id);
+        indices[position] = self.block.properties[position]
+            .values
+            .iter()
+            .position(|known| T::from_value(known).as_ref() == Some(&value))?;
+
+        Some(self.block.state_id(&indices))
+    }
+}
+
+//Like the F3 screen and commands show it, e.g. minecraft:oak_log[axis=x]
+impl fmt::Display for BlockState<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.block.kind.name())?;
+
+        let properties: Vec<_> = self
+            .properties()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        if !properties.is_empty() {
+            write!(f, "[{}]", properties.join(","))?;
+        }
+
+        Ok(())
+    }
+}
+
+//Every block and block state of a version, read from the blocks.json report the vanilla data generator
+//writes with: java -DbundlerMainClass=net.minecraft.data.Main -jar server.jar --reports
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockRegistry {
+    blocks: Vec<Block>,
+    by_kind: HashMap<BlockKind, usize>,
+    //The index of the block each state ID belongs to
+    state_blocks: Vec<usize>,
+}
+
+impl BlockRegistry {
+    //The report the block kinds were generated from
+    pub fn builtin() -> Self {
+        Self::from_json(REPORT).expect("build.rs generated the block kinds from a valid report")
+    }
+
+    pub fn load(path: &Path) -> Result<Self, BlockRegistryError> {
+        let json = fs::read_to_string(path).map_err(|source| BlockRegistryError::Io {
+            path: path.to_owned(),
+            source,
+        })?;
+
+        Self::from_json(&json)
+    }
+
+    //Checks that the states are numbered like vanilla numbers them, which lets state IDs be
+    //calculated from property values instead of looking every state up
+    pub fn from_json(json: &str) -> Result<Self, BlockRegistryError> {
+        let reports: HashMap<String, BlockReport> = serde_json::from_str(json)?;
+
+        let mut blocks = reports
+            .into_iter()
+            .map(|(name, report)| parse_block(name, report))
+            .collect::<Result<Vec<_>, _>>()?;
+        blocks.sort_by_key(|block| block.first_state);
+
+        let mut state_blocks = Vec::new();
+        let mut by_kind = HashMap::new();
+        for (index, block) in blocks.iter().enumerate() {
+            if block.first_state as usize != state_blocks.len() {
+                return Err(BlockRegistryError::InvalidBlock {
+                    block: block.kind.name().to_owned(),
+                    reason: format!("Expected its states to start at {}", state_blocks.len()),
+                });
+            }
+
+            state_blocks.extend(block.states().map(|_| index));
+            by_kind.insert(block.kind, index);
+        }
+
+        Ok(Self {
+            blocks,
+            by_kind,
+            state_blocks,
+        })
+    }
+
+    //Lookups by name are for world generation and commands, neither of which exists yet
+    #[allow(dead_code)]
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    pub fn block(&self, kind: BlockKind) -> Option<&Block> {
+        self.by_kind.get(&kind).map(|&index| &self.blocks[index])
+    }
+
+    //Also the number of values the direct palette of block states has to fit
+    pub fn state_count(&self) -> u32 {
+        self.state_blocks.len() as u32
+    }
+
+    pub fn state(&self, id: u32) -> Option<BlockState<'_>> {
+        self.state_blocks.get(id as usize).map(|&index| BlockState {
+            id,
+            block: &self.blocks[index],
+        })
+    }
+
+    #[allow(dead_code)]
+    pub fn default_state(&self, kind: BlockKind) -> Option<u32> {
+        self.block(kind).map(|block| block.default_state)
+    }
+}
+
+fn parse_block(name: String, report: BlockReport) -> Result<Block, BlockRegistryError> {
+    let invalid = |reason: String| BlockRegistryError::InvalidBlock {
+        block: name.clone(),
+        reason,
+    };
+
+    //Blocks the server wasn't built with couldn't be told apart in code
+    let kind = BlockKind::from_name(&name)
+        .ok_or_else(|| invalid("Not in the report the server was built with".to_owned()))?;
+
+    let properties: Vec<Property> = report
+        .properties
+        .into_iter()
+        .filter(|(_, values)| !values.is_empty())
+        .map(|(name, values)| Property::new(name, values))
+        .collect();
+    let first_state = report
+        .states
+        .iter()
+        .map(|state| state.id)
+        .min()
+        .ok_or_else(|| invalid("No states".to_owned()))?;
+
+    let mut block = Block {
+        kind,
+        properties,
+        first_state,
+        default_state: first_state,
+    };
+    if report.states.len() as u32 != block.state_count() {
+        return Err(invalid(format!(
+            "{} states for {} property combinations",
+            report.states.len(),
+            block.state_count()
+        )));
+    }
+
+    let mut defaults = 0;
+    for state in &report.states {
+        let indices = block
+            .properties
+            .iter()
+            .map(|property| {
+                state
+                    .properties
+                    .get(&property.name)
+                    .and_then(|value| property.index_of(value))
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| invalid(format!("State {} has invalid properties", state.id)))?;
+
+        if block.state_id(&indices) != state.id {
+            return Err(invalid(format!("State {} is out of order", state.id)));
+        }
+        if state.default {
+            block.default_state = state.id;
+            defaults += 1;
+        }
+    }
+
+    if defaults != 1 {
+        return Err(invalid(format!("{} default states", defaults)));
+    }
+
+    Ok(block)
+}
+
+//Anvil palettes store states as the block's name and its property values
+impl PaletteMapping for BlockRegistry {
+    fn tag_of(&self, value: u32) -> Option<Tag> {
+        let state = self.state(value)?;
+        let mut compound = Compound::from([(
+            "Name".to_owned(),
+            Tag::String(state.block.kind.name().to_owned()),
+        )]);
+
+        let properties: Compound = state
+            .properties()
+            .map(|(name, value)| (name.to_owned(), Tag::from(value)))
+            .collect();
+        if !properties.is_empty() {
+            compound.insert("Properties".to_owned(), Tag::Compound(properties));
+        }
+
+        Some(Tag::Compound(compound))
+    }
+
+    //Missing or unknown property values keep those of the default state, like vanilla
+    fn value_of(&self, tag: &Tag) -> Option<u32> {
+        let compound = tag.as_compound()?;
+        let kind = BlockKind::from_name(compound.get("Name")?.as_str()?)?;
+        let block = self.block(kind)?;
+
+        let mut indices = block.value_indices(block.default_state);
+        if let Some(properties) = compound.get("Properties").and_then(Tag::as_compound) {
+            for (index, property) in indices.iter_mut().zip(&block.properties) {
+                let value = properties.get(&property.name).and_then(Tag::as_str);
+                if let Some(value) = value.and_then(|value| property.index_of(value)) {
+                    *index = value;
+                }
+            }
+        }
+
+        Some(block.state_id(&indices))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //The first blocks of the 1.21.1 report, up to and including oak logs. Read directly since the
+    //built in report is a different one when building with BLOCKS_REPORT.
+    const BLOCKS: &str = include_str!("../tests/fixtures/blocks.json");
+
+    fn registry() -> BlockRegistry {
+        BlockRegistry::from_json(BLOCKS).unwrap()
+    }
+
+    #[test]
+    fn test_load_report() {
+        let registry = registry();
+
+        assert_eq!(registry.state_count(), 133);
+        assert_eq!(registry.blocks().len(), 47);
+        assert_eq!(registry.default_state(BlockKind::Air), Some(0));
+        assert_eq!(registry.default_state(BlockKind::GrassBlock), Some(9));
+        assert_eq!(registry.default_state(BlockKind::Water), Some(80));
+        assert_eq!(registry.default_state(BlockKind::OakLog), Some(131));
+        assert!(BlockRegistry::builtin().state_count() >= 133);
+    }
+
+    #[test]
+    fn test_generated_kinds() {
+        assert_eq!(
+            BlockKind::from_name("minecraft:oak_log"),
+            Some(BlockKind::OakLog)
+        );
+        assert_eq!(BlockKind::OakLog.name(), "minecraft:oak_log");
+        assert_eq!(BlockKind::from_name("minecraft:oak_logs"), None);
+
+        assert_eq!(Axis::from_name("x"), Some(Axis::X));
+        assert_eq!(Axis::Z.name(), "z");
+        assert_eq!(Axis::from_value("w"), None);
+    }
+
+    #[test]
+    fn test_properties() {
+        let registry = registry();
+        let propagule = registry.block(BlockKind::MangrovePropagule).unwrap();
+
+        assert_eq!(propagule.states(), 39..79);
+        assert_eq!(propagule.default_state, 44);
+        assert_eq!(
+            propagule.properties[0].kind,
+            PropertyKind::Int { min: 0, max: 4 }
+        );
+        assert_eq!(propagule.properties[1].kind, PropertyKind::Bool);
+
+        let state = registry.state(44).unwrap();
+        assert!(state.is_default());
+        assert_eq!(state.get("hanging"), Some(false));
+        assert_eq!(state.get("age"), Some(0));
+        assert_eq!(state.get::<i32>("hanging"), None);
+        assert_eq!(
+            state.to_string(),
+            "minecraft:mangrove_propagule[age=0,hanging=false,stage=0,waterlogged=false]"
+        );
+
+        let grown = registry.state(state.with("age", 4).unwrap()).unwrap();
+        assert_eq!(grown.get("age"), Some(4));
+        assert_eq!(grown.with("age", 0), Some(44));
+        assert_eq!(state.with("age", 5), None);
+        assert_eq!(state.with("axis", Axis::X), None);
+        assert_eq!(registry.state(1).unwrap().to_string(), "minecraft:stone");
+    }
+
+    #[test]
+    fn test_enum_properties() {
+        let registry = registry();
+        let log = registry.state(131).unwrap();
+
+        assert_eq!(log.block.kind, BlockKind::OakLog);
+        assert_eq!(log.block.properties[0].kind, PropertyKind::Enum);
+        assert_eq!(log.get("axis"), Some(Axis::Y));
+        assert_eq!(log.get::<bool>("axis"), None);
+        assert_eq!(log.with("axis", Axis::X), Some(130));
+        assert_eq!(
+            registry.state(132).unwrap().to_string(),
+            "minecraft:oak_log[axis=z]"
+        );
+    }
+
+    #[test]
+    fn test_palette_mapping() {
+        let registry = registry();
+        let tag = registry.tag_of(12).unwrap();
+
+        assert_eq!(
+            tag.to_string(),
+            r#"{Name:"minecraft:podzol",Properties:{snowy:"true"}}"#
+        );
+        assert_eq!(registry.value_of(&tag), Some(12));
+        assert_eq!(
+            registry.value_of(&"{Name:\"minecraft:podzol\"}".parse().unwrap()),
+            Some(13)
+        );
+        assert_eq!(
+            registry.value_of(
+                &"{Name:\"minecraft:water\",Properties:{level:\"99\"}}"
+                    .parse()
+                    .unwrap()
+            ),
+            Some(80)
+        );
+        assert_eq!(
+            registry.value_of(&"{Name:\"test:missing\"}".parse().unwrap()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_invalid_report() {
+        let out_of_order = r#"{
+            "minecraft:grass_block": {
+                "properties": { "snowy": ["true", "false"] },
+                "states": [
+                    { "id": 0, "default": true, "properties": { "snowy": "false" } },
+                    { "id": 1, "properties": { "snowy": "true" } }
+                ]
+            }
+        }"#;
+        assert!(matches!(
+            BlockRegistry::from_json(out_of_order),
+            Err(BlockRegistryError::InvalidBlock { .. })
+        ));
+
+        let gap = r#"{ "minecraft:stone": { "states": [{ "id": 1, "default": true }] } }"#;
+        assert!(BlockRegistry::from_json(gap).is_err());
+
+        let unknown = r#"{ "test:stone": { "states": [{ "id": 0, "default": true }] } }"#;
+        assert!(matches!(
+            BlockRegistry::from_json(unknown),
+            Err(BlockRegistryError::InvalidBlock { .. })
+        ));
+    }
+}
@@ -0,0 +1,1320 @@
+use std::collections::{BTreeMap, HashMap};
+
+use bytes::{Buf, BytesMut};
+
+use crate::nbt::{Compound, Tag};
+use crate::packet::play::{ChunkData, ChunkDataAndUpdateLight, LightData};
+use crate::reader::{ProtocolBufferReaderExt, ProtocolError};
+use crate::region::ChunkPos;
+use crate::registry::Registry;
+use crate::types::{BitSet, Encode};
+use crate::writer::ProtocolBufferWriterExt;
+
+pub const SECTION_SIZE: usize = 16 * 16 * 16;
+//Light levels are nibbles, two per byte
+pub const LIGHT_SIZE: usize = SECTION_SIZE / 2;
+//The block state new sections are filled with
+pub const AIR: u32 = 0;
+//The data version of 1.21.1, which tells vanilla what upgrades a saved chunk needs
+pub const DATA_VERSION: i32 = 3955;
+
+//The bits needed to tell apart this many values
+fn ceil_log2(values: usize) -> u8 {
+    match values {
+        0 | 1 => 0,
+        _ => (usize::BITS - (values - 1).leading_zeros()) as u8,
+    }
+}
+
+fn invalid(message: String) -> ProtocolError {
+    ProtocolError::InvalidData(message)
+}
+
+//Vanilla's SimpleBitStorage: entries of a fixed number of bits packed into longs from the lowest
+//bit up, never spanning two longs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BitStorage {
+    bits: u8,
+    size: usize,
+    data: Vec<i64>,
+}
+
+impl BitStorage {
+    pub fn new(bits: u8, size: usize) -> Self {
+        Self {
+            bits,
+            size,
+            data: vec![0; Self::longs(bits, size)],
+        }
+    }
+
+    pub fn from_data(bits: u8, size: usize, data: Vec<i64>) -> Result<Self, ProtocolError> {
+        let longs = Self::longs(bits, size);
+        if data.len() != longs {
+            return Err(invalid(format!(
+                "{} entries of {} bits take {} longs, not {}",
+                size,
+                bits,
+                longs,
+                data.len()
+            )));
+        }
+
+        Ok(Self { bits, size, data })
+    }
+
+    fn longs(bits: u8, size: usize) -> usize {
+        match bits {
+            0 => 0,
+            _ => size.div_ceil(64 / bits as usize),
+        }
+    }
+
+    fn locate(&self, index: usize) -> (usize, u32, u64) {
+        assert!(
+            index < self.size,
+            "Entry {} is out of range for {}",
+            index,
+            self.size
+        );
+        let per_long = 64 / self.bits as usize;
+        let shift = (index % per_long) as u32 * self.bits as u32;
+        (index / per_long, shift, (1 << self.bits) - 1)
+    }
+
+    pub fn get(&self, index: usize) -> u32 {
+        if self.bits == 0 {
+            return 0;
+        }
+
+        let (long, shift, mask) = self.locate(index);
+        ((self.data[long] as u64 >> shift) & mask) as u32
+    }
+
+    pub fn set(&mut self, index: usize, value: u32) {
+        let (long, shift, mask) = self.locate(index);
+        debug_assert!(
+            value as u64 <= mask,
+            "{} doesn't fit {} bits",
+            value,
+            self.bits
+        );
+
+        let long = &mut self.data[long];
+        *long = ((*long as u64 & !(mask << shift)) | ((value as u64 & mask) << shift)) as i64;
+    }
+
+    pub fn bits(&self) -> u8 {
+        self.bits
+    }
+
+    pub fn data(&self) -> &[i64] {
+        &self.data
+    }
+}
+
+//What a paletted container holds, which decides its size and how many bits each palette uses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContainerKind {
+    //The container is a cube of 2^edge_bits entries along each side
+    edge_bits: u8,
+    min_indirect_bits: u8,
+    max_indirect_bits: u8,
+    //Enough bits for any value, which depends on the size of the registry
+    direct_bits: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PaletteType {
+    Single,
+    Indirect,
+    Direct,
+}
+
+impl ContainerKind {
+    //A section's 16x16x16 blocks, with the number of block states in the registry
+    pub fn block_states(state_count: usize) -> Self {
+        Self {
+            edge_bits: 4,
+            min_indirect_bits: 4,
+            max_indirect_bits: 8,
+            direct_bits: ceil_log2(state_count),
+        }
+    }
+
+    //A section's 4x4x4 biomes, with the number of biomes in the registry
+    pub fn biomes(biome_count: usize) -> Self {
+        Self {
+            edge_bits: 2,
+            min_indirect_bits: 1,
+            max_indirect_bits: 3,
+            direct_bits: ceil_log2(biome_count),
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        1 << (3 * self.edge_bits)
+    }
+
+    pub fn edge(&self) -> usize {
+        1 << self.edge_bits
+    }
+
+    fn index(&self, x: usize, y: usize, z: usize) -> usize {
+        let edge = self.edge();
+        assert!(
+            x < edge && y < edge && z < edge,
+            "{} {} {} is out of range for {}",
+            x,
+            y,
+            z,
+            edge
+        );
+        (((y << self.edge_bits) | z) << self.edge_bits) | x
+    }
+
+    //The palette and bits vanilla uses when an entry needs this many bits
+    fn configure(&self, bits: u8) -> (PaletteType, u8) {
+        match bits {
+            0 => (PaletteType::Single, 0),
+            bits if bits <= self.max_indirect_bits => {
+                (PaletteType::Indirect, bits.max(self.min_indirect_bits))
+            }
+            _ => (PaletteType::Direct, self.direct_bits),
+        }
+    }
+
+    //Anvil files always use a palette, even past the bits of an indirect one
+    fn storage_bits(&self, palette_length: usize) -> u8 {
+        let bits = ceil_log2(palette_length);
+        match self.configure(bits) {
+            (PaletteType::Direct, _) => bits,
+            (_, bits) => bits,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Palette {
+    Single(u32),
+    Indirect(Vec<u32>),
+    Direct,
+}
+
+//Block states or biomes of a section. Storing them as indices into a palette of the values that occur
+//keeps sections small, and the palette is dropped once the indices would be as large as the values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PalettedContainer {
+    kind: ContainerKind,
+    palette: Palette,
+    storage: BitStorage,
+}
+
+impl PalettedContainer {
+    pub fn new(kind: ContainerKind, value: u32) -> Self {
+        Self {
+            kind,
+            palette: Palette::Single(value),
+            storage: BitStorage::new(0, kind.size()),
+        }
+    }
+
+    //Packs the values with the smallest palette that fits them
+    pub fn from_values(kind: ContainerKind, values: &[u32]) -> Self {
+        assert_eq!(values.len(), kind.size(), "Wrong number of values");
+
+        let mut palette = Vec::new();
+        let mut indices = HashMap::new();
+        for &value in values {
+            indices.entry(value).or_insert_with(|| {
+                palette.push(value);
+                palette.len() as u32 - 1
+            });
+        }
+
+        let (palette_type, bits) = kind.configure(ceil_log2(palette.len()));
+        let mut storage = BitStorage::new(bits, kind.size());
+        let palette = match palette_type {
+            PaletteType::Single => Palette::Single(palette[0]),
+            PaletteType::Indirect => {
+                for (index, value) in values.iter().enumerate() {
+                    storage.set(index, indices[value]);
+                }
+                Palette::Indirect(palette)
+            }
+            PaletteType::Direct => {
+                for (index, &value) in values.iter().enumerate() {
+                    storage.set(index, value);
+                }
+                Palette::Direct
+            }
+        };
+
+        Self {
+            kind,
+            palette,
+            storage,
+        }
+    }
+
+    pub fn kind(&self) -> ContainerKind {
+        self.kind
+    }
+
+    pub fn get(&self, x: usize, y: usize, z: usize) -> u32 {
+        self.get_index(self.kind.index(x, y, z))
+    }
+
+    fn get_index(&self, index: usize) -> u32 {
+        match &self.palette {
+            Palette::Single(value) => *value,
+            Palette::Indirect(palette) => palette[self.storage.get(index) as usize],
+            Palette::Direct => self.storage.get(index),
+        }
+    }
+
+    //Returns the value that was there before
+    pub fn set(&mut self, x: usize, y: usize, z: usize, value: u32) -> u32 {
+        let index = self.kind.index(x, y, z);
+        let old = self.get_index(index);
+        if old == value {
+            return old;
+        }
+
+        let id = match &mut self.palette {
+            Palette::Single(_) => None,
+            Palette::Indirect(palette) => match palette.iter().position(|&entry| entry == value) {
+                Some(id) => Some(id as u32),
+                None if palette.len() < 1 << self.storage.bits() => {
+                    palette.push(value);
+                    Some(palette.len() as u32 - 1)
+                }
+                None => None,
+            },
+            Palette::Direct => Some(value),
+        };
+
+        match id {
+            Some(id) => self.storage.set(index, id),
+            //The palette is full, so repack everything with more bits
+            None => {
+                let mut values = self.values();
+                values[index] = value;
+                *self = Self::from_values(self.kind, &values);
+            }
+        }
+
+        old
+    }
+
+    pub fn fill(&mut self, value: u32) {
+        *self = Self::new(self.kind, value);
+    }
+
+    //Every value, X first, then Z, then Y
+    pub fn values(&self) -> Vec<u32> {
+        (0..self.kind.size())
+            .map(|index| self.get_index(index))
+            .collect()
+    }
+
+    pub fn count(&self, predicate: impl Fn(u32) -> bool) -> usize {
+        match self.palette {
+            Palette::Single(value) if predicate(value) => self.kind.size(),
+            Palette::Single(_) => 0,
+            _ => (0..self.kind.size())
+                .filter(|&index| predicate(self.get_index(index)))
+                .count(),
+        }
+    }
+
+    //The network format, which the kind is needed for since it isn't sent
+    pub fn read(buffer: &mut impl Buf, kind: ContainerKind) -> Result<Self, ProtocolError> {
+        let (palette_type, bits) = kind.configure(buffer.read_u8()?);
+
+        let palette = match palette_type {
+            PaletteType::Single => Palette::Single(buffer.read_var_int()? as u32),
+            PaletteType::Indirect => {
+                let palette = buffer.read_array(|buffer| Ok(buffer.read_var_int()? as u32))?;
+                if palette.is_empty() || palette.len() > 1 << bits {
+                    return Err(invalid(format!(
+                        "Palette of {} entries for {} bits",
+                        palette.len(),
+                        bits
+                    )));
+                }
+                Palette::Indirect(palette)
+            }
+            PaletteType::Direct => Palette::Direct,
+        };
+
+        let data = buffer.read_array(|buffer| buffer.read_i64())?;
+        let storage = BitStorage::from_data(bits, kind.size(), data)?;
+
+        if let Palette::Indirect(palette) = &palette {
+            if (0..kind.size()).any(|index| storage.get(index) as usize >= palette.len()) {
+                return Err(invalid("Palette index out of range".to_owned()));
+            }
+        }
+
+        Ok(Self {
+            kind,
+            palette,
+            storage,
+        })
+    }
+
+    //Anvil's format, a list of the values in the palette and the data if there's more than one
+    fn to_nbt(&self, mapping: &impl PaletteMapping) -> Result<Compound, ProtocolError> {
+        let values = self.values();
+        let mut palette = Vec::new();
+        let mut indices = HashMap::new();
+        for &value in &values {
+            indices.entry(value).or_insert_with(|| {
+                palette.push(value);
+                palette.len() as u32 - 1
+            });
+        }
+
+        let tags = palette
+            .iter()
+            .map(|&value| {
+                mapping
+                    .tag_of(value)
+                    .ok_or_else(|| invalid(format!("Palette entry {} has no name", value)))
+            })
+            .collect::<Result<_, _>>()?;
+        let mut compound = Compound::from([("palette".to_owned(), Tag::List(tags))]);
+
+        if palette.len() > 1 {
+            let mut storage =
+                BitStorage::new(self.kind.storage_bits(palette.len()), self.kind.size());
+            for (index, value) in values.iter().enumerate() {
+                storage.set(index, indices[value]);
+            }
+            compound.insert("data".to_owned(), Tag::LongArray(storage.data));
+        }
+
+        Ok(compound)
+    }
+
+    //Entries that aren't known anymore become 0, which is air for blocks
+    fn from_nbt(
+        compound: &Compound,
+        kind: ContainerKind,
+        mapping: &impl PaletteMapping,
+    ) -> Result<Self, ProtocolError> {
+        let palette: Vec<u32> = compound
+            .get("palette")
+            .and_then(Tag::as_list)
+            .filter(|palette| !palette.is_empty())
+            .ok_or_else(|| invalid("Missing palette".to_owned()))?
+            .iter()
+            .map(|tag| mapping.value_of(tag).unwrap_or(0))
+            .collect();
+
+        if palette.len() == 1 {
+            return Ok(Self::new(kind, palette[0]));
+        }
+
+        let data = match compound.get("data") {
+            Some(Tag::LongArray(data)) => data.clone(),
+            _ => return Err(invalid("Missing data for a palette".to_owned())),
+        };
+        let storage = BitStorage::from_data(kind.storage_bits(palette.len()), kind.size(), data)?;
+
+        let values = (0..kind.size())
+            .map(|index| {
+                let id = storage.get(index) as usize;
+                palette
+                    .get(id)
+                    .copied()
+                    .ok_or_else(|| invalid(format!("Palette index {} out of range", id)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self::from_values(kind, &values))
+    }
+}
+
+impl Encode for PalettedContainer {
+    fn encode(&self, buffer: &mut BytesMut) -> Result<(), ProtocolError> {
+        buffer.write_u8(&self.storage.bits())?;
+
+        match &self.palette {
+            Palette::Single(value) => buffer.write_var_int(&(*value as i32))?,
+            Palette::Indirect(palette) => buffer.write_array(palette, |buffer, value| {
+                buffer.write_var_int(&(*value as i32))
+            })?,
+            Palette::Direct => {}
+        }
+
+        buffer.write_array(self.storage.data(), |buffer, long| buffer.write_i64(long))
+    }
+}
+
+//Converts between the values in palettes and what Anvil files store instead, which keeps meaning the
+//same thing when IDs change between versions
+pub trait PaletteMapping {
+    fn tag_of(&self, value: u32) -> Option<Tag>;
+    fn value_of(&self, tag: &Tag) -> Option<u32>;
+}
+
+//Entries are stored by name, like biomes
+impl PaletteMapping for Registry {
+    fn tag_of(&self, value: u32) -> Option<Tag> {
+        self.entries
+            .get(value as usize)
+            .map(|entry| Tag::from(entry.as_str()))
+    }
+
+    fn value_of(&self, tag: &Tag) -> Option<u32> {
+        tag.as_str()
+            .and_then(|entry| self.id_of(entry))
+            .map(|id| id as u32)
+    }
+}
+
+//16 blocks high slice of a chunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkSection {
+    //Blocks that aren't air, which lets the client skip empty sections
+    block_count: u16,
+    block_states: PalettedContainer,
+    biomes: PalettedContainer,
+}
+
+impl ChunkSection {
+    pub fn new(layout: &ChunkLayout, biome: u32) -> Self {
+        Self {
+            block_count: 0,
+            block_states: PalettedContainer::new(layout.block_states, AIR),
+            biomes: PalettedContainer::new(layout.biomes, biome),
+        }
+    }
+
+    pub fn from_containers(block_states: PalettedContainer, biomes: PalettedContainer) -> Self {
+        Self {
+            block_count: block_states.count(|state| state != AIR) as u16,
+            block_states,
+            biomes,
+        }
+    }
+
+    pub fn block_count(&self) -> u16 {
+        self.block_count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.block_count == 0
+    }
+
+    pub fn block_states(&self) -> &PalettedContainer {
+        &self.block_states
+    }
+
+    pub fn biomes(&self) -> &PalettedContainer {
+        &self.biomes
+    }
+
+    pub fn block(&self, x: usize, y: usize, z: usize) -> u32 {
+        self.block_states.get(x, y, z)
+    }
+
+    //Returns the state that was there before
+    pub fn set_block(&mut self, x: usize, y: usize, z: usize, state: u32) -> u32 {
+        let old = self.block_states.set(x, y, z, state);
+        match (old == AIR, state == AIR) {
+            (true, false) => self.block_count += 1,
+            (false, true) => self.block_count -= 1,
+            _ => {}
+        }
+        old
+    }
+
+    //In biome coordinates, 4 blocks along each side
+    pub fn biome(&self, x: usize, y: usize, z: usize) -> u32 {
+        self.biomes.get(x, y, z)
+    }
+
+    pub fn set_biome(&mut self, x: usize, y: usize, z: usize, biome: u32) -> u32 {
+        self.biomes.set(x, y, z, biome)
+    }
+
+    //The block count is recounted rather than trusting the one sent
+    pub fn read(buffer: &mut impl Buf, layout: &ChunkLayout) -> Result<Self, ProtocolError> {
+        buffer.read_i16()?;
+        let block_states = PalettedContainer::read(buffer, layout.block_states)?;
+        let biomes = PalettedContainer::read(buffer, layout.biomes)?;
+
+        Ok(Self::from_containers(block_states, biomes))
+    }
+}
+
+impl Encode for ChunkSection {
+    fn encode(&self, buffer: &mut BytesMut) -> Result<(), ProtocolError> {
+        buffer.write_i16(&(self.block_count as i16))?;
+        self.block_states.encode(buffer)?;
+        self.biomes.encode(buffer)
+    }
+}
+
+//A light level from 0 to 15 for every block of a section, indexed like the block states
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LightArray(Box<[u8; LIGHT_SIZE]>);
+
+impl LightArray {
+    pub fn new() -> Self {
+        Self(Box::new([0; LIGHT_SIZE]))
+    }
+
+    pub fn filled(level: u8) -> Self {
+        Self(Box::new([(level & 0xF) * 0x11; LIGHT_SIZE]))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let bytes: [u8; LIGHT_SIZE] = bytes.try_into().ok()?;
+        Some(Self(Box::new(bytes)))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0[..]
+    }
+
+    fn locate(x: usize, y: usize, z: usize) -> (usize, u32) {
+        assert!(
+            x < 16 && y < 16 && z < 16,
+            "{} {} {} is out of range",
+            x,
+            y,
+            z
+        );
+        let index = (y << 8) | (z << 4) | x;
+        (index / 2, (index % 2) as u32 * 4)
+    }
+
+    pub fn get(&self, x: usize, y: usize, z: usize) -> u8 {
+        let (byte, shift) = Self::locate(x, y, z);
+        (self.0[byte] >> shift) & 0xF
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, z: usize, level: u8) {
+        let (byte, shift) = Self::locate(x, y, z);
+        self.0[byte] = (self.0[byte] & !(0xF << shift)) | ((level & 0xF) << shift);
+    }
+
+    //Completely dark, which is sent as just a bit in the empty mask
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(|&byte| byte == 0)
+    }
+}
+
+impl Default for LightArray {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum HeightmapKind {
+    WorldSurfaceWg,
+    WorldSurface,
+    OceanFloorWg,
+    OceanFloor,
+    MotionBlocking,
+    MotionBlockingNoLeaves,
+}
+
+impl HeightmapKind {
+    pub const ALL: [HeightmapKind; 6] = [
+        HeightmapKind::WorldSurfaceWg,
+        HeightmapKind::WorldSurface,
+        HeightmapKind::OceanFloorWg,
+        HeightmapKind::OceanFloor,
+        HeightmapKind::MotionBlocking,
+        HeightmapKind::MotionBlockingNoLeaves,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            HeightmapKind::WorldSurfaceWg => "WORLD_SURFACE_WG",
+            HeightmapKind::WorldSurface => "WORLD_SURFACE",
+            HeightmapKind::OceanFloorWg => "OCEAN_FLOOR_WG",
+            HeightmapKind::OceanFloor => "OCEAN_FLOOR",
+            HeightmapKind::MotionBlocking => "MOTION_BLOCKING",
+            HeightmapKind::MotionBlockingNoLeaves => "MOTION_BLOCKING_NO_LEAVES",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+
+    //The client only uses these, for rendering rain and snow
+    pub fn sent_to_client(self) -> bool {
+        matches!(
+            self,
+            HeightmapKind::WorldSurface | HeightmapKind::MotionBlocking
+        )
+    }
+}
+
+//For every column of a chunk, the height above the bottom of the world of the first block above
+//those the heightmap counts, so 0 means there are none
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Heightmap(BitStorage);
+
+impl Heightmap {
+    pub fn new(height: u32) -> Self {
+        Self(BitStorage::new(Self::bits(height), 16 * 16))
+    }
+
+    pub fn from_data(height: u32, data: Vec<i64>) -> Result<Self, ProtocolError> {
+        BitStorage::from_data(Self::bits(height), 16 * 16, data).map(Self)
+    }
+
+    fn bits(height: u32) -> u8 {
+        ceil_log2(height as usize + 1)
+    }
+
+    pub fn get(&self, x: usize, z: usize) -> u32 {
+        self.0.get(z * 16 + x)
+    }
+
+    pub fn set(&mut self, x: usize, z: usize, height: u32) {
+        self.0.set(z * 16 + x, height)
+    }
+
+    pub fn data(&self) -> &[i64] {
+        self.0.data()
+    }
+}
+
+//The vertical extent of a dimension and what its sections' containers hold
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkLayout {
+    //Both multiples of 16
+    pub min_y: i32,
+    pub height: u32,
+    pub block_states: ContainerKind,
+    pub biomes: ContainerKind,
+}
+
+impl ChunkLayout {
+    pub fn section_count(&self) -> usize {
+        self.height as usize / 16
+    }
+
+    pub fn min_section(&self) -> i32 {
+        self.min_y >> 4
+    }
+}
+
+//A 16 block wide column of sections from the bottom of the world to the top
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    pub pos: ChunkPos,
+    layout: ChunkLayout,
+    pub sections: Vec<ChunkSection>,
+    pub heightmaps: BTreeMap<HeightmapKind, Heightmap>,
+    //One more section below and above the world than there are sections, since light spreads into
+    //them. None where there's no light data, rather than it being dark.
+    pub sky_light: Vec<Option<LightArray>>,
+    pub block_light: Vec<Option<LightArray>>,
+}
+
+impl Chunk {
+    pub fn new(pos: ChunkPos, layout: ChunkLayout) -> Self {
+        assert!(
+            layout.min_y % 16 == 0 && layout.height.is_multiple_of(16),
+            "The world has to start and end at a section"
+        );
+
+        Self {
+            pos,
+            layout,
+            sections: vec![ChunkSection::new(&layout, 0); layout.section_count()],
+            heightmaps: HeightmapKind::ALL
+                .into_iter()
+                .map(|kind| (kind, Heightmap::new(layout.height)))
+                .collect(),
+            sky_light: vec![None; layout.section_count() + 2],
+            block_light: vec![None; layout.section_count() + 2],
+        }
+    }
+
+    pub fn layout(&self) -> &ChunkLayout {
+        &self.layout
+    }
+
+    fn section_index(&self, y: i32) -> Option<usize> {
+        let index = (y >> 4) - self.layout.min_section();
+        usize::try_from(index)
+            .ok()
+            .filter(|&index| index < self.sections.len())
+    }
+
+    //X and Z within the chunk, Y in the world. Outside of the world is always air.
+    pub fn block(&self, x: usize, y: i32, z: usize) -> u32 {
+        self.section_index(y).map_or(AIR, |index| {
+            self.sections[index].block(x, (y & 15) as usize, z)
+        })
+    }
+
+    //Returns the state that was there before, blocks outside of the world are ignored
+    pub fn set_block(&mut self, x: usize, y: i32, z: usize, state: u32) -> u32 {
+        match self.section_index(y) {
+            Some(index) => self.sections[index].set_block(x, (y & 15) as usize, z, state),
+            None => AIR,
+        }
+    }
+
+    //In block coordinates like set_block, each biome covers 4x4x4 blocks
+    pub fn biome(&self, x: usize, y: i32, z: usize) -> Option<u32> {
+        self.section_index(y)
+            .map(|index| self.sections[index].biome(x >> 2, (y & 15) as usize >> 2, z >> 2))
+    }
+
+    pub fn set_biome(&mut self, x: usize, y: i32, z: usize, biome: u32) {
+        if let Some(index) = self.section_index(y) {
+            self.sections[index].set_biome(x >> 2, (y & 15) as usize >> 2, z >> 2, biome);
+        }
+    }
+
+    //Chunk Data and Update Light, without block entities since chunks don't have them yet
+    pub fn to_packet(&self) -> Result<ChunkDataAndUpdateLight, ProtocolError> {
+        let mut data = BytesMut::new();
+        for section in &self.sections {
+            section.encode(&mut data)?;
+        }
+
+        let heightmaps = self
+            .heightmaps
+            .iter()
+            .filter(|(kind, _)| kind.sent_to_client())
+            .map(|(kind, heightmap)| {
+                (
+                    kind.name().to_owned(),
+                    Tag::LongArray(heightmap.data().to_vec()),
+                )
+            })
+            .collect();
+
+        Ok(ChunkDataAndUpdateLight {
+            chunk_x: self.pos.x,
+            chunk_z: self.pos.z,
+            data: ChunkData {
+                heightmaps: Tag::Compound(heightmaps),
+                data: data.to_vec(),
+                block_entities: Vec::new(),
+            },
+            light: self.light_data(),
+        })
+    }
+
+    fn light_data(&self) -> LightData {
+        let mut light = LightData {
+            sky_light_mask: BitSet::new(),
+            block_light_mask: BitSet::new(),
+            empty_sky_light_mask: BitSet::new(),
+            empty_block_light_mask: BitSet::new(),
+            sky_light: Vec::new(),
+            block_light: Vec::new(),
+        };
+
+        let layers = [
+            (
+                &self.sky_light,
+                &mut light.sky_light_mask,
+                &mut light.empty_sky_light_mask,
+                &mut light.sky_light,
+            ),
+            (
+                &self.block_light,
+                &mut light.block_light_mask,
+                &mut light.empty_block_light_mask,
+                &mut light.block_light,
+            ),
+        ];
+        for (arrays, mask, empty_mask, sent) in layers {
+            for (index, array) in arrays.iter().enumerate() {
+                match array {
+                    Some(array) if array.is_empty() => empty_mask.set(index, true),
+                    Some(array) => {
+                        mask.set(index, true);
+                        sent.push(array.as_bytes().to_vec());
+                    }
+                    None => {}
+                }
+            }
+        }
+
+        light
+    }
+
+    //The compound vanilla stores in region files, with the palettes converted by the mappings
+    pub fn to_nbt(
+        &self,
+        blocks: &impl PaletteMapping,
+        biomes: &impl PaletteMapping,
+    ) -> Result<Compound, ProtocolError> {
+        let min_section = self.layout.min_section();
+        let mut sections = Vec::new();
+
+        for index in 0..self.sky_light.len() {
+            let y = min_section - 1 + index as i32;
+            let mut section = Compound::from([("Y".to_owned(), Tag::Byte(y as i8))]);
+
+            if let Some(blocks_section) = index
+                .checked_sub(1)
+                .and_then(|index| self.sections.get(index))
+            {
+                section.insert(
+                    "block_states".to_owned(),
+                    Tag::Compound(blocks_section.block_states.to_nbt(blocks)?),
+                );
+                section.insert(
+                    "biomes".to_owned(),
+                    Tag::Compound(blocks_section.biomes.to_nbt(biomes)?),
+                );
+            }
+            for (name, array) in [
+                ("BlockLight", &self.block_light[index]),
+                ("SkyLight", &self.sky_light[index]),
+            ] {
+                if let Some(array) = array {
+                    let bytes = array.as_bytes().iter().map(|&byte| byte as i8).collect();
+                    section.insert(name.to_owned(), Tag::ByteArray(bytes));
+                }
+            }
+
+            if section.len() > 1 {
+                sections.push(Tag::Compound(section));
+            }
+        }
+
+        let heightmaps = self
+            .heightmaps
+            .iter()
+            .map(|(kind, heightmap)| {
+                (
+                    kind.name().to_owned(),
+                    Tag::LongArray(heightmap.data().to_vec()),
+                )
+            })
+            .collect();
+        let has_light = self
+            .sky_light
+            .iter()
+            .chain(&self.block_light)
+            .any(Option::is_some);
+
+        Ok(Compound::from([
+            ("DataVersion".to_owned(), Tag::Int(DATA_VERSION)),
+            ("xPos".to_owned(), Tag::Int(self.pos.x)),
+            ("yPos".to_owned(), Tag::Int(min_section)),
+            ("zPos".to_owned(), Tag::Int(self.pos.z)),
+            ("Status".to_owned(), Tag::from("minecraft:full")),
+            ("LastUpdate".to_owned(), Tag::Long(0)),
+            ("sections".to_owned(), Tag::List(sections)),
+            ("Heightmaps".to_owned(), Tag::Compound(heightmaps)),
+            ("block_entities".to_owned(), Tag::List(Vec::new())),
+            //Without it vanilla recalculates the light when loading the chunk
+            ("isLightOn".to_owned(), Tag::from(has_light)),
+        ]))
+    }
+
+    //Sections missing from the compound are left empty and anything outside the layout is ignored,
+    //like vanilla does for worlds whose height changed
+    pub fn from_nbt(
+        compound: &Compound,
+        layout: ChunkLayout,
+        blocks: &impl PaletteMapping,
+        biomes: &impl PaletteMapping,
+    ) -> Result<Self, ProtocolError> {
+        let int = |name: &str| {
+            compound
+                .get(name)
+                .and_then(Tag::as_i64)
+                .map(|value| value as i32)
+                .ok_or_else(|| invalid(format!("Missing {}", name)))
+        };
+        let mut chunk = Self::new(ChunkPos::new(int("xPos")?, int("zPos")?), layout);
+
+        let sections = compound
+            .get("sections")
+            .and_then(Tag::as_list)
+            .unwrap_or_default();
+        for section in sections {
+            let section = section
+                .as_compound()
+                .ok_or_else(|| invalid("Section isn't a compound".to_owned()))?;
+            let y = section
+                .get("Y")
+                .and_then(Tag::as_i64)
+                .ok_or_else(|| invalid("Section without a Y".to_owned()))?
+                as i32;
+
+            let Some(index) = usize::try_from(y - layout.min_section() + 1)
+                .ok()
+                .filter(|&index| index < chunk.sky_light.len())
+            else {
+                continue;
+            };
+
+            if let Some(blocks_section) = index
+                .checked_sub(1)
+                .and_then(|index| chunk.sections.get_mut(index))
+            {
+                let container = |name: &str| {
+                    section
+                        .get(name)
+                        .and_then(Tag::as_compound)
+                        .ok_or_else(|| invalid(format!("Section {} without {}", y, name)))
+                };
+                *blocks_section = ChunkSection::from_containers(
+                    PalettedContainer::from_nbt(
+                        container("block_states")?,
+                        layout.block_states,
+                        blocks,
+                    )?,
+                    PalettedContainer::from_nbt(container("biomes")?, layout.biomes, biomes)?,
+                );
+            }
+
+            for (name, arrays) in [
+                ("BlockLight", &mut chunk.block_light),
+                ("SkyLight", &mut chunk.sky_light),
+            ] {
+                if let Some(Tag::ByteArray(bytes)) = section.get(name) {
+                    let bytes: Vec<u8> = bytes.iter().map(|&byte| byte as u8).collect();
+                    arrays[index] = Some(LightArray::from_bytes(&bytes).ok_or_else(|| {
+                        invalid(format!(
+                            "{} of section {} is {} bytes",
+                            name,
+                            y,
+                            bytes.len()
+                        ))
+                    })?);
+                }
+            }
+        }
+
+        if let Some(heightmaps) = compound.get("Heightmaps").and_then(Tag::as_compound) {
+            for (name, data) in heightmaps {
+                let (Some(kind), Tag::LongArray(data)) = (HeightmapKind::from_name(name), data)
+                else {
+                    continue;
+                };
+
+                //A heightmap for another world height is left for the server to recalculate
+                if let Ok(heightmap) = Heightmap::from_data(layout.height, data.clone()) {
+                    chunk.heightmaps.insert(kind, heightmap);
+                }
+            }
+        }
+
+        Ok(chunk)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::BIOMES;
+
+    //The 1.21.1 block state count, so 15 bits for the direct palette
+    const STATE_COUNT: usize = 26684;
+
+    fn layout() -> ChunkLayout {
+        ChunkLayout {
+            min_y: -64,
+            height: 384,
+            block_states: ContainerKind::block_states(STATE_COUNT),
+            biomes: ContainerKind::biomes(BIOMES.len()),
+        }
+    }
+
+    //Names blocks after their state ID, enough to round trip through Anvil's format
+    struct TestBlocks;
+
+    impl PaletteMapping for TestBlocks {
+        fn tag_of(&self, value: u32) -> Option<Tag> {
+            let name = Tag::from(format!("test:block_{}", value));
+            Some(Tag::Compound(Compound::from([("Name".to_owned(), name)])))
+        }
+
+        fn value_of(&self, tag: &Tag) -> Option<u32> {
+            tag.as_compound()?
+                .get("Name")?
+                .as_str()?
+                .strip_prefix("test:block_")?
+                .parse()
+                .ok()
+        }
+    }
+
+    fn biomes() -> Registry {
+        Registry::new("minecraft:worldgen/biome", BIOMES)
+    }
+
+    #[test]
+    fn test_bit_storage() {
+        let mut storage = BitStorage::new(5, SECTION_SIZE);
+        //12 entries per long, leaving 4 bits unused
+        assert_eq!(storage.data().len(), 342);
+
+        storage.set(0, 31);
+        storage.set(1, 1);
+        storage.set(12, 7);
+        assert_eq!(storage.data()[0], 31 | 1 << 5);
+        assert_eq!(storage.data()[1], 7);
+        assert_eq!(
+            (storage.get(0), storage.get(1), storage.get(12)),
+            (31, 1, 7)
+        );
+
+        storage.set(0, 0);
+        assert_eq!(storage.data()[0], 1 << 5);
+        assert!(BitStorage::from_data(5, SECTION_SIZE, vec![0; 341]).is_err());
+    }
+
+    #[test]
+    fn test_palette_resizing() {
+        let kind = ContainerKind::block_states(STATE_COUNT);
+        let mut container = PalettedContainer::new(kind, AIR);
+        assert_eq!(container.storage.bits(), 0);
+
+        assert_eq!(container.set(1, 2, 3, 9), AIR);
+        assert_eq!(container.palette, Palette::Indirect(vec![AIR, 9]));
+        assert_eq!(container.storage.bits(), 4);
+
+        for value in 0..20 {
+            container.set(value as usize % 16, 0, value as usize / 16, value + 100);
+        }
+        assert_eq!(container.storage.bits(), 5);
+
+        for value in 0..300 {
+            let (x, z) = (value as usize % 16, value as usize / 16 % 16);
+            container.set(x, 8 + value as usize / 256, z, value + 1000);
+        }
+        assert_eq!(container.palette, Palette::Direct);
+        assert_eq!(container.storage.bits(), 15);
+
+        assert_eq!(container.get(1, 2, 3), 9);
+        assert_eq!(container.get(3, 0, 1), 119);
+        assert_eq!(container.get(3, 8, 1), 1000 + 19);
+        assert_eq!(container.get(0, 9, 0), 1000 + 256);
+        assert_eq!(container.get(0, 12, 0), AIR);
+
+        container.fill(5);
+        assert_eq!(container.count(|value| value == 5), SECTION_SIZE);
+    }
+
+    #[test]
+    fn test_network_format() {
+        let kind = ContainerKind::biomes(BIOMES.len());
+        let mut buffer = BytesMut::new();
+        PalettedContainer::new(kind, 7).encode(&mut buffer).unwrap();
+        assert_eq!(&buffer[..], [0, 7, 0]);
+
+        let mut container = PalettedContainer::new(kind, 7);
+        container.set(1, 0, 0, 3);
+        let mut buffer = BytesMut::new();
+        container.encode(&mut buffer).unwrap();
+        //One bit per entry, a palette of two and a single long
+        assert_eq!(&buffer[..4], [1, 2, 7, 3]);
+        assert_eq!(buffer[4], 1);
+        assert_eq!(&buffer[5..], (1i64 << 1).to_be_bytes());
+
+        assert_eq!(
+            PalettedContainer::read(&mut buffer, kind).unwrap(),
+            container
+        );
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_invalid_palette_index() {
+        let kind = ContainerKind::biomes(BIOMES.len());
+        let mut buffer = BytesMut::new();
+        buffer.write_u8(&1).unwrap();
+        buffer
+            .write_array(&[7], |buffer, value| buffer.write_var_int(value))
+            .unwrap();
+        buffer
+            .write_array(&[2i64], |buffer, long| buffer.write_i64(long))
+            .unwrap();
+
+        assert!(PalettedContainer::read(&mut buffer, kind).is_err());
+    }
+
+    #[test]
+    fn test_section_block_count() {
+        let layout = layout();
+        let mut section = ChunkSection::new(&layout, 0);
+        assert!(section.is_empty());
+
+        section.set_block(0, 0, 0, 1);
+        section.set_block(15, 15, 15, 2);
+        section.set_block(15, 15, 15, 3);
+        assert_eq!(section.block_count(), 2);
+        section.set_block(0, 0, 0, AIR);
+        assert_eq!(section.block_count(), 1);
+
+        let mut buffer = BytesMut::new();
+        section.encode(&mut buffer).unwrap();
+        assert_eq!(&buffer[..2], [0, 1]);
+        let read = ChunkSection::read(&mut buffer, &layout).unwrap();
+        assert_eq!(read, section);
+
+        //Each container is read with the kind the layout gives it
+        assert_eq!(read.block_states().kind(), layout.block_states);
+        assert_eq!(read.block_states().get(15, 15, 15), 3);
+        assert_eq!(read.biomes().kind(), layout.biomes);
+        assert_eq!(read.biomes().get(3, 3, 3), 0);
+    }
+
+    #[test]
+    fn test_light_array() {
+        let mut light = LightArray::new();
+        assert!(light.is_empty());
+
+        light.set(0, 0, 0, 15);
+        light.set(1, 0, 0, 4);
+        light.set(0, 1, 0, 2);
+        assert_eq!(light.as_bytes()[0], 0x4F);
+        assert_eq!(light.as_bytes()[128], 0x02);
+        assert_eq!(light.get(1, 0, 0), 4);
+        assert_eq!(LightArray::filled(15).get(7, 7, 7), 15);
+    }
+
+    #[test]
+    fn test_heightmap() {
+        let mut heightmap = Heightmap::new(384);
+        //9 bits each, 7 to a long
+        assert_eq!(heightmap.data().len(), 37);
+
+        heightmap.set(15, 15, 384);
+        assert_eq!(heightmap.get(15, 15), 384);
+        assert_eq!(heightmap.data()[36], 384 << (3 * 9));
+    }
+
+    fn test_chunk() -> Chunk {
+        let mut chunk = Chunk::new(ChunkPos::new(-3, 12), layout());
+        for x in 0..16 {
+            for z in 0..16 {
+                chunk.set_block(x, -64, z, 79);
+                chunk.set_block(x, 70, z, (x * 16 + z) as u32 + 1);
+                chunk
+                    .heightmaps
+                    .get_mut(&HeightmapKind::MotionBlocking)
+                    .unwrap()
+                    .set(x, z, 70 + 64 + 1);
+            }
+        }
+        chunk.set_biome(0, 319, 0, 5);
+
+        chunk.sky_light[0] = Some(LightArray::new());
+        chunk.sky_light[25] = Some(LightArray::filled(15));
+        chunk.block_light[1] = Some(LightArray::filled(3));
+        chunk
+    }
+
+    #[test]
+    fn test_chunk_blocks() {
+        let chunk = test_chunk();
+
+        assert_eq!(chunk.block(3, -64, 4), 79);
+        assert_eq!(chunk.block(3, -65, 4), AIR);
+        assert_eq!(chunk.block(3, 320, 4), AIR);
+        assert_eq!(chunk.block(2, 70, 5), 2 * 16 + 5 + 1);
+        assert_eq!(chunk.biome(3, 316, 3), Some(5));
+        assert_eq!(chunk.biome(4, 316, 3), Some(0));
+        assert_eq!(chunk.sections[0].block_count(), 256);
+        assert_eq!(chunk.sections[8].block_count(), 256);
+    }
+
+    #[test]
+    fn test_chunk_packet() {
+        let chunk = test_chunk();
+        let packet = chunk.to_packet().unwrap();
+
+        assert_eq!((packet.chunk_x, packet.chunk_z), (-3, 12));
+        let heightmaps = packet.data.heightmaps.as_compound().unwrap();
+        assert_eq!(
+            heightmaps.keys().collect::<Vec<_>>(),
+            ["MOTION_BLOCKING", "WORLD_SURFACE"]
+        );
+
+        let mut data = BytesMut::from(&packet.data.data[..]);
+        for section in &chunk.sections {
+            assert_eq!(
+                &ChunkSection::read(&mut data, chunk.layout()).unwrap(),
+                section
+            );
+        }
+        assert!(data.is_empty());
+
+        let light = &packet.light;
+        assert_eq!(light.sky_light_mask.0, [1 << 25]);
+        assert_eq!(light.empty_sky_light_mask.0, [1]);
+        assert_eq!(light.block_light_mask.0, [1 << 1]);
+        assert!(light.empty_block_light_mask.is_empty());
+        assert_eq!(light.sky_light, [vec![0xFF; LIGHT_SIZE]]);
+        assert_eq!(light.block_light, [vec![0x33; LIGHT_SIZE]]);
+    }
+
+    #[test]
+    fn test_chunk_nbt() {
+        let chunk = test_chunk();
+        let compound = chunk.to_nbt(&TestBlocks, &biomes()).unwrap();
+
+        assert_eq!(compound["yPos"], Tag::Int(-4));
+        assert_eq!(compound["isLightOn"], Tag::Byte(1));
+        let sections = compound["sections"].as_list().unwrap();
+        //Every section in the world, plus light below and above it
+        assert_eq!(sections.len(), 26);
+        let bottom = sections[1].as_compound().unwrap();
+        assert_eq!(
+            bottom["block_states"].as_compound().unwrap()["palette"],
+            Tag::List(vec![
+                TestBlocks.tag_of(79).unwrap(),
+                TestBlocks.tag_of(AIR).unwrap()
+            ])
+        );
+        let top = sections[24].as_compound().unwrap()["biomes"]
+            .as_compound()
+            .unwrap();
+        assert_eq!(
+            top["palette"],
+            Tag::List(vec![
+                Tag::from("minecraft:badlands"),
+                Tag::from("minecraft:cherry_grove")
+            ])
+        );
+        assert_eq!(top["data"], Tag::LongArray(vec![1 << 48]));
+
+        let loaded = Chunk::from_nbt(&compound, layout(), &TestBlocks, &biomes()).unwrap();
+        assert_eq!(loaded, chunk);
+    }
+
+    #[test]
+    fn test_chunk_nbt_from_other_height() {
+        let chunk = test_chunk();
+        let compound = chunk.to_nbt(&TestBlocks, &biomes()).unwrap();
+
+        let layout = ChunkLayout {
+            min_y: 0,
+            height: 128,
+            ..layout()
+        };
+        let loaded = Chunk::from_nbt(&compound, layout, &TestBlocks, &biomes()).unwrap();
+        assert_eq!(loaded.block(2, 70, 5), 2 * 16 + 5 + 1);
+        assert_eq!(loaded.block(2, 0, 5), AIR);
+        assert_eq!(
+            loaded.heightmaps[&HeightmapKind::MotionBlocking],
+            Heightmap::new(128)
+        );
+    }
+}
@@ -1,80 +1,1298 @@
 use bytes::BytesMut;
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use thiserror::Error;
 use tokio::io::AsyncWriteExt;
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::sync::oneshot;
 use tokio::{io::AsyncReadExt, net::TcpStream};
+use tokio_util::codec::{Decoder, Encoder};
 
-use crate::packet::Packet;
-use crate::reader::ProtocolBufferReaderExt;
+use crate::auth::{server_hash, PlayerProfile};
+use crate::chunk::DATA_VERSION;
+use crate::encryption::{StreamDecryptor, StreamEncryptor};
+use crate::framing::{FrameCodec, FrameError};
+use crate::nbt::{Compound, Tag};
+use crate::packet::configuration::{
+    self, ClientInformation, ClientboundKnownPacks, ClientboundPluginMessage, FeatureFlags,
+    FinishConfiguration, KnownPacks, PluginMessage, ServerboundConfigurationPacket,
+};
+use crate::packet::handshaking::ServerboundHandshakingPacket;
+use crate::packet::login::{
+    self, EncryptionRequest, EncryptionResponse, LoginStart, LoginSuccess,
+    ServerboundLoginPacket, SetCompression,
+};
+use crate::packet::play;
+use crate::packet::status::{self, ServerboundStatusPacket};
+use crate::packet::version::ProtocolVersion;
+use crate::packet::{ConnectionState, Packet, PacketError, RawPacket};
+use crate::registry::Registries;
+use crate::server::{ServerContext, ServerEvent};
+use crate::text::TextComponent;
+use crate::types::{BoundedString, Decode, Encode, RemainingBytes, VarInt};
+
+const READ_BUFFER_SIZE: usize = 1024;
+//Packets waiting to be written before the client is considered too slow and dropped
+pub const OUTBOUND_QUEUE_SIZE: usize = 1024;
+const MAX_WRITE_BATCH: usize = 64;
+const VERIFY_TOKEN_LENGTH: usize = 4;
+const BRAND_CHANNEL: &str = "minecraft:brand";
+const SERVER_BRAND: &str = "copper";
 
 pub struct Client {
-    pub stream: TcpStream,
-    packet_queue: Vec<Packet>,
+    pub stream: OwnedReadHalf,
+    pub state: ConnectionState,
+    //Negotiated during the handshake, until then packets are read and written in the native version
+    pub version: ProtocolVersion,
+    //Set once the player is authenticated and sent Login Success
+    pub profile: Option<PlayerProfile>,
+    //Whether the server still has to check the new profile against other sessions
+    new_login: bool,
+    codec: FrameCodec,
+    read_buffer: BytesMut,
+    packet_queue: Vec<RawPacket>,
+    outbound: mpsc::Sender<OutboundMessage>,
+    decryptor: Option<StreamDecryptor>,
+    //Sent in the Encryption Request, the client has to send it back encrypted
+    verify_token: Option<[u8; VERIFY_TOKEN_LENGTH]>,
+    username: Option<String>,
+    //Settings the client sends during configuration, and again whenever they change
+    pub client_information: Option<ClientInformation>,
+    //Client brand from the minecraft:brand plugin channel, e.g. "vanilla"
+    pub brand: Option<String>,
+    //The client may only acknowledge once the server sent Finish Configuration
+    finish_configuration_sent: bool,
+}
+
+//Everything the writer task handles, in the order it has to be applied to the stream
+#[derive(Debug)]
+pub enum OutboundMessage {
+    Packet(RawPacket),
+    SetCompression(Option<usize>),
+    EnableEncryption(Box<StreamEncryptor>),
 }
 
 impl Client {
     pub fn new(stream: TcpStream) -> Self {
+        Self::with_outbound_queue_size(stream, OUTBOUND_QUEUE_SIZE)
+    }
+
+    //Splits the stream, handing the write half to its own task which drains the outbound queue
+    pub fn with_outbound_queue_size(stream: TcpStream, queue_size: usize) -> Self {
+        let (reader, writer) = stream.into_split();
+        let (outbound, receiver) = mpsc::channel(queue_size);
+
+        tokio::spawn(write_outbound_packets(writer, receiver));
+
         Self {
-            stream,
+            stream: reader,
+            state: ConnectionState::Handshaking,
+            version: ProtocolVersion::NATIVE,
+            profile: None,
+            new_login: false,
+            codec: FrameCodec::new(),
+            read_buffer: BytesMut::with_capacity(READ_BUFFER_SIZE),
             packet_queue: Vec::new(),
+            outbound,
+            decryptor: None,
+            verify_token: None,
+            username: None,
+            client_information: None,
+            brand: None,
+            finish_configuration_sent: false,
         }
     }
 
-    //Rreturns an bool depending if the client disconnects
-    pub async fn get_incoming_packets(&mut self) -> bool {
-        let mut main_buffer = BytesMut::with_capacity(1024);
-        let mut count: u16 = 0;
+    //Returns false once the client closed the connection, or an error if it sent a malformed frame
+    pub async fn get_incoming_packets(&mut self) -> Result<bool, FrameError> {
+        self.read_buffer.reserve(READ_BUFFER_SIZE);
+        let start = self.read_buffer.len();
 
-        match self.stream.read_buf(&mut main_buffer).await {
+        match self.stream.read_buf(&mut self.read_buffer).await {
             Ok(size) => {
                 if size > 0 {
-                    while main_buffer.len() != 0 {
-                        let length = main_buffer.read_var_int().unwrap();
-    
-                        let mut buffer = main_buffer.split_to(length as usize);
-    
-                        let packet_id = buffer.read_var_int().unwrap();
-    
-                        self.packet_queue.push(Packet { id: packet_id, buffer });
-                        count += 1;
+                    if let Some(decryptor) = &mut self.decryptor {
+                        decryptor.decrypt(&mut self.read_buffer[start..]);
                     }
 
-                    println!("Received {:?} incoming packets from {:?}",count,self.stream.local_addr().unwrap());
+                    while let Some(packet) = self.codec.decode(&mut self.read_buffer)? {
+                        self.packet_queue.push(packet);
+                    }
 
-                    return true;
+                    return Ok(true);
                 }
             },
-            Err(_) => {
-                self.test_client_disconnection().await;
+            Err(error) => return Err(error.into()),
+        }
+
+        Ok(false)
+    }
+
+    //Serializes the packet and queues it for the writer task, failing if the client
+    //can't keep up with the outbound queue or its connection is already gone
+    pub fn send<P: Packet>(&mut self, packet: &P) -> Result<(), PacketError> {
+        debug_assert_eq!(P::STATE, self.state, "Packet sent in the wrong connection state");
+
+        self.queue_outbound(OutboundMessage::Packet(packet.to_raw(&self.version)?))
+    }
+
+    fn queue_outbound(&mut self, message: OutboundMessage) -> Result<(), PacketError> {
+        queue_message(&self.outbound, message)
+    }
+
+    //Sends Set Compression and compresses every packet after it, in both directions
+    pub fn enable_compression(&mut self, threshold: usize) -> Result<(), PacketError> {
+        self.send(&SetCompression {
+            threshold: VarInt(threshold as i32),
+        })?;
+        self.queue_outbound(OutboundMessage::SetCompression(Some(threshold)))?;
+        self.codec.set_compression_threshold(Some(threshold));
+
+        Ok(())
+    }
+
+    //Encrypts every byte after this point, in both directions
+    pub fn enable_encryption(&mut self, shared_secret: &[u8]) -> Result<(), PacketError> {
+        let encryptor = StreamEncryptor::new(shared_secret)?;
+        let mut decryptor = StreamDecryptor::new(shared_secret)?;
+
+        //Anything still buffered was sent after the client switched to encryption
+        decryptor.decrypt(&mut self.read_buffer);
+
+        self.queue_outbound(OutboundMessage::EnableEncryption(Box::new(encryptor)))?;
+        self.decryptor = Some(decryptor);
+
+        Ok(())
+    }
+
+    //Dispatches every queued packet to the handler of the current connection state,
+    //stopping at the first packet that can't be handled.
+    //Play packets are left queued for the game loop, see take_play_packets.
+    pub async fn process_packets(&mut self, context: &ServerContext) -> Result<(), PacketError> {
+        let mut packets = std::mem::take(&mut self.packet_queue).into_iter();
+
+        while self.state != ConnectionState::Play {
+            let Some(mut packet) = packets.next() else {
+                break;
+            };
+
+            let version = self.version;
+            match self.state {
+                ConnectionState::Handshaking => self.handle_handshaking_packet(
+                    ServerboundHandshakingPacket::decode(&mut packet, &version)?,
+                )?,
+                ConnectionState::Status => self.handle_status_packet(
+                    ServerboundStatusPacket::decode(&mut packet, &version)?,
+                    context,
+                )?,
+                ConnectionState::Login => {
+                    self.handle_login_packet(
+                        ServerboundLoginPacket::decode(&mut packet, &version)?,
+                        context,
+                    )
+                    .await?
+                }
+                ConnectionState::Configuration => self.handle_configuration_packet(
+                    ServerboundConfigurationPacket::decode(&mut packet, &version)?,
+                    context,
+                )?,
+                ConnectionState::Play => unreachable!(),
+            }
+        }
+
+        self.packet_queue.extend(packets);
+
+        Ok(())
+    }
+
+    //Hands over every packet received in play, which are handled by the game loop
+    pub fn take_play_packets(&mut self) -> Vec<RawPacket> {
+        if self.state == ConnectionState::Play {
+            std::mem::take(&mut self.packet_queue)
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn handle_handshaking_packet(
+        &mut self,
+        packet: ServerboundHandshakingPacket,
+    ) -> Result<(), PacketError> {
+        match packet {
+            ServerboundHandshakingPacket::Handshake(handshake) => {
+                self.state = match handshake.next_state.0 {
+                    1 => ConnectionState::Status,
+                    //3 is a transfer from another server, which still goes through login
+                    2 | 3 => ConnectionState::Login,
+                    next_state => return Err(PacketError::InvalidNextState(next_state)),
+                };
+
+                //Any client may see the server list entry, which tells it what versions to use
+                match ProtocolVersion::negotiate(handshake.protocol_version.0) {
+                    Ok(version) => self.version = version,
+                    Err(error) if self.state == ConnectionState::Login => return Err(error),
+                    Err(_) => {}
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    fn handle_status_packet(
+        &mut self,
+        packet: ServerboundStatusPacket,
+        context: &ServerContext,
+    ) -> Result<(), PacketError> {
+        match packet {
+            ServerboundStatusPacket::StatusRequest(_) => {
+                //Supported clients are told the server runs their version,
+                //the others get the native one and are shown as outdated
+                let mut status = context.status.read().unwrap().clone();
+                status.version.protocol = self.version.protocol;
+
+                self.send(&status::StatusResponse {
+                    json_response: status.to_json(),
+                })
+            }
+            ServerboundStatusPacket::PingRequest(ping) => self.send(&status::PongResponse {
+                payload: ping.payload,
+            }),
+        }
+    }
+
+    async fn handle_login_packet(
+        &mut self,
+        packet: ServerboundLoginPacket,
+        context: &ServerContext,
+    ) -> Result<(), PacketError> {
+        match packet {
+            ServerboundLoginPacket::LoginStart(login_start) => {
+                self.handle_login_start(login_start, context).await?
+            }
+            ServerboundLoginPacket::EncryptionResponse(response) => {
+                self.handle_encryption_response(response, context).await?
+            }
+            ServerboundLoginPacket::LoginAcknowledged(_) => {
+                if self.profile.is_none() {
+                    return Err(PacketError::UnexpectedLoginPacket("Login Acknowledged"));
+                }
+
+                self.state = ConnectionState::Configuration;
+                self.start_configuration()?;
+            }
+            //Plugin and cookie responses only answer requests, which the server never sends
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    async fn handle_login_start(
+        &mut self,
+        login_start: LoginStart,
+        context: &ServerContext,
+    ) -> Result<(), PacketError> {
+        if self.username.is_some() {
+            return Err(PacketError::UnexpectedLoginPacket("Login Start"));
+        }
+
+        println!("Received login start from {} ({})", login_start.name.0, login_start.uuid);
+
+        let username = login_start.name.0;
+        self.username = Some(username.clone());
+
+        match &context.key_pair {
+            Some(key_pair) => {
+                let verify_token: [u8; VERIFY_TOKEN_LENGTH] = rand::random();
+                self.verify_token = Some(verify_token);
+
+                self.send(&EncryptionRequest {
+                    server_id: BoundedString(String::new()),
+                    public_key: key_pair.public_key_der.clone(),
+                    verify_token: verify_token.to_vec(),
+                    should_authenticate: true,
+                })
+            }
+            None => {
+                let profile = context.authenticator.authenticate(&username, "").await?;
+                self.finish_login(profile, context)
+            }
+        }
+    }
+
+    async fn handle_encryption_response(
+        &mut self,
+        response: EncryptionResponse,
+        context: &ServerContext,
+    ) -> Result<(), PacketError> {
+        let (Some(key_pair), Some(verify_token), Some(username)) = (
+            &context.key_pair,
+            self.verify_token.take(),
+            self.username.clone(),
+        ) else {
+            return Err(PacketError::UnexpectedLoginPacket("Encryption Response"));
+        };
+
+        let shared_secret = key_pair.decrypt_shared_secret(
+            &response.shared_secret,
+            &response.verify_token,
+            &verify_token,
+        )?;
+
+        self.enable_encryption(&shared_secret)?;
+
+        let server_hash = server_hash("", &shared_secret, &key_pair.public_key_der);
+        let profile = context
+            .authenticator
+            .authenticate(&username, &server_hash)
+            .await?;
+
+        self.finish_login(profile, context)
+    }
+
+    //Runs once the player is authenticated and the connection is encrypted, if it needs to be
+    fn finish_login(
+        &mut self,
+        profile: PlayerProfile,
+        context: &ServerContext,
+    ) -> Result<(), PacketError> {
+        println!("Authenticated {} with UUID {}", profile.name, profile.uuid);
+
+        let compression = {
+            let config = context.global_config.read().unwrap();
+            config
+                .enable_packet_compression
+                .then_some(config.compression_threshold)
+        };
+        if let Some(threshold) = compression {
+            self.enable_compression(threshold)?;
+        }
+
+        self.send(&LoginSuccess {
+            uuid: profile.uuid,
+            username: BoundedString(profile.name.clone()),
+            properties: profile.properties.clone(),
+            strict_error_handling: false,
+        })?;
+
+        //The client moves to configuration once it acknowledges the Login Success
+        self.profile = Some(profile);
+        self.new_login = true;
+
+        Ok(())
+    }
+
+    //Returns the profile that logged in since the last call
+    pub fn take_new_login(&mut self) -> Option<PlayerProfile> {
+        if std::mem::take(&mut self.new_login) {
+            self.profile.clone()
+        } else {
+            None
+        }
+    }
+
+    //Sends the Disconnect packet of the current state, the connection should be closed after it.
+    //Clients in handshaking or status have no way to be told why.
+    pub fn disconnect(&mut self, reason: TextComponent) -> Result<(), PacketError> {
+        match self.state {
+            ConnectionState::Handshaking | ConnectionState::Status => Ok(()),
+            ConnectionState::Login => self.send(&login::Disconnect {
+                reason: reason.to_json(),
+            }),
+            ConnectionState::Configuration => self.send(&configuration::Disconnect { reason }),
+            ConnectionState::Play => self.send(&play::Disconnect { reason }),
+        }
+    }
+
+    //Announces the server brand, enabled features and the data packs the server expects
+    //the client to know, then waits for the client's Known Packs
+    fn start_configuration(&mut self) -> Result<(), PacketError> {
+        let mut brand = BytesMut::new();
+        SERVER_BRAND.to_owned().encode(&mut brand)?;
+
+        self.send(&ClientboundPluginMessage {
+            channel: BRAND_CHANNEL.to_owned(),
+            data: RemainingBytes(brand.freeze()),
+        })?;
+        self.send(&FeatureFlags {
+            feature_flags: vec!["minecraft:vanilla".to_owned()],
+        })?;
+        self.send(&ClientboundKnownPacks {
+            known_packs: Registries::core_packs(&self.version),
+        })
+    }
+
+    fn handle_configuration_packet(
+        &mut self,
+        packet: ServerboundConfigurationPacket,
+        context: &ServerContext,
+    ) -> Result<(), PacketError> {
+        match packet {
+            ServerboundConfigurationPacket::ClientInformation(information) => {
+                self.client_information = Some(information);
             }
+            ServerboundConfigurationPacket::PluginMessage(message) => {
+                self.handle_plugin_message(message)?
+            }
+            ServerboundConfigurationPacket::KnownPacks(known_packs) => {
+                self.handle_known_packs(known_packs, context)?
+            }
+            ServerboundConfigurationPacket::AcknowledgeFinishConfiguration(_) => {
+                if !self.finish_configuration_sent {
+                    return Err(PacketError::UnexpectedConfigurationPacket(
+                        "Acknowledge Finish Configuration",
+                    ));
+                }
+
+                self.state = ConnectionState::Play;
+            }
+            //Keep alives, pongs and pack responses only answer what the server hasn't sent yet
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn handle_plugin_message(&mut self, message: PluginMessage) -> Result<(), PacketError> {
+        if message.channel == BRAND_CHANNEL {
+            let mut data = BytesMut::from(&message.data.0[..]);
+            self.brand = Some(String::decode(&mut data)?);
+        } else {
+            println!("Received plugin message on channel {}", message.channel);
+        }
+
+        Ok(())
+    }
+
+    //Registry data is only sent as entry IDs, so the client has to have the vanilla data pack
+    fn handle_known_packs(
+        &mut self,
+        known_packs: KnownPacks,
+        context: &ServerContext,
+    ) -> Result<(), PacketError> {
+        if self.finish_configuration_sent {
+            return Err(PacketError::UnexpectedConfigurationPacket("Known Packs"));
+        }
+
+        let core_packs = Registries::core_packs(&self.version);
+        if !core_packs.iter().any(|pack| known_packs.known_packs.contains(pack)) {
+            return Err(PacketError::MissingKnownPack(format!(
+                "minecraft:core {}",
+                self.version.name()
+            )));
+        }
+
+        for registry_data in context.registries.to_packets() {
+            self.send(&registry_data)?;
+        }
+
+        self.send(&FinishConfiguration {})?;
+        self.finish_configuration_sent = true;
+
+        Ok(())
+    }
+}
+
+//Identifies a connection for as long as the server runs, never reused unlike addresses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ConnectionId(pub u64);
+
+impl fmt::Display for ConnectionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+//Why a connection ended
+#[derive(Debug, Error)]
+pub enum DisconnectReason {
+    #[error("Client closed the connection")]
+    EndOfStream,
+    #[error("Failed to read from the connection: {0}")]
+    ReadError(io::Error),
+    #[error("{0}")]
+    Protocol(PacketError),
+    #[error("Kicked: {0}")]
+    Kicked(TextComponent),
+}
+
+impl From<FrameError> for DisconnectReason {
+    fn from(error: FrameError) -> Self {
+        match error {
+            FrameError::Io(error) => DisconnectReason::ReadError(error),
+            error => DisconnectReason::Protocol(error.into()),
         }
+    }
+}
+
+impl DisconnectReason {
+    //What the client is told, if it's still there to be told anything
+    pub fn message(&self) -> Option<TextComponent> {
+        match self {
+            DisconnectReason::EndOfStream | DisconnectReason::ReadError(_) => None,
+            DisconnectReason::Protocol(error) => Some(TextComponent::text(error.to_string())),
+            DisconnectReason::Kicked(reason) => Some(reason.clone()),
+        }
+    }
+}
+
+//The game loop's side of a logged in connection
+pub struct ClientHandle {
+    pub id: ConnectionId,
+    pub peer_addr: SocketAddr,
+    pub profile: PlayerProfile,
+    //Whether the client finished configuration and is in play
+    pub joined: bool,
+    pub version: ProtocolVersion,
+    //The player's playerdata file, written back when they leave
+    pub player_data: Compound,
+    outbound: mpsc::Sender<OutboundMessage>,
+    //Dropping the handle without kicking still ends the connection task, just without a reason
+    kick: oneshot::Sender<TextComponent>,
+}
 
-        return false;
+impl ClientHandle {
+    pub fn send<P: Packet>(&self, packet: &P) -> Result<(), PacketError> {
+        queue_message(
+            &self.outbound,
+            OutboundMessage::Packet(packet.to_raw(&self.version)?),
+        )
     }
 
-    pub async fn test_client_disconnection(&mut self) -> bool {
-        let mut buffer = [0u8; 1];
-        match self.stream.peek(&mut buffer).await {
-            Ok(0) => true,
-            Ok(_) => false,
-            Err(_) => true,
+    //Has the connection task disconnect the client with the given reason
+    pub fn kick(self, reason: TextComponent) {
+        let _ = self.kick.send(reason);
+    }
+}
+
+//What vanilla starts a player's playerdata with before they've been saved once
+fn new_player_data() -> Compound {
+    Compound::from([("DataVersion".to_owned(), Tag::Int(DATA_VERSION))])
+}
+
+//Players whose data can't be read start over like new ones, the same as in vanilla.
+//Their damaged file is kept as the _old backup when they're saved next.
+async fn load_player_data(context: &ServerContext, profile: &PlayerProfile) -> Compound {
+    let storage = context.storage.clone();
+    let uuid = profile.uuid;
+
+    match tokio::task::spawn_blocking(move || storage.load_player(uuid)).await {
+        Ok(Ok(Some(player_data))) => player_data,
+        Ok(Ok(None)) => new_player_data(),
+        Ok(Err(error)) => {
+            println!("Failed to load player data of {}: {}", profile.name, error);
+            new_player_data()
         }
+        Err(error) => {
+            println!("Failed to load player data of {}: {}", profile.name, error);
+            new_player_data()
+        }
+    }
+}
+
+fn queue_message(
+    outbound: &mpsc::Sender<OutboundMessage>,
+    message: OutboundMessage,
+) -> Result<(), PacketError> {
+    match outbound.try_send(message) {
+        Ok(()) => Ok(()),
+        Err(TrySendError::Full(_)) => Err(PacketError::OutboundQueueFull),
+        Err(TrySendError::Closed(_)) => Err(PacketError::ConnectionClosed),
     }
+}
 
-    pub fn process_packets(&mut self) {
-        for i in 0..self.packet_queue.len() {
-            let packet = self.packet_queue.get_mut(i).unwrap();
-            
-            //let protocol_version = packet.buffer.read_var_int().unwrap();
+//Reads and handles everything up to play on the connection's own task, so a slow client
+//only ever stalls itself. Logins and play packets are passed on to the game loop.
+pub async fn run_connection(
+    mut client: Client,
+    id: ConnectionId,
+    peer_addr: SocketAddr,
+    context: Arc<ServerContext>,
+    events: mpsc::Sender<ServerEvent>,
+) {
+    let (kick, mut kicked) = oneshot::channel();
+    let mut kick = Some(kick);
+    let mut joined = false;
 
-            //let server_address = packet.buffer.read_string(255).unwrap();
+    let reason = loop {
+        let result = tokio::select! {
+            reason = &mut kicked => {
+                break DisconnectReason::Kicked(
+                    reason.unwrap_or_else(|_| TextComponent::text("Kicked by the server")),
+                );
+            }
+            result = client.get_incoming_packets() => result,
+        };
 
-            //let server_port = packet.buffer.read_u16().unwrap();
+        match result {
+            Ok(true) => {}
+            Ok(false) => break DisconnectReason::EndOfStream,
+            Err(error) => break error.into(),
+        }
 
-            //let next_state = packet.buffer.read_var_int().unwrap();
+        if let Err(error) = client.process_packets(&context).await {
+            break DisconnectReason::Protocol(error);
+        }
+
+        if let Some(profile) = client.take_new_login() {
+            let player_data = load_player_data(&context, &profile).await;
+            let handle = ClientHandle {
+                id,
+                peer_addr,
+                profile,
+                joined: false,
+                version: client.version,
+                player_data,
+                outbound: client.outbound.clone(),
+                kick: kick.take().expect("Client logged in twice"),
+            };
 
-            println!("Received packet ID: {:?} with content: {:?}",packet.id,packet.buffer);
-            //println!("Handshake info: \nProtocol Version: {:?}\nServer address: {:?}\nServer Port: {:?}\nNext State: {:?}",protocol_version,server_address,server_port,next_state);
+            if events.send(ServerEvent::Login { handle }).await.is_err() {
+                return;
+            }
         }
 
-        self.packet_queue.clear();
+        if !joined && client.state == ConnectionState::Play {
+            joined = true;
+            if events.send(ServerEvent::Joined { id }).await.is_err() {
+                return;
+            }
+        }
+
+        for packet in client.take_play_packets() {
+            if events.send(ServerEvent::Packet { id, packet }).await.is_err() {
+                return;
+            }
+        }
+    };
+
+    println!("Disconnecting client {} ({}): {}", id, peer_addr, reason);
+    if let Some(message) = reason.message() {
+        //The writer task still writes this out once the client is dropped
+        let _ = client.disconnect(message);
+    }
+
+    //Only logged in clients are known to the game loop
+    if kick.is_none() {
+        let _ = events.send(ServerEvent::Disconnected { id, reason }).await;
+    }
+}
+
+//Frames every queued packet and writes them in batches, flushing once per batch.
+//Ends when the client is dropped or the connection fails.
+async fn write_outbound_packets(
+    mut writer: OwnedWriteHalf,
+    mut receiver: mpsc::Receiver<OutboundMessage>,
+) {
+    let mut codec = FrameCodec::new();
+    let mut encryptor: Option<StreamEncryptor> = None;
+    let mut messages = Vec::with_capacity(MAX_WRITE_BATCH);
+    let mut buffer = BytesMut::new();
+
+    while receiver.recv_many(&mut messages, MAX_WRITE_BATCH).await > 0 {
+        for message in messages.drain(..) {
+            match message {
+                OutboundMessage::Packet(packet) => {
+                    let start = buffer.len();
+
+                    if let Err(error) = codec.encode(packet, &mut buffer) {
+                        println!("Failed to frame outbound packet: {}", error);
+                        return;
+                    }
+
+                    if let Some(encryptor) = &mut encryptor {
+                        encryptor.encrypt(&mut buffer[start..]);
+                    }
+                }
+                OutboundMessage::SetCompression(threshold) => {
+                    codec.set_compression_threshold(threshold);
+                }
+                OutboundMessage::EnableEncryption(new_encryptor) => {
+                    encryptor = Some(*new_encryptor);
+                }
+            }
+        }
+
+        if let Err(error) = writer.write_all(&buffer).await {
+            println!("Failed to write outbound packets: {}", error);
+            return;
+        }
+
+        if let Err(error) = writer.flush().await {
+            println!("Failed to flush outbound packets: {}", error);
+            return;
+        }
+
+        buffer.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::InMemoryAuthenticator;
+    use crate::packet::configuration::{AcknowledgeFinishConfiguration, RegistryData};
+    use crate::packet::handshaking::Handshake;
+    use crate::packet::login::LoginAcknowledged;
+    use crate::packet::status::PongResponse;
+    use crate::server::GlobalConfiguration;
+    use crate::types::Decode;
+    use rsa::pkcs8::DecodePublicKey;
+    use rsa::{Pkcs1v15Encrypt, RsaPublicKey};
+    use tokio::net::TcpListener;
+
+    //The vanilla client's side of a connection, used to drive the server end to end
+    struct TestClient {
+        stream: TcpStream,
+        codec: FrameCodec,
+        buffer: BytesMut,
+        encryptor: Option<StreamEncryptor>,
+        decryptor: Option<StreamDecryptor>,
+        version: ProtocolVersion,
+    }
+
+    impl TestClient {
+        fn new(stream: TcpStream) -> Self {
+            Self {
+                stream,
+                codec: FrameCodec::new(),
+                buffer: BytesMut::new(),
+                encryptor: None,
+                decryptor: None,
+                version: ProtocolVersion::NATIVE,
+            }
+        }
+
+        async fn send<P: Packet>(&mut self, packet: &P) {
+            let mut frame = BytesMut::new();
+            let packet = packet.to_raw(&self.version).unwrap();
+            self.codec.encode(packet, &mut frame).unwrap();
+
+            if let Some(encryptor) = &mut self.encryptor {
+                encryptor.encrypt(&mut frame);
+            }
+
+            self.stream.write_all(&frame).await.unwrap();
+        }
+
+        async fn receive<P: Packet>(&mut self) -> P {
+            loop {
+                if let Some(mut packet) = self.codec.decode(&mut self.buffer).unwrap() {
+                    assert_eq!(packet.id, self.version.packet_id::<P>());
+                    return P::decode_for(&mut packet.buffer, &self.version).unwrap();
+                }
+
+                let start = self.buffer.len();
+                self.stream.read_buf(&mut self.buffer).await.unwrap();
+
+                if let Some(decryptor) = &mut self.decryptor {
+                    decryptor.decrypt(&mut self.buffer[start..]);
+                }
+            }
+        }
+    }
+
+    async fn connect(queue_size: usize) -> (Client, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let remote = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+
+        let mut client = Client::with_outbound_queue_size(stream, queue_size);
+        client.state = ConnectionState::Status;
+
+        (client, remote)
+    }
+
+    #[tokio::test]
+    async fn test_send_frames_packets() {
+        let (mut client, mut remote) = connect(OUTBOUND_QUEUE_SIZE).await;
+
+        for payload in 0..3 {
+            client.send(&PongResponse { payload }).unwrap();
+        }
+
+        let mut codec = FrameCodec::new();
+        let mut buffer = BytesMut::new();
+        let mut received = Vec::new();
+
+        while received.len() < 3 {
+            remote.read_buf(&mut buffer).await.unwrap();
+
+            while let Some(mut packet) = codec.decode(&mut buffer).unwrap() {
+                assert_eq!(packet.id, PongResponse::ID);
+                let pong =
+                    PongResponse::decode_for(&mut packet.buffer, &ProtocolVersion::NATIVE).unwrap();
+                received.push(pong.payload);
+            }
+        }
+
+        assert_eq!(received, vec![0, 1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_enable_compression() {
+        let (mut client, mut remote) = connect(OUTBOUND_QUEUE_SIZE).await;
+        client.state = ConnectionState::Login;
+
+        client.enable_compression(16).unwrap();
+        client.state = ConnectionState::Status;
+        client.send(&PongResponse { payload: 7 }).unwrap();
+
+        //Set Compression itself still goes out uncompressed
+        let mut codec = FrameCodec::new();
+        let mut buffer = BytesMut::new();
+        let mut received = Vec::new();
+
+        while received.len() < 2 {
+            remote.read_buf(&mut buffer).await.unwrap();
+
+            while let Some(packet) = codec.decode(&mut buffer).unwrap() {
+                if packet.id == SetCompression::ID && received.is_empty() {
+                    codec.set_compression_threshold(Some(16));
+                }
+                received.push(packet);
+            }
+        }
+
+        let mut pong = received.pop().unwrap();
+        let mut set_compression = received.pop().unwrap();
+
+        assert_eq!(
+            SetCompression::decode_for(&mut set_compression.buffer, &ProtocolVersion::NATIVE)
+                .unwrap()
+                .threshold,
+            VarInt(16)
+        );
+        let pong = PongResponse::decode_for(&mut pong.buffer, &ProtocolVersion::NATIVE).unwrap();
+        assert_eq!(pong.payload, 7);
+    }
+
+    #[tokio::test]
+    async fn test_send_full_queue() {
+        let (mut client, _remote) = connect(2).await;
+
+        //The writer task can't run until this test yields, so the queue fills up
+        client.send(&PongResponse { payload: 0 }).unwrap();
+        client.send(&PongResponse { payload: 1 }).unwrap();
+
+        assert!(matches!(
+            client.send(&PongResponse { payload: 2 }),
+            Err(PacketError::OutboundQueueFull)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_login() {
+        let mut context = ServerContext::new(GlobalConfiguration {
+            enable_packet_encryption: true,
+            enable_packet_compression: true,
+            compression_threshold: 16,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let mut authenticator = InMemoryAuthenticator::new();
+        authenticator.add_profile(PlayerProfile {
+            uuid: uuid::Uuid::from_u128(1),
+            name: "Steve".to_owned(),
+            properties: Vec::new(),
+        });
+        context.authenticator = Box::new(authenticator);
+
+        let (mut client, remote) = connect(OUTBOUND_QUEUE_SIZE).await;
+        let mut remote = TestClient::new(remote);
+        client.state = ConnectionState::Login;
+
+        remote
+            .send(&LoginStart {
+                name: BoundedString("Steve".to_owned()),
+                uuid: uuid::Uuid::nil(),
+            })
+            .await;
+        assert!(client.get_incoming_packets().await.unwrap());
+        client.process_packets(&context).await.unwrap();
+
+        let request: EncryptionRequest = remote.receive().await;
+        let public_key = RsaPublicKey::from_public_key_der(&request.public_key).unwrap();
+        let shared_secret: [u8; 16] = rand::random();
+        let mut rng = rand::thread_rng();
+
+        remote
+            .send(&EncryptionResponse {
+                shared_secret: public_key
+                    .encrypt(&mut rng, Pkcs1v15Encrypt, &shared_secret)
+                    .unwrap(),
+                verify_token: public_key
+                    .encrypt(&mut rng, Pkcs1v15Encrypt, &request.verify_token)
+                    .unwrap(),
+            })
+            .await;
+        assert!(client.get_incoming_packets().await.unwrap());
+        client.process_packets(&context).await.unwrap();
+
+        //Everything from here on is encrypted, starting with Set Compression
+        remote.encryptor = Some(StreamEncryptor::new(&shared_secret).unwrap());
+        remote.decryptor = Some(StreamDecryptor::new(&shared_secret).unwrap());
+
+        let set_compression: SetCompression = remote.receive().await;
+        assert_eq!(set_compression.threshold, VarInt(16));
+        remote.codec.set_compression_threshold(Some(16));
+
+        let login_success: LoginSuccess = remote.receive().await;
+        assert_eq!(login_success.uuid, uuid::Uuid::from_u128(1));
+        assert_eq!(
+            client.take_new_login().map(|profile| profile.uuid),
+            Some(uuid::Uuid::from_u128(1))
+        );
+        assert_eq!(client.take_new_login(), None);
+
+        remote.send(&LoginAcknowledged {}).await;
+        assert!(client.get_incoming_packets().await.unwrap());
+        client.process_packets(&context).await.unwrap();
+
+        assert_eq!(client.state, ConnectionState::Configuration);
+        assert_eq!(client.profile.unwrap().name, "Steve");
+    }
+
+    #[tokio::test]
+    async fn test_offline_login() {
+        let context = ServerContext::new(GlobalConfiguration::offline()).unwrap();
+        let (mut client, remote) = connect(OUTBOUND_QUEUE_SIZE).await;
+        let mut remote = TestClient::new(remote);
+        client.state = ConnectionState::Login;
+
+        //Acknowledging before the server sent Login Success isn't allowed
+        remote.send(&LoginAcknowledged {}).await;
+        assert!(client.get_incoming_packets().await.unwrap());
+        assert!(matches!(
+            client.process_packets(&context).await,
+            Err(PacketError::UnexpectedLoginPacket("Login Acknowledged"))
+        ));
+
+        remote
+            .send(&LoginStart {
+                name: BoundedString("Notch".to_owned()),
+                uuid: uuid::Uuid::nil(),
+            })
+            .await;
+        assert!(client.get_incoming_packets().await.unwrap());
+        client.process_packets(&context).await.unwrap();
+
+        let login_success: LoginSuccess = remote.receive().await;
+        assert_eq!(login_success.uuid, crate::auth::offline_uuid("Notch"));
+        assert_eq!(login_success.username.0, "Notch");
+        assert!(login_success.properties.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_configuration() {
+        let context = ServerContext::new(GlobalConfiguration::offline()).unwrap();
+        let (mut client, remote) = connect(OUTBOUND_QUEUE_SIZE).await;
+        let mut remote = TestClient::new(remote);
+        client.state = ConnectionState::Login;
+
+        remote
+            .send(&LoginStart {
+                name: BoundedString("Notch".to_owned()),
+                uuid: uuid::Uuid::nil(),
+            })
+            .await;
+        assert!(client.get_incoming_packets().await.unwrap());
+        client.process_packets(&context).await.unwrap();
+        let _: LoginSuccess = remote.receive().await;
+
+        remote.send(&LoginAcknowledged {}).await;
+        assert!(client.get_incoming_packets().await.unwrap());
+        client.process_packets(&context).await.unwrap();
+
+        let brand: ClientboundPluginMessage = remote.receive().await;
+        assert_eq!(brand.channel, BRAND_CHANNEL);
+        assert_eq!(
+            String::decode(&mut BytesMut::from(&brand.data.0[..])).unwrap(),
+            SERVER_BRAND
+        );
+        let feature_flags: FeatureFlags = remote.receive().await;
+        assert_eq!(feature_flags.feature_flags, vec!["minecraft:vanilla"]);
+        let known_packs: ClientboundKnownPacks = remote.receive().await;
+        assert_eq!(
+            known_packs.known_packs,
+            Registries::core_packs(&ProtocolVersion::NATIVE)
+        );
+
+        let mut brand = BytesMut::new();
+        "vanilla".to_owned().encode(&mut brand).unwrap();
+        remote
+            .send(&PluginMessage {
+                channel: BRAND_CHANNEL.to_owned(),
+                data: RemainingBytes(brand.freeze()),
+            })
+            .await;
+        assert!(client.get_incoming_packets().await.unwrap());
+        client.process_packets(&context).await.unwrap();
+        assert_eq!(client.brand.as_deref(), Some("vanilla"));
+
+        remote
+            .send(&KnownPacks {
+                //Clients only list the packs they have, here 1.21.1's
+                known_packs: Registries::core_packs(&ProtocolVersion::NATIVE)[1..].to_vec(),
+            })
+            .await;
+        assert!(client.get_incoming_packets().await.unwrap());
+        client.process_packets(&context).await.unwrap();
+
+        for registry in &context.registries.registries {
+            let registry_data: RegistryData = remote.receive().await;
+            assert_eq!(registry_data, registry.to_packet());
+        }
+        let _: FinishConfiguration = remote.receive().await;
+
+        remote.send(&AcknowledgeFinishConfiguration {}).await;
+        assert!(client.get_incoming_packets().await.unwrap());
+        client.process_packets(&context).await.unwrap();
+        assert_eq!(client.state, ConnectionState::Play);
+    }
+
+    #[tokio::test]
+    async fn test_configuration_without_core_pack() {
+        let context = ServerContext::new(GlobalConfiguration::offline()).unwrap();
+        let (mut client, remote) = connect(OUTBOUND_QUEUE_SIZE).await;
+        let mut remote = TestClient::new(remote);
+        client.state = ConnectionState::Configuration;
+
+        //Acknowledging before the server sent Finish Configuration isn't allowed
+        remote.send(&AcknowledgeFinishConfiguration {}).await;
+        assert!(client.get_incoming_packets().await.unwrap());
+        assert!(matches!(
+            client.process_packets(&context).await,
+            Err(PacketError::UnexpectedConfigurationPacket(_))
+        ));
+
+        remote.send(&KnownPacks { known_packs: Vec::new() }).await;
+        assert!(client.get_incoming_packets().await.unwrap());
+        assert!(matches!(
+            client.process_packets(&context).await,
+            Err(PacketError::MissingKnownPack(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_run_connection() {
+        let context = Arc::new(ServerContext::new(GlobalConfiguration::offline()).unwrap());
+        let (events, mut receiver) = mpsc::channel(16);
+        let (mut client, remote) = connect(OUTBOUND_QUEUE_SIZE).await;
+        let mut remote = TestClient::new(remote);
+        client.state = ConnectionState::Login;
+
+        let peer_addr = remote.stream.local_addr().unwrap();
+        tokio::spawn(run_connection(client, ConnectionId(0), peer_addr, context, events));
+
+        remote
+            .send(&LoginStart {
+                name: BoundedString("Notch".to_owned()),
+                uuid: uuid::Uuid::nil(),
+            })
+            .await;
+        let _: LoginSuccess = remote.receive().await;
+
+        let Some(ServerEvent::Login { handle, .. }) = receiver.recv().await else {
+            panic!("Expected a login event");
+        };
+        assert_eq!(handle.profile.name, "Notch");
+
+        //Kicking tells the client why in the Disconnect packet of its state, then closes the connection
+        handle.kick(TextComponent::text("Bye"));
+        assert!(matches!(
+            receiver.recv().await,
+            Some(ServerEvent::Disconnected {
+                reason: DisconnectReason::Kicked(_),
+                ..
+            })
+        ));
+
+        let disconnect: login::Disconnect = remote.receive().await;
+        assert_eq!(disconnect.reason, r#"{"text":"Bye"}"#);
+        assert_eq!(remote.stream.read_buf(&mut remote.buffer).await.unwrap(), 0);
+    }
+
+    fn handshake(protocol_version: i32, next_state: i32) -> Handshake {
+        Handshake {
+            protocol_version: VarInt(protocol_version),
+            server_address: BoundedString("localhost".to_owned()),
+            port: 25565,
+            next_state: VarInt(next_state),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handshake_next_state() {
+        let context = ServerContext::new(GlobalConfiguration::offline()).unwrap();
+
+        for (next_state, state) in [
+            (1, ConnectionState::Status),
+            (2, ConnectionState::Login),
+            (3, ConnectionState::Login),
+        ] {
+            let (mut client, remote) = connect(OUTBOUND_QUEUE_SIZE).await;
+            let mut remote = TestClient::new(remote);
+            client.state = ConnectionState::Handshaking;
+
+            remote
+                .send(&handshake(ProtocolVersion::NATIVE.protocol, next_state))
+                .await;
+            assert!(client.get_incoming_packets().await.unwrap());
+            client.process_packets(&context).await.unwrap();
+            assert_eq!(client.state, state);
+        }
+
+        let (mut client, remote) = connect(OUTBOUND_QUEUE_SIZE).await;
+        let mut remote = TestClient::new(remote);
+        client.state = ConnectionState::Handshaking;
+
+        remote
+            .send(&handshake(ProtocolVersion::NATIVE.protocol, 4))
+            .await;
+        assert!(client.get_incoming_packets().await.unwrap());
+        assert!(matches!(
+            client.process_packets(&context).await,
+            Err(PacketError::InvalidNextState(4))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_follows_state() {
+        let context = ServerContext::new(GlobalConfiguration::offline()).unwrap();
+        let (mut client, remote) = connect(OUTBOUND_QUEUE_SIZE).await;
+        let mut remote = TestClient::new(remote);
+        client.state = ConnectionState::Handshaking;
+
+        //Status Request and Login Start share ID 0, each is decoded in the state it arrives in
+        remote
+            .send(&handshake(ProtocolVersion::NATIVE.protocol, 2))
+            .await;
+        remote
+            .send(&LoginStart {
+                name: BoundedString("Notch".to_owned()),
+                uuid: uuid::Uuid::nil(),
+            })
+            .await;
+        //Ignored, the server never sent a Login Plugin Request
+        remote
+            .send(&login::LoginPluginResponse {
+                message_id: VarInt(0),
+                data: None,
+            })
+            .await;
+        assert!(client.get_incoming_packets().await.unwrap());
+        client.process_packets(&context).await.unwrap();
+        assert_eq!(client.state, ConnectionState::Login);
+        let _: LoginSuccess = remote.receive().await;
+
+        remote.send(&LoginAcknowledged {}).await;
+        assert!(client.get_incoming_packets().await.unwrap());
+        client.process_packets(&context).await.unwrap();
+        assert_eq!(client.state, ConnectionState::Configuration);
+        assert!(client.take_play_packets().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_play_packets_stay_queued() {
+        let context = ServerContext::new(GlobalConfiguration::offline()).unwrap();
+        let (mut client, remote) = connect(OUTBOUND_QUEUE_SIZE).await;
+        let mut remote = TestClient::new(remote);
+        client.state = ConnectionState::Configuration;
+        client.finish_configuration_sent = true;
+
+        //Everything after the acknowledgement belongs to play and is left for the game loop
+        remote.send(&AcknowledgeFinishConfiguration {}).await;
+        remote.send(&PongResponse { payload: 1 }).await;
+        remote.send(&PongResponse { payload: 2 }).await;
+        assert!(client.get_incoming_packets().await.unwrap());
+        client.process_packets(&context).await.unwrap();
+
+        assert_eq!(client.state, ConnectionState::Play);
+        let packets = client.take_play_packets();
+        assert_eq!(packets.len(), 2);
+        assert!(client.take_play_packets().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_status_for_newer_client() {
+        let context = ServerContext::new(GlobalConfiguration::offline()).unwrap();
+        let (mut client, remote) = connect(OUTBOUND_QUEUE_SIZE).await;
+        let mut remote = TestClient::new(remote);
+        client.state = ConnectionState::Handshaking;
+
+        //1.21.2 isn't supported, so it's shown the native version and listed as incompatible
+        remote.send(&handshake(768, 1)).await;
+        remote.send(&status::StatusRequest {}).await;
+        assert!(client.get_incoming_packets().await.unwrap());
+        client.process_packets(&context).await.unwrap();
+        assert_eq!(client.version, ProtocolVersion::NATIVE);
+
+        let response: status::StatusResponse = remote.receive().await;
+        let json: serde_json::Value = serde_json::from_str(&response.json_response).unwrap();
+        assert_eq!(json["version"]["protocol"], ProtocolVersion::NATIVE.protocol);
+        assert_eq!(json["version"]["name"], "1.21-1.21.1");
+    }
+
+    #[tokio::test]
+    async fn test_outdated_client() {
+        let context = ServerContext::new(GlobalConfiguration::offline()).unwrap();
+        let (events, _receiver) = mpsc::channel(16);
+        let (mut client, remote) = connect(OUTBOUND_QUEUE_SIZE).await;
+        let mut remote = TestClient::new(remote);
+        client.state = ConnectionState::Handshaking;
+
+        //Unsupported clients can still see the server list entry
+        remote.send(&handshake(766, 1)).await;
+        assert!(client.get_incoming_packets().await.unwrap());
+        client.process_packets(&context).await.unwrap();
+        assert_eq!(client.version, ProtocolVersion::NATIVE);
+
+        client.state = ConnectionState::Handshaking;
+        let peer_addr = remote.stream.local_addr().unwrap();
+        tokio::spawn(run_connection(
+            client,
+            ConnectionId(0),
+            peer_addr,
+            Arc::new(context),
+            events,
+        ));
+
+        //But they're told which version to use when they try to join
+        remote.send(&handshake(766, 2)).await;
+        let disconnect: login::Disconnect = remote.receive().await;
+        assert_eq!(
+            disconnect.reason,
+            r#"{"text":"Outdated client! Please use 1.21-1.21.1"}"#
+        );
+        assert_eq!(remote.stream.read_buf(&mut remote.buffer).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_unexpected_encryption_response() {
+        let context = ServerContext::new(GlobalConfiguration::offline()).unwrap();
+        let (mut client, remote) = connect(OUTBOUND_QUEUE_SIZE).await;
+        let mut remote = TestClient::new(remote);
+        client.state = ConnectionState::Login;
+
+        remote
+            .send(&EncryptionResponse {
+                shared_secret: vec![0; 128],
+                verify_token: vec![0; 128],
+            })
+            .await;
+        assert!(client.get_incoming_packets().await.unwrap());
+
+        assert!(matches!(
+            client.process_packets(&context).await,
+            Err(PacketError::UnexpectedLoginPacket(_))
+        ));
     }
 }
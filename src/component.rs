@@ -0,0 +1,568 @@
+use bytes::{Bytes, BytesMut};
+
+use crate::nbt::{NbtBufferReaderExt, NbtLimits};
+use crate::reader::{ProtocolBufferReaderExt, ProtocolError};
+use crate::writer::ProtocolBufferWriterExt;
+
+//Items hold other items, like a bundle in a shulker box, but never anywhere near this deep
+const MAX_ITEM_DEPTH: usize = 64;
+const MAX_IDENTIFIER_LENGTH: i32 = 32767;
+
+//The data component type registry of 1.21.1, in ID order
+const COMPONENT_TYPES: &[&str] = &[
+    "minecraft:custom_data",
+    "minecraft:max_stack_size",
+    "minecraft:max_damage",
+    "minecraft:damage",
+    "minecraft:unbreakable",
+    "minecraft:custom_name",
+    "minecraft:item_name",
+    "minecraft:lore",
+    "minecraft:rarity",
+    "minecraft:enchantments",
+    "minecraft:can_place_on",
+    "minecraft:can_break",
+    "minecraft:attribute_modifiers",
+    "minecraft:custom_model_data",
+    "minecraft:hide_additional_tooltip",
+    "minecraft:hide_tooltip",
+    "minecraft:repair_cost",
+    "minecraft:creative_slot_lock",
+    "minecraft:enchantment_glint_override",
+    "minecraft:intangible_projectile",
+    "minecraft:food",
+    "minecraft:fire_resistant",
+    "minecraft:tool",
+    "minecraft:stored_enchantments",
+    "minecraft:dyed_color",
+    "minecraft:map_color",
+    "minecraft:map_id",
+    "minecraft:map_decorations",
+    "minecraft:map_post_processing",
+    "minecraft:charged_projectiles",
+    "minecraft:bundle_contents",
+    "minecraft:potion_contents",
+    "minecraft:suspicious_stew_effects",
+    "minecraft:writable_book_content",
+    "minecraft:written_book_content",
+    "minecraft:trim",
+    "minecraft:debug_stick_state",
+    "minecraft:entity_data",
+    "minecraft:bucket_entity_data",
+    "minecraft:block_entity_data",
+    "minecraft:instrument",
+    "minecraft:ominous_bottle_amplifier",
+    "minecraft:jukebox_playable",
+    "minecraft:recipes",
+    "minecraft:lodestone_tracker",
+    "minecraft:firework_explosion",
+    "minecraft:fireworks",
+    "minecraft:profile",
+    "minecraft:note_block_sound",
+    "minecraft:banner_patterns",
+    "minecraft:base_color",
+    "minecraft:pot_decorations",
+    "minecraft:container",
+    "minecraft:block_state",
+    "minecraft:bees",
+    "minecraft:lock",
+    "minecraft:container_loot",
+];
+
+//A data component added to an item stack. Nothing looks inside components yet, so the value is
+//kept as the bytes it was sent as, and reading one only walks its format to find where it ends.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DataComponent {
+    //The component type's ID in the data component type registry
+    pub id: i32,
+    pub data: Bytes,
+}
+
+impl DataComponent {
+    pub fn read(buffer: &mut BytesMut) -> Result<Self, ProtocolError> {
+        let id = buffer.read_var_int()?;
+        let mut rest = &buffer[..];
+        skip_component(&mut rest, id, 0)?;
+
+        let length = buffer.len() - rest.len();
+        Ok(Self {
+            id,
+            data: buffer.split_to(length).freeze(),
+        })
+    }
+
+    pub fn write(&self, buffer: &mut BytesMut) -> Result<(), ProtocolError> {
+        buffer.write_var_int(&self.id)?;
+        buffer.extend_from_slice(&self.data);
+        Ok(())
+    }
+}
+
+type Skip = fn(&mut &[u8]) -> Result<(), ProtocolError>;
+
+fn skip_component(buffer: &mut &[u8], id: i32, depth: usize) -> Result<(), ProtocolError> {
+    let name = usize::try_from(id)
+        .ok()
+        .and_then(|index| COMPONENT_TYPES.get(index))
+        .copied()
+        .unwrap_or_default();
+
+    match name {
+        //Text and arbitrary data are NBT, and types without a network format of their own fall
+        //back to the NBT their save format writes
+        "minecraft:custom_data"
+        | "minecraft:custom_name"
+        | "minecraft:item_name"
+        | "minecraft:intangible_projectile"
+        | "minecraft:map_decorations"
+        | "minecraft:debug_stick_state"
+        | "minecraft:entity_data"
+        | "minecraft:bucket_entity_data"
+        | "minecraft:block_entity_data"
+        | "minecraft:recipes"
+        | "minecraft:lock"
+        | "minecraft:container_loot" => skip_nbt(buffer),
+        "minecraft:max_stack_size"
+        | "minecraft:max_damage"
+        | "minecraft:damage"
+        | "minecraft:rarity"
+        | "minecraft:custom_model_data"
+        | "minecraft:repair_cost"
+        | "minecraft:map_id"
+        | "minecraft:map_post_processing"
+        | "minecraft:ominous_bottle_amplifier"
+        | "minecraft:base_color" => skip_var_int(buffer),
+        "minecraft:note_block_sound" => skip_identifier(buffer),
+        "minecraft:hide_additional_tooltip"
+        | "minecraft:hide_tooltip"
+        | "minecraft:creative_slot_lock"
+        | "minecraft:fire_resistant" => Ok(()),
+        "minecraft:unbreakable" | "minecraft:enchantment_glint_override" => skip_bool(buffer),
+        "minecraft:map_color" => skip_int(buffer),
+        "minecraft:dyed_color" => {
+            skip_int(buffer)?;
+            skip_bool(buffer)
+        }
+        "minecraft:lore" => skip_list(buffer, skip_nbt),
+        "minecraft:enchantments" | "minecraft:stored_enchantments" => {
+            skip_list(buffer, |buffer| {
+                skip_var_int(buffer)?;
+                skip_var_int(buffer)
+            })?;
+            skip_bool(buffer)
+        }
+        "minecraft:can_place_on" | "minecraft:can_break" => {
+            skip_list(buffer, skip_block_predicate)?;
+            skip_bool(buffer)
+        }
+        "minecraft:attribute_modifiers" => {
+            //The attribute, the modifier's ID, amount and operation, and the slots it applies in
+            skip_list(buffer, |buffer| {
+                skip_var_int(buffer)?;
+                skip_identifier(buffer)?;
+                buffer.read_f64()?;
+                skip_var_int(buffer)?;
+                skip_var_int(buffer)
+            })?;
+            skip_bool(buffer)
+        }
+        "minecraft:food" => {
+            //Nutrition, saturation, whether it can always be eaten and how long eating takes
+            skip_var_int(buffer)?;
+            buffer.read_f32()?;
+            skip_bool(buffer)?;
+            buffer.read_f32()?;
+            if buffer.read_bool()? {
+                skip_stack(buffer, depth + 1)?;
+            }
+            for _ in 0..read_length(buffer)? {
+                skip_effect(buffer, 0)?;
+                buffer.read_f32()?;
+            }
+            Ok(())
+        }
+        "minecraft:tool" => {
+            skip_list(buffer, |buffer| {
+                skip_holder_set(buffer)?;
+                skip_option(buffer, skip_float)?;
+                skip_option(buffer, skip_bool)
+            })?;
+            buffer.read_f32()?;
+            skip_var_int(buffer)
+        }
+        "minecraft:charged_projectiles" | "minecraft:bundle_contents" | "minecraft:container" => {
+            for _ in 0..read_length(buffer)? {
+                skip_stack(buffer, depth + 1)?;
+            }
+            Ok(())
+        }
+        "minecraft:potion_contents" => {
+            skip_option(buffer, skip_var_int)?;
+            skip_option(buffer, skip_int)?;
+            for _ in 0..read_length(buffer)? {
+                skip_effect(buffer, 0)?;
+            }
+            Ok(())
+        }
+        "minecraft:suspicious_stew_effects" => skip_list(buffer, |buffer| {
+            skip_var_int(buffer)?;
+            skip_var_int(buffer)
+        }),
+        "minecraft:writable_book_content" => skip_list(buffer, |buffer| {
+            skip_filterable(buffer, |buffer| buffer.read_string(1024).map(drop))
+        }),
+        "minecraft:written_book_content" => {
+            //The title, author and generation, then pages that may have a filtered version
+            skip_filterable(buffer, |buffer| buffer.read_string(32).map(drop))?;
+            skip_string(buffer)?;
+            skip_var_int(buffer)?;
+            skip_list(buffer, |buffer| skip_filterable(buffer, skip_nbt))?;
+            skip_bool(buffer)
+        }
+        "minecraft:trim" => {
+            skip_holder(buffer, skip_trim_material)?;
+            skip_holder(buffer, skip_trim_pattern)?;
+            skip_bool(buffer)
+        }
+        "minecraft:instrument" => skip_holder(buffer, skip_instrument),
+        "minecraft:jukebox_playable" => {
+            //Either the song itself or the key of one
+            if buffer.read_bool()? {
+                skip_holder(buffer, skip_jukebox_song)?;
+            } else {
+                skip_identifier(buffer)?;
+            }
+            skip_bool(buffer)
+        }
+        "minecraft:lodestone_tracker" => {
+            //The dimension and position of the lodestone, and whether it's tracked
+            skip_option(buffer, |buffer| {
+                skip_identifier(buffer)?;
+                buffer.read_i64().map(drop)
+            })?;
+            skip_bool(buffer)
+        }
+        "minecraft:firework_explosion" => skip_firework_explosion(buffer),
+        "minecraft:fireworks" => {
+            skip_var_int(buffer)?;
+            skip_list(buffer, skip_firework_explosion)
+        }
+        "minecraft:profile" => {
+            skip_option(buffer, |buffer| buffer.read_string(16).map(drop))?;
+            skip_option(buffer, |buffer| buffer.read_uuid().map(drop))?;
+            skip_list(buffer, |buffer| {
+                buffer.read_string(64)?;
+                skip_string(buffer)?;
+                skip_option(buffer, |buffer| buffer.read_string(1024).map(drop))
+            })
+        }
+        "minecraft:banner_patterns" => skip_list(buffer, |buffer| {
+            skip_holder(buffer, |buffer| {
+                skip_identifier(buffer)?;
+                skip_string(buffer)
+            })?;
+            skip_var_int(buffer)
+        }),
+        "minecraft:pot_decorations" => skip_list(buffer, skip_var_int),
+        "minecraft:block_state" => skip_list(buffer, |buffer| {
+            skip_string(buffer)?;
+            skip_string(buffer)
+        }),
+        "minecraft:bees" => skip_list(buffer, |buffer| {
+            //The bee, how long it has been in the hive and how long it has to stay
+            skip_nbt(buffer)?;
+            skip_var_int(buffer)?;
+            skip_var_int(buffer)
+        }),
+        _ => Err(ProtocolError::InvalidEnum {
+            name: "data component type",
+            value: id,
+        }),
+    }
+}
+
+//The same format as a Slot, for items inside other items
+fn skip_stack(buffer: &mut &[u8], depth: usize) -> Result<(), ProtocolError> {
+    if depth > MAX_ITEM_DEPTH {
+        return Err(ProtocolError::InvalidData(format!(
+            "Items are nested deeper than the maximum of {}",
+            MAX_ITEM_DEPTH
+        )));
+    }
+
+    if buffer.read_var_int()? <= 0 {
+        return Ok(());
+    }
+
+    skip_var_int(buffer)?;
+    let added = read_length(buffer)?;
+    let removed = read_length(buffer)?;
+
+    for _ in 0..added {
+        let id = buffer.read_var_int()?;
+        skip_component(buffer, id, depth)?;
+    }
+    for _ in 0..removed {
+        skip_var_int(buffer)?;
+    }
+
+    Ok(())
+}
+
+//An effect with the effect it goes back to when it runs out, which can have one of its own
+fn skip_effect(buffer: &mut &[u8], depth: usize) -> Result<(), ProtocolError> {
+    skip_var_int(buffer)?;
+    skip_effect_details(buffer, depth)
+}
+
+fn skip_effect_details(buffer: &mut &[u8], depth: usize) -> Result<(), ProtocolError> {
+    if depth > MAX_ITEM_DEPTH {
+        return Err(ProtocolError::InvalidData(format!(
+            "Effects are nested deeper than the maximum of {}",
+            MAX_ITEM_DEPTH
+        )));
+    }
+
+    //Amplifier and duration, then whether it's ambient, shows particles and shows its icon
+    skip_var_int(buffer)?;
+    skip_var_int(buffer)?;
+    skip_bool(buffer)?;
+    skip_bool(buffer)?;
+    skip_bool(buffer)?;
+
+    if buffer.read_bool()? {
+        skip_effect_details(buffer, depth + 1)?;
+    }
+
+    Ok(())
+}
+
+//Blocks, block state properties and block entity NBT, each of which is optional
+fn skip_block_predicate(buffer: &mut &[u8]) -> Result<(), ProtocolError> {
+    skip_option(buffer, skip_holder_set)?;
+    skip_option(buffer, |buffer| {
+        skip_list(buffer, |buffer| {
+            skip_string(buffer)?;
+            //An exact value, or a range with an optional minimum and maximum
+            if buffer.read_bool()? {
+                skip_string(buffer)
+            } else {
+                skip_option(buffer, skip_string)?;
+                skip_option(buffer, skip_string)
+            }
+        })
+    })?;
+    skip_option(buffer, skip_nbt)
+}
+
+fn skip_trim_material(buffer: &mut &[u8]) -> Result<(), ProtocolError> {
+    //The asset name, ingredient item and model index
+    skip_string(buffer)?;
+    skip_var_int(buffer)?;
+    buffer.read_f32()?;
+    //The asset names that replace it on specific armor materials
+    skip_list(buffer, |buffer| {
+        skip_var_int(buffer)?;
+        skip_string(buffer)
+    })?;
+    skip_nbt(buffer)
+}
+
+fn skip_trim_pattern(buffer: &mut &[u8]) -> Result<(), ProtocolError> {
+    //The asset ID, template item and description, and whether it's a decal
+    skip_identifier(buffer)?;
+    skip_var_int(buffer)?;
+    skip_nbt(buffer)?;
+    skip_bool(buffer)
+}
+
+fn skip_instrument(buffer: &mut &[u8]) -> Result<(), ProtocolError> {
+    //The sound, how long it plays and how far it's heard
+    skip_holder(buffer, skip_sound_event)?;
+    skip_var_int(buffer)?;
+    buffer.read_f32().map(drop)
+}
+
+fn skip_jukebox_song(buffer: &mut &[u8]) -> Result<(), ProtocolError> {
+    //The sound, description, length in seconds and comparator output
+    skip_holder(buffer, skip_sound_event)?;
+    skip_nbt(buffer)?;
+    buffer.read_f32()?;
+    skip_var_int(buffer)
+}
+
+fn skip_sound_event(buffer: &mut &[u8]) -> Result<(), ProtocolError> {
+    skip_identifier(buffer)?;
+    skip_option(buffer, skip_float)
+}
+
+fn skip_firework_explosion(buffer: &mut &[u8]) -> Result<(), ProtocolError> {
+    //The shape, colors and fade colors, then whether it has a trail and twinkles
+    skip_var_int(buffer)?;
+    skip_list(buffer, skip_int)?;
+    skip_list(buffer, skip_int)?;
+    skip_bool(buffer)?;
+    skip_bool(buffer)
+}
+
+//A value that may have a version with profanity filtered out
+fn skip_filterable(buffer: &mut &[u8], value: Skip) -> Result<(), ProtocolError> {
+    value(buffer)?;
+    skip_option(buffer, value)
+}
+
+//A registry entry's ID plus one, or 0 followed by the entry itself
+fn skip_holder(buffer: &mut &[u8], direct: Skip) -> Result<(), ProtocolError> {
+    match buffer.read_var_int()? {
+        0 => direct(buffer),
+        _ => Ok(()),
+    }
+}
+
+//A tag's name after a 0, or that many registry entry IDs plus one
+fn skip_holder_set(buffer: &mut &[u8]) -> Result<(), ProtocolError> {
+    match buffer.read_var_int()? {
+        0 => skip_identifier(buffer),
+        count => {
+            for _ in 1..count {
+                skip_var_int(buffer)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn skip_option(buffer: &mut &[u8], value: Skip) -> Result<(), ProtocolError> {
+    if buffer.read_bool()? {
+        value(buffer)?;
+    }
+    Ok(())
+}
+
+fn skip_list(buffer: &mut &[u8], element: Skip) -> Result<(), ProtocolError> {
+    for _ in 0..read_length(buffer)? {
+        element(buffer)?;
+    }
+    Ok(())
+}
+
+//Every element takes at least a byte, so a length past the buffer is always invalid
+fn read_length(buffer: &mut &[u8]) -> Result<usize, ProtocolError> {
+    let length = buffer.read_var_int()?;
+
+    if length < 0 || length as usize > buffer.len() {
+        return Err(ProtocolError::InvalidLength {
+            length: length.into(),
+            max: buffer.len(),
+        });
+    }
+
+    Ok(length as usize)
+}
+
+fn skip_nbt(buffer: &mut &[u8]) -> Result<(), ProtocolError> {
+    buffer.read_network_nbt(NbtLimits::NETWORK).map(drop)
+}
+
+fn skip_identifier(buffer: &mut &[u8]) -> Result<(), ProtocolError> {
+    buffer.read_string(MAX_IDENTIFIER_LENGTH).map(drop)
+}
+
+fn skip_string(buffer: &mut &[u8]) -> Result<(), ProtocolError> {
+    buffer.read_full_string().map(drop)
+}
+
+fn skip_var_int(buffer: &mut &[u8]) -> Result<(), ProtocolError> {
+    buffer.read_var_int().map(drop)
+}
+
+fn skip_int(buffer: &mut &[u8]) -> Result<(), ProtocolError> {
+    buffer.read_i32().map(drop)
+}
+
+fn skip_float(buffer: &mut &[u8]) -> Result<(), ProtocolError> {
+    buffer.read_f32().map(drop)
+}
+
+fn skip_bool(buffer: &mut &[u8]) -> Result<(), ProtocolError> {
+    buffer.read_bool().map(drop)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(data: &[u8]) -> Result<(DataComponent, BytesMut), ProtocolError> {
+        let mut buffer = BytesMut::from(data);
+        let component = DataComponent::read(&mut buffer)?;
+        Ok((component, buffer))
+    }
+
+    #[test]
+    fn test_read_component() {
+        //A custom name as a string tag, followed by the next component
+        let (component, rest) = read(&[5, 8, 0, 2, b'h', b'i', 3, 1]).unwrap();
+        assert_eq!(component.id, 5);
+        assert_eq!(&component.data[..], &[8, 0, 2, b'h', b'i']);
+        assert_eq!(&rest[..], &[3, 1]);
+
+        let mut buffer = BytesMut::new();
+        component.write(&mut buffer).unwrap();
+        assert_eq!(&buffer[..], &[5, 8, 0, 2, b'h', b'i']);
+
+        //Units have no data at all
+        let (component, rest) = read(&[15, 1]).unwrap();
+        assert!(component.data.is_empty());
+        assert_eq!(&rest[..], &[1]);
+    }
+
+    #[test]
+    fn test_nested_items() {
+        //A bundle holding a stick with a lore line and a stack of 3 dirt
+        let data = [30, 2, 1, 9, 1, 0, 7, 1, 8, 0, 1, b'x', 3, 4, 0, 0];
+        let (component, rest) = read(&data).unwrap();
+        assert_eq!(component.data.len(), data.len() - 1);
+        assert!(rest.is_empty());
+
+        //Each bundle holds the next one
+        let mut data = vec![30];
+        for _ in 0..=MAX_ITEM_DEPTH {
+            data.extend_from_slice(&[1, 1, 1, 1, 0, 30]);
+        }
+        data.push(0);
+        assert!(matches!(read(&data), Err(ProtocolError::InvalidData(_))));
+    }
+
+    #[test]
+    fn test_registry_entries() {
+        //A trim with a material from the registry and a pattern sent inline
+        let data = [35, 3, 0, 1, b'p', 4, 8, 0, 1, b'd', 0, 1];
+        let (component, rest) = read(&data).unwrap();
+        assert_eq!(component.data.len(), data.len() - 1);
+        assert!(rest.is_empty());
+
+        //A tool that mines a block tag at its own speed
+        let data = [22, 1, 0, 1, b't', 1, 0x40, 0, 0, 0, 0, 0x3f, 0x80, 0, 0, 1];
+        let (component, rest) = read(&data).unwrap();
+        assert_eq!(component.data.len(), data.len() - 1);
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn test_every_type_has_a_format() {
+        for id in 0..COMPONENT_TYPES.len() as i32 {
+            let result = skip_component(&mut &[0u8; 64][..], id, 0);
+            assert!(
+                !matches!(result, Err(ProtocolError::InvalidEnum { .. })),
+                "{}",
+                COMPONENT_TYPES[id as usize]
+            );
+        }
+
+        assert!(matches!(
+            read(&[COMPONENT_TYPES.len() as u8]),
+            Err(ProtocolError::InvalidEnum { .. })
+        ));
+        assert!(read(&[0x80, 0x80, 0x80, 0x80, 0x0f]).is_err());
+    }
+}
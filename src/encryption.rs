@@ -0,0 +1,160 @@
+use aes::Aes128;
+use cfb8::cipher::{generic_array::GenericArray, BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use rsa::pkcs8::EncodePublicKey;
+use rsa::{Pkcs1v15Encrypt, RsaPrivateKey};
+use thiserror::Error;
+
+//Same key size the vanilla server uses
+const RSA_KEY_BITS: usize = 1024;
+const SHARED_SECRET_LENGTH: usize = 16;
+
+#[derive(Debug, Error)]
+pub enum EncryptionError {
+    #[error("RSA error: {0}")]
+    Rsa(#[from] rsa::Error),
+    #[error("Failed to encode public key: {0}")]
+    PublicKey(#[from] rsa::pkcs8::spki::Error),
+    #[error("Shared secret must be {SHARED_SECRET_LENGTH} bytes, got {0}")]
+    InvalidSharedSecret(usize),
+    #[error("Verify token doesn't match")]
+    VerifyTokenMismatch,
+}
+
+//RSA keypair generated at startup, used by clients to send the shared secret
+pub struct ServerKeyPair {
+    private_key: RsaPrivateKey,
+    pub public_key_der: Vec<u8>,
+}
+
+impl ServerKeyPair {
+    pub fn generate() -> Result<Self, EncryptionError> {
+        let private_key = RsaPrivateKey::new(&mut rand::thread_rng(), RSA_KEY_BITS)?;
+        let public_key_der = private_key
+            .to_public_key()
+            .to_public_key_der()?
+            .into_vec();
+
+        Ok(Self {
+            private_key,
+            public_key_der,
+        })
+    }
+
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        Ok(self.private_key.decrypt(Pkcs1v15Encrypt, data)?)
+    }
+
+    //Decrypts the Encryption Response, checking the verify token and returning the shared secret
+    pub fn decrypt_shared_secret(
+        &self,
+        shared_secret: &[u8],
+        verify_token: &[u8],
+        expected_token: &[u8],
+    ) -> Result<Vec<u8>, EncryptionError> {
+        if self.decrypt(verify_token)? != expected_token {
+            return Err(EncryptionError::VerifyTokenMismatch);
+        }
+
+        let shared_secret = self.decrypt(shared_secret)?;
+
+        if shared_secret.len() != SHARED_SECRET_LENGTH {
+            return Err(EncryptionError::InvalidSharedSecret(shared_secret.len()));
+        }
+
+        Ok(shared_secret)
+    }
+}
+
+//AES-128 in CFB8 mode, using the shared secret as both the key and the IV.
+//Each direction keeps its own state for the whole connection.
+#[derive(Debug)]
+pub struct StreamEncryptor(cfb8::Encryptor<Aes128>);
+
+#[derive(Debug)]
+pub struct StreamDecryptor(cfb8::Decryptor<Aes128>);
+
+impl StreamEncryptor {
+    pub fn new(shared_secret: &[u8]) -> Result<Self, EncryptionError> {
+        cfb8::Encryptor::new_from_slices(shared_secret, shared_secret)
+            .map(StreamEncryptor)
+            .map_err(|_| EncryptionError::InvalidSharedSecret(shared_secret.len()))
+    }
+
+    pub fn encrypt(&mut self, data: &mut [u8]) {
+        for byte in data.chunks_mut(1) {
+            self.0.encrypt_block_mut(GenericArray::from_mut_slice(byte));
+        }
+    }
+}
+
+impl StreamDecryptor {
+    pub fn new(shared_secret: &[u8]) -> Result<Self, EncryptionError> {
+        cfb8::Decryptor::new_from_slices(shared_secret, shared_secret)
+            .map(StreamDecryptor)
+            .map_err(|_| EncryptionError::InvalidSharedSecret(shared_secret.len()))
+    }
+
+    pub fn decrypt(&mut self, data: &mut [u8]) {
+        for byte in data.chunks_mut(1) {
+            self.0.decrypt_block_mut(GenericArray::from_mut_slice(byte));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rsa::pkcs8::DecodePublicKey;
+    use rsa::RsaPublicKey;
+
+    #[test]
+    fn test_decrypt_shared_secret() {
+        let key_pair = ServerKeyPair::generate().unwrap();
+        let public_key = RsaPublicKey::from_public_key_der(&key_pair.public_key_der).unwrap();
+
+        let mut rng = rand::thread_rng();
+        let secret = [7u8; 16];
+        let token = [1u8, 2, 3, 4];
+
+        let encrypted_secret = public_key.encrypt(&mut rng, Pkcs1v15Encrypt, &secret).unwrap();
+        let encrypted_token = public_key.encrypt(&mut rng, Pkcs1v15Encrypt, &token).unwrap();
+
+        assert_eq!(
+            key_pair
+                .decrypt_shared_secret(&encrypted_secret, &encrypted_token, &token)
+                .unwrap(),
+            secret
+        );
+        assert!(matches!(
+            key_pair.decrypt_shared_secret(&encrypted_secret, &encrypted_token, &[0, 0, 0, 0]),
+            Err(EncryptionError::VerifyTokenMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_stream_cipher_round_trip() {
+        let secret = [42u8; 16];
+        let mut encryptor = StreamEncryptor::new(&secret).unwrap();
+        let mut decryptor = StreamDecryptor::new(&secret).unwrap();
+
+        let message = b"Hello, world! This spans multiple AES blocks.".to_vec();
+        let mut data = message.clone();
+
+        //The cipher state carries over, so encrypting in pieces must match decrypting in others
+        encryptor.encrypt(&mut data[..10]);
+        encryptor.encrypt(&mut data[10..]);
+        assert_ne!(data, message);
+
+        decryptor.decrypt(&mut data[..25]);
+        decryptor.decrypt(&mut data[25..]);
+        assert_eq!(data, message);
+    }
+
+    #[test]
+    fn test_invalid_shared_secret() {
+        assert!(matches!(
+            StreamEncryptor::new(&[0u8; 8]),
+            Err(EncryptionError::InvalidSharedSecret(8))
+        ));
+    }
+}
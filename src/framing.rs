@@ -0,0 +1,404 @@
+use std::io::{Read, Write};
+
+use bytes::{Buf, BufMut, BytesMut};
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use thiserror::Error;
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::packet::RawPacket;
+use crate::reader::{ProtocolBufferReaderExt, ProtocolError};
+use crate::writer::ProtocolBufferWriterExt;
+
+//The frame length is a VarInt of at most 3 bytes, so no frame can be bigger than 2^21 - 1
+pub const MAX_LENGTH_BYTES: usize = 3;
+pub const MAX_FRAME_SIZE: usize = (1 << (7 * MAX_LENGTH_BYTES)) - 1;
+//The biggest packet a compressed frame is allowed to inflate to
+pub const MAX_DECOMPRESSED_SIZE: usize = 1 << 23;
+
+const SEGMENT_BITS: u8 = 0x7F;
+const CONTINUE_BIT: u8 = 0x80;
+
+#[derive(Debug, Error)]
+pub enum FrameError {
+    #[error("Frame length is longer than {MAX_LENGTH_BYTES} bytes")]
+    LengthTooLong,
+    #[error("Frame of {size} bytes is bigger than the max size of {max} bytes")]
+    FrameTooLarge { size: usize, max: usize },
+    #[error("Received an empty frame")]
+    EmptyFrame,
+    #[error("Failed to read packet ID: {0}")]
+    InvalidPacketId(ProtocolError),
+    #[error("Failed to read uncompressed size: {0}")]
+    InvalidDataLength(ProtocolError),
+    #[error("Failed to write frame: {0}")]
+    Write(#[from] ProtocolError),
+    #[error("Badly compressed packet, size of {size} is below the threshold of {threshold}")]
+    BelowCompressionThreshold { size: usize, threshold: usize },
+    #[error("Compressed packet of {size} bytes is bigger than the max size of {MAX_DECOMPRESSED_SIZE} bytes")]
+    DecompressedTooLarge { size: usize },
+    #[error("Compressed packet inflated to {actual} bytes instead of {expected}")]
+    DecompressedSizeMismatch { expected: usize, actual: usize },
+    #[error("Connection error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+//Splits the byte stream into length-prefixed packets, keeping incomplete frames
+//buffered until the rest of their bytes arrive.
+//Once a compression threshold is set, every frame also carries its uncompressed size
+//and packets of at least that size are zlib compressed.
+#[derive(Debug, Clone)]
+pub struct FrameCodec {
+    max_frame_size: usize,
+    compression_threshold: Option<usize>,
+}
+
+impl FrameCodec {
+    pub fn new() -> Self {
+        Self::with_max_frame_size(MAX_FRAME_SIZE)
+    }
+
+    pub fn with_max_frame_size(max_frame_size: usize) -> Self {
+        Self {
+            max_frame_size: max_frame_size.min(MAX_FRAME_SIZE),
+            compression_threshold: None,
+        }
+    }
+
+    pub fn set_compression_threshold(&mut self, threshold: Option<usize>) {
+        self.compression_threshold = threshold;
+    }
+
+    //Inflates the body of a compressed frame, leaving uncompressed ones untouched
+    fn decompress(&self, mut frame: BytesMut, threshold: usize) -> Result<BytesMut, FrameError> {
+        let data_length = frame
+            .read_var_int()
+            .map_err(FrameError::InvalidDataLength)?;
+
+        if data_length == 0 {
+            return Ok(frame);
+        }
+
+        let data_length = data_length as u32 as usize;
+
+        if data_length < threshold {
+            return Err(FrameError::BelowCompressionThreshold {
+                size: data_length,
+                threshold,
+            });
+        }
+
+        if data_length > MAX_DECOMPRESSED_SIZE {
+            return Err(FrameError::DecompressedTooLarge { size: data_length });
+        }
+
+        //Reading one byte past the declared size is enough to tell it was a lie,
+        //without letting a small frame inflate into something huge
+        let mut decompressed = Vec::with_capacity(data_length);
+        ZlibDecoder::new(&frame[..])
+            .take(data_length as u64 + 1)
+            .read_to_end(&mut decompressed)?;
+
+        if decompressed.len() != data_length {
+            return Err(FrameError::DecompressedSizeMismatch {
+                expected: data_length,
+                actual: decompressed.len(),
+            });
+        }
+
+        Ok(BytesMut::from(&decompressed[..]))
+    }
+
+    //Reads the frame length without consuming it, returning the length and the
+    //amount of bytes it takes, or None if the VarInt isn't complete yet
+    fn peek_length(src: &BytesMut) -> Result<Option<(usize, usize)>, FrameError> {
+        let mut length: usize = 0;
+
+        for (position, byte) in src.iter().take(MAX_LENGTH_BYTES).enumerate() {
+            length |= ((byte & SEGMENT_BITS) as usize) << (7 * position);
+
+            if byte & CONTINUE_BIT == 0 {
+                return Ok(Some((length, position + 1)));
+            }
+        }
+
+        if src.len() >= MAX_LENGTH_BYTES {
+            return Err(FrameError::LengthTooLong);
+        }
+
+        Ok(None)
+    }
+}
+
+impl Default for FrameCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder for FrameCodec {
+    type Item = RawPacket;
+    type Error = FrameError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let Some((length, header_size)) = Self::peek_length(src)? else {
+            return Ok(None);
+        };
+
+        if length == 0 {
+            return Err(FrameError::EmptyFrame);
+        }
+
+        if length > self.max_frame_size {
+            return Err(FrameError::FrameTooLarge {
+                size: length,
+                max: self.max_frame_size,
+            });
+        }
+
+        if src.len() < header_size + length {
+            src.reserve(header_size + length - src.len());
+            return Ok(None);
+        }
+
+        src.advance(header_size);
+        let mut buffer = src.split_to(length);
+
+        if let Some(threshold) = self.compression_threshold {
+            buffer = self.decompress(buffer, threshold)?;
+        }
+
+        let id = buffer
+            .read_var_int()
+            .map_err(FrameError::InvalidPacketId)?;
+
+        Ok(Some(RawPacket { id, buffer }))
+    }
+}
+
+impl Encoder<RawPacket> for FrameCodec {
+    type Error = FrameError;
+
+    fn encode(&mut self, packet: RawPacket, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let mut payload = BytesMut::with_capacity(5 + packet.buffer.len());
+        payload.write_var_int(&packet.id)?;
+        payload.extend_from_slice(&packet.buffer);
+
+        if let Some(threshold) = self.compression_threshold {
+            let mut compressed = BytesMut::with_capacity(payload.len() + 5);
+
+            if payload.len() >= threshold {
+                compressed.write_var_int(&(payload.len() as i32))?;
+
+                let mut encoder = ZlibEncoder::new(compressed.writer(), Compression::default());
+                encoder.write_all(&payload)?;
+                compressed = encoder.finish()?.into_inner();
+            } else {
+                compressed.write_var_int(&0)?;
+                compressed.extend_from_slice(&payload);
+            }
+
+            payload = compressed;
+        }
+
+        if payload.len() > self.max_frame_size {
+            return Err(FrameError::FrameTooLarge {
+                size: payload.len(),
+                max: self.max_frame_size,
+            });
+        }
+
+        dst.reserve(MAX_LENGTH_BYTES + payload.len());
+        dst.write_var_int(&(payload.len() as i32))?;
+        dst.extend_from_slice(&payload);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(id: i32, body: &[u8]) -> BytesMut {
+        let mut dst = BytesMut::new();
+        FrameCodec::new()
+            .encode(
+                RawPacket {
+                    id,
+                    buffer: BytesMut::from(body),
+                },
+                &mut dst,
+            )
+            .unwrap();
+        dst
+    }
+
+    fn compressed_codec(threshold: usize) -> FrameCodec {
+        let mut codec = FrameCodec::new();
+        codec.set_compression_threshold(Some(threshold));
+        codec
+    }
+
+    #[test]
+    fn test_decode_partial_frame() {
+        let data = frame(0x01, &[1, 2, 3, 4]);
+        let mut codec = FrameCodec::new();
+        let mut src = BytesMut::new();
+
+        for byte in &data[..data.len() - 1] {
+            src.extend_from_slice(&[*byte]);
+            assert!(codec.decode(&mut src).unwrap().is_none());
+        }
+
+        src.extend_from_slice(&data[data.len() - 1..]);
+        let packet = codec.decode(&mut src).unwrap().unwrap();
+
+        assert_eq!(packet.id, 0x01);
+        assert_eq!(&packet.buffer[..], &[1, 2, 3, 4]);
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn test_decode_coalesced_frames() {
+        let mut src = frame(0x00, &[]);
+        src.extend_from_slice(&frame(0x01, &[9; 300]));
+        src.extend_from_slice(&frame(0x02, &[7])[..1]);
+
+        let mut codec = FrameCodec::new();
+
+        assert_eq!(codec.decode(&mut src).unwrap().unwrap().id, 0x00);
+        assert_eq!(codec.decode(&mut src).unwrap().unwrap().buffer.len(), 300);
+        assert!(codec.decode(&mut src).unwrap().is_none());
+        assert_eq!(src.len(), 1);
+    }
+
+    #[test]
+    fn test_decode_length_too_long() {
+        let mut src = BytesMut::from(&[0x80, 0x80, 0x80, 0x01][..]);
+
+        assert!(matches!(
+            FrameCodec::new().decode(&mut src),
+            Err(FrameError::LengthTooLong)
+        ));
+    }
+
+    #[test]
+    fn test_decode_frame_too_large() {
+        let mut src = frame(0x00, &[0; 64]);
+
+        assert!(matches!(
+            FrameCodec::with_max_frame_size(32).decode(&mut src),
+            Err(FrameError::FrameTooLarge { size: 65, max: 32 })
+        ));
+    }
+
+    #[test]
+    fn test_decode_empty_frame() {
+        let mut src = BytesMut::from(&[0x00][..]);
+
+        assert!(matches!(
+            FrameCodec::new().decode(&mut src),
+            Err(FrameError::EmptyFrame)
+        ));
+    }
+
+    #[test]
+    fn test_decode_invalid_packet_id() {
+        let mut src = BytesMut::from(&[0x01, 0x80][..]);
+
+        assert!(matches!(
+            FrameCodec::new().decode(&mut src),
+            Err(FrameError::InvalidPacketId(_))
+        ));
+    }
+
+    #[test]
+    fn test_compression_round_trip() {
+        let mut codec = compressed_codec(64);
+        let mut src = BytesMut::new();
+
+        for body in [vec![1u8; 8], vec![2u8; 4096]] {
+            codec
+                .encode(
+                    RawPacket {
+                        id: 0x2A,
+                        buffer: BytesMut::from(&body[..]),
+                    },
+                    &mut src,
+                )
+                .unwrap();
+        }
+
+        //The big packet compresses far below its original size
+        assert!(src.len() < 4096);
+
+        let small = codec.decode(&mut src).unwrap().unwrap();
+        assert_eq!((small.id, &small.buffer[..]), (0x2A, &[1u8; 8][..]));
+
+        let big = codec.decode(&mut src).unwrap().unwrap();
+        assert_eq!((big.id, &big.buffer[..]), (0x2A, &[2u8; 4096][..]));
+    }
+
+    #[test]
+    fn test_decode_below_threshold() {
+        let mut src = BytesMut::new();
+        compressed_codec(1)
+            .encode(
+                RawPacket {
+                    id: 0x00,
+                    buffer: BytesMut::from(&[0u8; 16][..]),
+                },
+                &mut src,
+            )
+            .unwrap();
+
+        assert!(matches!(
+            compressed_codec(256).decode(&mut src),
+            Err(FrameError::BelowCompressionThreshold {
+                size: 17,
+                threshold: 256
+            })
+        ));
+    }
+
+    #[test]
+    fn test_decode_decompressed_too_large() {
+        let mut frame = BytesMut::new();
+        frame.write_var_int(&((MAX_DECOMPRESSED_SIZE + 1) as i32)).unwrap();
+        frame.extend_from_slice(&[0x78, 0x9C]);
+
+        let mut src = BytesMut::new();
+        src.write_var_int(&(frame.len() as i32)).unwrap();
+        src.extend_from_slice(&frame);
+
+        assert!(matches!(
+            compressed_codec(256).decode(&mut src),
+            Err(FrameError::DecompressedTooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn test_decode_decompressed_size_mismatch() {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&[0u8; 1024]).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut frame = BytesMut::new();
+        frame.write_var_int(&512).unwrap();
+        frame.extend_from_slice(&compressed);
+
+        let mut src = BytesMut::new();
+        src.write_var_int(&(frame.len() as i32)).unwrap();
+        src.extend_from_slice(&frame);
+
+        assert!(matches!(
+            compressed_codec(256).decode(&mut src),
+            Err(FrameError::DecompressedSizeMismatch {
+                expected: 512,
+                actual: 513
+            })
+        ));
+    }
+}
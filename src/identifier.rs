@@ -0,0 +1,133 @@
+use std::fmt;
+use std::str::FromStr;
+
+use bytes::BytesMut;
+
+use crate::reader::{ProtocolBufferReaderExt, ProtocolError};
+use crate::types::{Decode, Encode};
+use crate::writer::ProtocolBufferWriterExt;
+
+pub const DEFAULT_NAMESPACE: &str = "minecraft";
+pub const MAX_IDENTIFIER_LENGTH: i32 = 32767;
+
+//A namespaced ID like minecraft:stone, called a resource location in vanilla
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Identifier {
+    namespace: String,
+    path: String,
+}
+
+impl Identifier {
+    pub fn new(namespace: &str, path: &str) -> Result<Self, ProtocolError> {
+        let valid_namespace = namespace.bytes().all(|byte| is_valid_char(byte, false));
+        let valid_path = path.bytes().all(|byte| is_valid_char(byte, true));
+
+        if !valid_namespace || !valid_path {
+            return Err(ProtocolError::InvalidIdentifier(format!(
+                "{}:{}",
+                namespace, path
+            )));
+        }
+
+        Ok(Self {
+            namespace: namespace.to_owned(),
+            path: path.to_owned(),
+        })
+    }
+
+    //An identifier in the minecraft namespace, panicking if the path isn't valid.
+    //Blocks were the only ones named in code, and they have BlockKind now.
+    #[allow(dead_code)]
+    pub fn minecraft(path: &str) -> Self {
+        Self::new(DEFAULT_NAMESPACE, path).expect("Vanilla identifiers are always valid")
+    }
+
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+//Lowercase letters, digits, underscores, dashes and dots, plus slashes in the path
+fn is_valid_char(byte: u8, path: bool) -> bool {
+    matches!(byte, b'a'..=b'z' | b'0'..=b'9' | b'_' | b'-' | b'.') || (path && byte == b'/')
+}
+
+//Without a namespace, or with an empty one, the identifier is in the minecraft namespace
+impl FromStr for Identifier {
+    type Err = ProtocolError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let identifier = match value.split_once(':') {
+            Some(("", path)) => Self::new(DEFAULT_NAMESPACE, path),
+            Some((namespace, path)) => Self::new(namespace, path),
+            None => Self::new(DEFAULT_NAMESPACE, value),
+        };
+
+        identifier.map_err(|_| ProtocolError::InvalidIdentifier(value.to_owned()))
+    }
+}
+
+impl fmt::Display for Identifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.namespace, self.path)
+    }
+}
+
+impl Encode for Identifier {
+    fn encode(&self, buffer: &mut BytesMut) -> Result<(), ProtocolError> {
+        buffer.write_string(&self.to_string(), MAX_IDENTIFIER_LENGTH)
+    }
+}
+
+impl Decode for Identifier {
+    fn decode(buffer: &mut BytesMut) -> Result<Self, ProtocolError> {
+        buffer.read_string(MAX_IDENTIFIER_LENGTH)?.parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_parse() {
+        let stone: Identifier = "stone".parse().unwrap();
+        assert_eq!(stone, Identifier::minecraft("stone"));
+        assert_eq!(":stone".parse::<Identifier>().unwrap(), stone);
+
+        let custom: Identifier = "copper:blocks/wire.v2".parse().unwrap();
+        assert_eq!(custom.namespace(), "copper");
+        assert_eq!(custom.path(), "blocks/wire.v2");
+        assert_eq!(custom.to_string(), "copper:blocks/wire.v2");
+    }
+
+    #[test]
+    fn test_invalid() {
+        for value in ["Stone", "minecraft:a:b", "some/ns:stone", "minecraft:hello world"] {
+            assert_eq!(
+                value.parse::<Identifier>(),
+                Err(ProtocolError::InvalidIdentifier(value.to_owned())),
+                "{}",
+                value
+            );
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_round_trip(namespace in "[a-z0-9_.-]{1,16}", path in "[a-z0-9_./-]{1,32}") {
+            let identifier = Identifier::new(&namespace, &path).unwrap();
+
+            let mut buffer = BytesMut::new();
+            identifier.encode(&mut buffer).unwrap();
+
+            prop_assert_eq!(Identifier::decode(&mut buffer).unwrap(), identifier);
+            prop_assert!(buffer.is_empty());
+        }
+    }
+}
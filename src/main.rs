@@ -1,37 +1,80 @@
-use bytes::{Buf, BytesMut};
-use server::Server;
-use std::sync::Arc;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use server::{GlobalConfiguration, Server, ServerContext};
+use std::path::Path;
 use tokio::net::TcpListener;
-use tokio::sync::Mutex as AsyncMutex;
-use tokio::time::{sleep, Duration};
 
+mod auth;
+mod block;
+//Chunk sections and heightmaps wait on the play state sending chunks, only tests build them so far
+#[allow(dead_code)]
+mod chunk;
 mod client;
+//Item data components are only decoded through Slot, which no play packet carries yet
+#[allow(dead_code)]
+mod component;
+mod encryption;
+mod framing;
+mod identifier;
+mod nbt;
 mod packet;
+mod properties;
 mod reader;
+//Region files are read and written once worlds are loaded from disk, only tests open them so far
+#[allow(dead_code)]
+mod region;
+mod registry;
 mod writer;
 mod server;
+mod status;
+mod storage;
+mod text;
+mod tick;
+mod types;
+
+const PROPERTIES_PATH: &str = "server.properties";
 
 #[tokio::main]
 async fn main() {
-    let listener = TcpListener::bind("127.0.0.1:25565")
+    let config = match GlobalConfiguration::load_or_create(Path::new(PROPERTIES_PATH)) {
+        Ok(config) => config,
+        Err(error) => {
+            println!("Failed to load {}: {}", PROPERTIES_PATH, error);
+            return;
+        }
+    };
+    let address = match config.bind_address() {
+        Ok(address) => address,
+        Err(error) => {
+            println!("Failed to resolve server-ip: {}", error);
+            return;
+        }
+    };
+
+    let context = match ServerContext::new(config) {
+        Ok(context) => context,
+        Err(error) => {
+            println!("Failed to load the blocks report: {}", error);
+            return;
+        }
+    };
+
+    let listener = TcpListener::bind(address)
         .await
         .expect("Could not bind to address");
-    let server = Arc::new(AsyncMutex::new(Server::new()));
+    let mut server = Server::with_context(context);
+    server.watch_properties(PROPERTIES_PATH);
+
+    if let Err(error) = server.load_level() {
+        println!("Failed to load the world: {}", error);
+        return;
+    }
 
-    println!("Server started on 127.0.0.1:25565");
+    println!("Server started on {}", address);
 
-    let server_clone = Arc::clone(&server);
+    let spawner = server.spawner();
     tokio::spawn(async move {
         loop {
             match listener.accept().await {
-                Ok((stream, _)) => {
-                    let server = Arc::clone(&server_clone);
-                    tokio::spawn(async move {
-                        let mut locked_server = server.lock().await;
-                        locked_server.add_client(stream);
-                    });
-                }
+                Ok((stream, _)) => spawner.spawn(stream),
                 Err(e) => {
                     println!("Failed to accept connection: {}", e);
                 }
@@ -39,20 +82,5 @@ async fn main() {
         }
     });
 
-    // Spawn a task for processing clients independently.
-    let server_clone = Arc::clone(&server);
-    tokio::spawn(async move {
-        loop {
-            let mut locked_server = server_clone.lock().await;
-            locked_server.process_clients().await;
-            // Add a short delay if needed to avoid tight looping.
-            sleep(Duration::from_millis(10)).await;
-        }
-    });
-
-    // Main loop that isn't blocked by client processing.
-    loop {
-        println!("L");
-        sleep(Duration::from_millis(100)).await; // Adjust timing as needed for your application.
-    }
+    server.run().await;
 }
@@ -0,0 +1,693 @@
+use std::collections::BTreeMap;
+
+use bytes::{Buf, BufMut, BytesMut};
+
+use crate::reader::{ProtocolBufferReaderExt, ProtocolError};
+use crate::types::{Decode, Encode};
+use crate::writer::ProtocolBufferWriterExt;
+
+pub mod de;
+pub mod ser;
+pub mod snbt;
+
+pub use de::from_tag;
+pub use ser::to_tag;
+
+pub const TAG_END: u8 = 0;
+pub const TAG_BYTE: u8 = 1;
+pub const TAG_SHORT: u8 = 2;
+pub const TAG_INT: u8 = 3;
+pub const TAG_LONG: u8 = 4;
+pub const TAG_FLOAT: u8 = 5;
+pub const TAG_DOUBLE: u8 = 6;
+pub const TAG_BYTE_ARRAY: u8 = 7;
+pub const TAG_STRING: u8 = 8;
+pub const TAG_LIST: u8 = 9;
+pub const TAG_COMPOUND: u8 = 10;
+pub const TAG_INT_ARRAY: u8 = 11;
+pub const TAG_LONG_ARRAY: u8 = 12;
+
+//Named binary tags, used for registry data, item components, chunks, block entities and
+//text components. Sorted by name so the same compound always encodes the same way.
+pub type Compound = BTreeMap<String, Tag>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    //Every element has the same type, which is checked when it's written
+    List(Vec<Tag>),
+    Compound(Compound),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl Tag {
+    pub fn id(&self) -> u8 {
+        match self {
+            Tag::Byte(_) => TAG_BYTE,
+            Tag::Short(_) => TAG_SHORT,
+            Tag::Int(_) => TAG_INT,
+            Tag::Long(_) => TAG_LONG,
+            Tag::Float(_) => TAG_FLOAT,
+            Tag::Double(_) => TAG_DOUBLE,
+            Tag::ByteArray(_) => TAG_BYTE_ARRAY,
+            Tag::String(_) => TAG_STRING,
+            Tag::List(_) => TAG_LIST,
+            Tag::Compound(_) => TAG_COMPOUND,
+            Tag::IntArray(_) => TAG_INT_ARRAY,
+            Tag::LongArray(_) => TAG_LONG_ARRAY,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Tag::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Tag]> {
+        match self {
+            Tag::List(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_compound(&self) -> Option<&Compound> {
+        match self {
+            Tag::Compound(compound) => Some(compound),
+            _ => None,
+        }
+    }
+
+    //Any numeric tag as a long, like vanilla's getAsLong
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Tag::Byte(value) => Some(value as i64),
+            Tag::Short(value) => Some(value as i64),
+            Tag::Int(value) => Some(value as i64),
+            Tag::Long(value) => Some(value),
+            Tag::Float(value) => Some(value as i64),
+            Tag::Double(value) => Some(value as i64),
+            _ => None,
+        }
+    }
+}
+
+macro_rules! impl_from {
+    ($($ty:ty => $variant:ident),* $(,)?) => {
+        $(
+            impl From<$ty> for Tag {
+                fn from(value: $ty) -> Self {
+                    Tag::$variant(value)
+                }
+            }
+        )*
+    };
+}
+
+impl_from! {
+    i8 => Byte,
+    i16 => Short,
+    i32 => Int,
+    i64 => Long,
+    f32 => Float,
+    f64 => Double,
+    Vec<i8> => ByteArray,
+    String => String,
+    Vec<Tag> => List,
+    Compound => Compound,
+    Vec<i32> => IntArray,
+    Vec<i64> => LongArray,
+}
+
+impl From<bool> for Tag {
+    fn from(value: bool) -> Self {
+        Tag::Byte(value as i8)
+    }
+}
+
+impl From<&str> for Tag {
+    fn from(value: &str) -> Self {
+        Tag::String(value.to_owned())
+    }
+}
+
+//How much NBT to accept before giving up, since a few bytes of lists can nest deep enough
+//to overflow the stack
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NbtLimits {
+    //How many lists and compounds can be nested inside each other
+    pub max_depth: usize,
+    //How many bytes can be read in total
+    pub max_size: usize,
+}
+
+impl NbtLimits {
+    //What vanilla accepts in packets
+    pub const NETWORK: NbtLimits = NbtLimits {
+        max_depth: 512,
+        max_size: 2 * 1024 * 1024,
+    };
+
+    //Files like chunks can be any size, but not any depth
+    pub const FILE: NbtLimits = NbtLimits {
+        max_depth: 512,
+        max_size: usize::MAX,
+    };
+}
+
+//Reads NBT straight from any buffer. Files name their root tag while packets since 1.20.2
+//leave the name out.
+pub trait NbtBufferReaderExt {
+    fn read_nbt(&mut self, limits: NbtLimits) -> Result<(String, Tag), ProtocolError>;
+    fn read_network_nbt(&mut self, limits: NbtLimits) -> Result<Tag, ProtocolError>;
+}
+
+pub trait NbtBufferWriterExt {
+    //Files always have a compound root, other roots are only written by the tests
+    #[allow(dead_code)]
+    fn write_nbt(&mut self, name: &str, tag: &Tag) -> Result<(), ProtocolError>;
+    //The same as write_nbt with a compound tag, for files whose root is always one
+    fn write_nbt_compound(&mut self, name: &str, compound: &Compound) -> Result<(), ProtocolError>;
+    fn write_network_nbt(&mut self, tag: &Tag) -> Result<(), ProtocolError>;
+}
+
+impl<B: Buf> NbtBufferReaderExt for B {
+    fn read_nbt(&mut self, limits: NbtLimits) -> Result<(String, Tag), ProtocolError> {
+        let mut reader = TagReader::new(self, limits);
+        let id = reader.read_root_id()?;
+        let name = reader.read_string()?;
+        let tag = reader.read_payload(id, 0)?;
+        Ok((name, tag))
+    }
+
+    fn read_network_nbt(&mut self, limits: NbtLimits) -> Result<Tag, ProtocolError> {
+        let mut reader = TagReader::new(self, limits);
+        let id = reader.read_root_id()?;
+        reader.read_payload(id, 0)
+    }
+}
+
+impl<B: BufMut> NbtBufferWriterExt for B {
+    fn write_nbt(&mut self, name: &str, tag: &Tag) -> Result<(), ProtocolError> {
+        self.write_u8(&tag.id())?;
+        write_nbt_string(self, name)?;
+        write_payload(self, tag)
+    }
+
+    fn write_nbt_compound(&mut self, name: &str, compound: &Compound) -> Result<(), ProtocolError> {
+        self.write_u8(&TAG_COMPOUND)?;
+        write_nbt_string(self, name)?;
+        write_compound(self, compound)
+    }
+
+    fn write_network_nbt(&mut self, tag: &Tag) -> Result<(), ProtocolError> {
+        self.write_u8(&tag.id())?;
+        write_payload(self, tag)
+    }
+}
+
+//Network NBT with the limits vanilla uses for packets
+impl Encode for Tag {
+    fn encode(&self, buffer: &mut BytesMut) -> Result<(), ProtocolError> {
+        buffer.write_network_nbt(self)
+    }
+}
+
+impl Decode for Tag {
+    fn decode(buffer: &mut BytesMut) -> Result<Self, ProtocolError> {
+        buffer.read_network_nbt(NbtLimits::NETWORK)
+    }
+}
+
+struct TagReader<'a, B> {
+    buffer: &'a mut B,
+    limits: NbtLimits,
+    start: usize,
+}
+
+impl<'a, B: Buf> TagReader<'a, B> {
+    fn new(buffer: &'a mut B, limits: NbtLimits) -> Self {
+        let start = buffer.remaining();
+        Self {
+            buffer,
+            limits,
+            start,
+        }
+    }
+
+    fn read_root_id(&mut self) -> Result<u8, ProtocolError> {
+        match self.buffer.read_u8()? {
+            TAG_END => Err(ProtocolError::InvalidData(
+                "NBT root can't be an end tag".to_owned(),
+            )),
+            id => Ok(id),
+        }
+    }
+
+    fn check_depth(&self, depth: usize) -> Result<(), ProtocolError> {
+        if depth > self.limits.max_depth {
+            return Err(ProtocolError::NbtTooDeep {
+                max: self.limits.max_depth,
+            });
+        }
+
+        Ok(())
+    }
+
+    //Checks the bytes about to be read against what's left of the size limit,
+    //so a single huge array or string can't get past it
+    fn charge(&self, size: usize) -> Result<(), ProtocolError> {
+        let read = self.start - self.buffer.remaining();
+        if read.saturating_add(size) > self.limits.max_size {
+            return Err(ProtocolError::NbtTooLarge {
+                max: self.limits.max_size,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn read_string(&mut self) -> Result<String, ProtocolError> {
+        let length = self.buffer.read_u16()? as usize;
+        self.charge(length)?;
+
+        decode_modified_utf8(&self.buffer.copy_buffer_to_bytes(length)?)
+    }
+
+    fn read_length(&mut self, element_size: usize) -> Result<usize, ProtocolError> {
+        let length = self.buffer.read_i32()?;
+        if length < 0 {
+            return Err(ProtocolError::InvalidLength {
+                length: length as i64,
+                max: i32::MAX as usize,
+            });
+        }
+
+        let needed = (length as usize).saturating_mul(element_size);
+        self.charge(needed)?;
+        if needed > self.buffer.remaining() {
+            return Err(ProtocolError::UnexpectedEof {
+                needed,
+                available: self.buffer.remaining(),
+            });
+        }
+
+        Ok(length as usize)
+    }
+
+    fn read_payload(&mut self, id: u8, depth: usize) -> Result<Tag, ProtocolError> {
+        self.check_depth(depth)?;
+        self.charge(min_payload_size(id)?)?;
+
+        let tag = match id {
+            TAG_BYTE => Tag::Byte(self.buffer.read_i8()?),
+            TAG_SHORT => Tag::Short(self.buffer.read_i16()?),
+            TAG_INT => Tag::Int(self.buffer.read_i32()?),
+            TAG_LONG => Tag::Long(self.buffer.read_i64()?),
+            TAG_FLOAT => Tag::Float(self.buffer.read_f32()?),
+            TAG_DOUBLE => Tag::Double(self.buffer.read_f64()?),
+            TAG_BYTE_ARRAY => {
+                let length = self.read_length(1)?;
+                Tag::ByteArray((0..length).map(|_| self.buffer.get_i8()).collect())
+            }
+            TAG_STRING => Tag::String(self.read_string()?),
+            TAG_LIST => {
+                let element = self.buffer.read_u8()?;
+                let length = self.read_length(min_payload_size(element)?)?;
+                if element == TAG_END && length > 0 {
+                    return Err(ProtocolError::InvalidData(
+                        "NBT list of end tags isn't empty".to_owned(),
+                    ));
+                }
+
+                let mut values = Vec::with_capacity(length);
+                for _ in 0..length {
+                    values.push(self.read_payload(element, depth + 1)?);
+                }
+                Tag::List(values)
+            }
+            TAG_COMPOUND => {
+                let mut compound = Compound::new();
+                loop {
+                    let id = self.buffer.read_u8()?;
+                    if id == TAG_END {
+                        break;
+                    }
+
+                    let name = self.read_string()?;
+                    let value = self.read_payload(id, depth + 1)?;
+                    compound.insert(name, value);
+                }
+                Tag::Compound(compound)
+            }
+            TAG_INT_ARRAY => {
+                let length = self.read_length(4)?;
+                Tag::IntArray((0..length).map(|_| self.buffer.get_i32()).collect())
+            }
+            TAG_LONG_ARRAY => {
+                let length = self.read_length(8)?;
+                Tag::LongArray((0..length).map(|_| self.buffer.get_i64()).collect())
+            }
+            id => {
+                return Err(ProtocolError::InvalidEnum {
+                    name: "NBT tag",
+                    value: id as i32,
+                })
+            }
+        };
+
+        Ok(tag)
+    }
+}
+
+//The fewest bytes a payload of this tag can take up
+fn min_payload_size(id: u8) -> Result<usize, ProtocolError> {
+    match id {
+        TAG_END => Ok(0),
+        TAG_BYTE | TAG_COMPOUND => Ok(1),
+        TAG_SHORT | TAG_STRING => Ok(2),
+        TAG_INT | TAG_FLOAT | TAG_BYTE_ARRAY | TAG_INT_ARRAY | TAG_LONG_ARRAY => Ok(4),
+        TAG_LIST => Ok(5),
+        TAG_LONG | TAG_DOUBLE => Ok(8),
+        id => Err(ProtocolError::InvalidEnum {
+            name: "NBT tag",
+            value: id as i32,
+        }),
+    }
+}
+
+fn write_length(buffer: &mut impl BufMut, length: usize) -> Result<(), ProtocolError> {
+    let length = i32::try_from(length).map_err(|_| ProtocolError::InvalidLength {
+        length: length as i64,
+        max: i32::MAX as usize,
+    })?;
+
+    buffer.write_i32(&length)
+}
+
+fn write_payload(buffer: &mut impl BufMut, tag: &Tag) -> Result<(), ProtocolError> {
+    match tag {
+        Tag::Byte(value) => buffer.write_i8(value),
+        Tag::Short(value) => buffer.write_i16(value),
+        Tag::Int(value) => buffer.write_i32(value),
+        Tag::Long(value) => buffer.write_i64(value),
+        Tag::Float(value) => buffer.write_f32(value),
+        Tag::Double(value) => buffer.write_f64(value),
+        Tag::ByteArray(values) => {
+            write_length(buffer, values.len())?;
+            values.iter().try_for_each(|value| buffer.write_i8(value))
+        }
+        Tag::String(value) => write_nbt_string(buffer, value),
+        Tag::List(values) => {
+            let element = values.first().map_or(TAG_END, Tag::id);
+            if let Some(value) = values.iter().find(|value| value.id() != element) {
+                return Err(ProtocolError::InvalidData(format!(
+                    "NBT list of tag {} can't hold tag {}",
+                    element,
+                    value.id()
+                )));
+            }
+
+            buffer.write_u8(&element)?;
+            write_length(buffer, values.len())?;
+            values
+                .iter()
+                .try_for_each(|value| write_payload(buffer, value))
+        }
+        Tag::Compound(compound) => write_compound(buffer, compound),
+        Tag::IntArray(values) => {
+            write_length(buffer, values.len())?;
+            values.iter().try_for_each(|value| buffer.write_i32(value))
+        }
+        Tag::LongArray(values) => {
+            write_length(buffer, values.len())?;
+            values.iter().try_for_each(|value| buffer.write_i64(value))
+        }
+    }
+}
+
+fn write_compound(buffer: &mut impl BufMut, compound: &Compound) -> Result<(), ProtocolError> {
+    for (name, value) in compound {
+        buffer.write_u8(&value.id())?;
+        write_nbt_string(buffer, name)?;
+        write_payload(buffer, value)?;
+    }
+    buffer.write_u8(&TAG_END)
+}
+
+//NBT strings are Java's modified UTF-8, prefixed with their length in bytes
+fn write_nbt_string(buffer: &mut impl BufMut, value: &str) -> Result<(), ProtocolError> {
+    let mut bytes = Vec::with_capacity(value.len());
+
+    for unit in value.encode_utf16() {
+        match unit {
+            0x0001..=0x007F => bytes.push(unit as u8),
+            0x0000 | 0x0080..=0x07FF => {
+                bytes.push(0xC0 | (unit >> 6) as u8);
+                bytes.push(0x80 | (unit & 0x3F) as u8);
+            }
+            _ => {
+                bytes.push(0xE0 | (unit >> 12) as u8);
+                bytes.push(0x80 | ((unit >> 6) & 0x3F) as u8);
+                bytes.push(0x80 | (unit & 0x3F) as u8);
+            }
+        }
+    }
+
+    let length = u16::try_from(bytes.len()).map_err(|_| ProtocolError::StringTooLong {
+        max: u16::MAX as usize,
+        actual: bytes.len(),
+    })?;
+
+    buffer.write_u16(&length)?;
+    if buffer.remaining_mut() < bytes.len() {
+        return Err(ProtocolError::BufferFull {
+            needed: bytes.len(),
+            available: buffer.remaining_mut(),
+        });
+    }
+    buffer.put_slice(&bytes);
+    Ok(())
+}
+
+fn decode_modified_utf8(bytes: &[u8]) -> Result<String, ProtocolError> {
+    let invalid = || ProtocolError::InvalidData("Invalid modified UTF-8 string".to_owned());
+
+    let mut units = Vec::with_capacity(bytes.len());
+    let mut bytes = bytes.iter().copied();
+
+    while let Some(first) = bytes.next() {
+        let mut continuation = || match bytes.next() {
+            Some(byte) if byte & 0xC0 == 0x80 => Ok((byte & 0x3F) as u16),
+            _ => Err(invalid()),
+        };
+
+        let unit = match first {
+            0x01..=0x7F => first as u16,
+            0xC0..=0xDF => ((first & 0x1F) as u16) << 6 | continuation()?,
+            0xE0..=0xEF => ((first & 0x0F) as u16) << 12 | continuation()? << 6 | continuation()?,
+            _ => return Err(invalid()),
+        };
+        units.push(unit);
+    }
+
+    String::from_utf16(&units).map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    //The hello world example from the original NBT specification
+    const HELLO_WORLD: &[u8] = &[
+        0x0A, 0x00, 0x0B, b'h', b'e', b'l', b'l', b'o', b' ', b'w', b'o', b'r', b'l', b'd', 0x08,
+        0x00, 0x04, b'n', b'a', b'm', b'e', 0x00, 0x09, b'B', b'a', b'n', b'a', b'n', b'r', b'a',
+        b'm', b'a', 0x00,
+    ];
+
+    fn hello_world() -> Tag {
+        Tag::Compound(Compound::from([(
+            "name".to_owned(),
+            Tag::from("Bananrama"),
+        )]))
+    }
+
+    #[test]
+    fn test_named_root() {
+        let mut buffer = HELLO_WORLD;
+        let (name, tag) = buffer.read_nbt(NbtLimits::FILE).unwrap();
+
+        assert_eq!(name, "hello world");
+        assert_eq!(tag, hello_world());
+        assert!(buffer.is_empty());
+
+        let mut written = BytesMut::new();
+        written.write_nbt("hello world", &tag).unwrap();
+        assert_eq!(&written[..], HELLO_WORLD);
+    }
+
+    #[test]
+    fn test_nameless_root() {
+        let mut buffer = BytesMut::new();
+        buffer.write_network_nbt(&hello_world()).unwrap();
+
+        assert_eq!(
+            &buffer[..],
+            [&HELLO_WORLD[..1], &HELLO_WORLD[14..]].concat()
+        );
+        assert_eq!(Tag::decode(&mut buffer).unwrap(), hello_world());
+    }
+
+    #[test]
+    fn test_mixed_list() {
+        let list = Tag::List(vec![Tag::Int(1), Tag::from("two")]);
+
+        assert!(BytesMut::new().write_network_nbt(&list).is_err());
+    }
+
+    #[test]
+    fn test_too_deep() {
+        let mut tag = Tag::List(Vec::new());
+        for _ in 0..NbtLimits::NETWORK.max_depth {
+            tag = Tag::List(vec![tag]);
+        }
+
+        let mut buffer = BytesMut::new();
+        buffer.write_network_nbt(&tag).unwrap();
+        assert!(buffer.clone().read_network_nbt(NbtLimits::NETWORK).is_ok());
+
+        let mut buffer = BytesMut::new();
+        buffer.write_network_nbt(&Tag::List(vec![tag])).unwrap();
+        assert_eq!(
+            buffer.read_network_nbt(NbtLimits::NETWORK),
+            Err(ProtocolError::NbtTooDeep { max: 512 })
+        );
+    }
+
+    #[test]
+    fn test_too_large() {
+        let limits = NbtLimits {
+            max_depth: 512,
+            max_size: 16,
+        };
+
+        let mut buffer = BytesMut::new();
+        buffer
+            .write_network_nbt(&Tag::List(vec![Tag::Long(0); 4]))
+            .unwrap();
+        assert_eq!(
+            buffer.read_network_nbt(limits),
+            Err(ProtocolError::NbtTooLarge { max: 16 })
+        );
+    }
+
+    #[test]
+    fn test_oversized_array() {
+        let limits = NbtLimits {
+            max_depth: 512,
+            max_size: 16,
+        };
+
+        //A single array at the root is checked before it's read, not only before the next tag
+        let mut buffer = BytesMut::new();
+        buffer.write_network_nbt(&Tag::LongArray(vec![0; 4])).unwrap();
+        assert_eq!(
+            buffer.read_network_nbt(limits),
+            Err(ProtocolError::NbtTooLarge { max: 16 })
+        );
+
+        //So is a string that's the last entry of a compound
+        let compound = Compound::from([("name".to_owned(), Tag::from("0123456789"))]);
+        let mut buffer = BytesMut::new();
+        buffer.write_network_nbt(&Tag::Compound(compound)).unwrap();
+        assert_eq!(
+            buffer.read_network_nbt(limits),
+            Err(ProtocolError::NbtTooLarge { max: 16 })
+        );
+    }
+
+    #[test]
+    fn test_huge_length() {
+        //An int array claiming to hold i32::MAX ints, which mustn't be allocated up front
+        let data: &[u8] = &[TAG_INT_ARRAY, 0x7F, 0xFF, 0xFF, 0xFF, 0, 0, 0, 1];
+
+        assert_eq!(
+            (&data[..]).read_network_nbt(NbtLimits::NETWORK),
+            Err(ProtocolError::NbtTooLarge { max: 2 * 1024 * 1024 })
+        );
+        assert!(matches!(
+            (&data[..]).read_network_nbt(NbtLimits::FILE),
+            Err(ProtocolError::UnexpectedEof { .. })
+        ));
+    }
+
+    #[test]
+    fn test_modified_utf8() {
+        let mut buffer = BytesMut::new();
+        write_nbt_string(&mut buffer, "\0\u{1F600}").unwrap();
+
+        //Null is two bytes and the emoji is a surrogate pair of three bytes each
+        assert_eq!(
+            &buffer[..],
+            &[0, 8, 0xC0, 0x80, 0xED, 0xA0, 0xBD, 0xED, 0xB8, 0x80]
+        );
+        assert_eq!(decode_modified_utf8(&buffer[2..]).unwrap(), "\0\u{1F600}");
+    }
+
+    //Any tag, except for NaN floats which never equal themselves
+    pub(crate) fn tag() -> impl Strategy<Value = Tag> {
+        let leaf = prop_oneof![
+            any::<i8>().prop_map(Tag::Byte),
+            any::<i16>().prop_map(Tag::Short),
+            any::<i32>().prop_map(Tag::Int),
+            any::<i64>().prop_map(Tag::Long),
+            prop::num::f32::NORMAL.prop_map(Tag::Float),
+            prop::num::f64::NORMAL.prop_map(Tag::Double),
+            any::<Vec<i8>>().prop_map(Tag::ByteArray),
+            any::<String>().prop_map(Tag::String),
+            any::<Vec<i32>>().prop_map(Tag::IntArray),
+            any::<Vec<i64>>().prop_map(Tag::LongArray),
+        ];
+
+        leaf.prop_recursive(4, 64, 8, |inner| {
+            prop_oneof![
+                //Lists can only hold one type of tag
+                prop::collection::vec(inner.clone(), 0..8).prop_map(|values| {
+                    let id = values.first().map(Tag::id);
+                    Tag::List(
+                        values
+                            .into_iter()
+                            .filter(|value| Some(value.id()) == id)
+                            .collect(),
+                    )
+                }),
+                prop::collection::btree_map(any::<String>(), inner, 0..8).prop_map(Tag::Compound),
+            ]
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn test_round_trip(name in any::<String>(), tag in tag()) {
+            let mut buffer = BytesMut::new();
+            buffer.write_nbt(&name, &tag).unwrap();
+
+            prop_assert_eq!(buffer.read_nbt(NbtLimits::FILE).unwrap(), (name, tag.clone()));
+            prop_assert!(buffer.is_empty());
+
+            buffer.write_network_nbt(&tag).unwrap();
+            prop_assert_eq!(buffer.read_network_nbt(NbtLimits::NETWORK).unwrap(), tag);
+            prop_assert!(buffer.is_empty());
+        }
+    }
+}
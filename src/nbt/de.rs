@@ -0,0 +1,391 @@
+use std::fmt::{self, Display};
+use std::iter;
+
+use serde::de::value::{MapDeserializer, SeqDeserializer, StringDeserializer};
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, Deserializer, EnumAccess, IntoDeserializer, MapAccess,
+    SeqAccess, VariantAccess, Visitor,
+};
+use serde::{forward_to_deserialize_any, Deserialize};
+
+use crate::nbt::ser::{BYTE_ARRAY_TOKEN, INT_ARRAY_TOKEN, LONG_ARRAY_TOKEN};
+use crate::nbt::{Compound, Tag};
+use crate::reader::ProtocolError;
+
+impl de::Error for ProtocolError {
+    fn custom<T: Display>(message: T) -> Self {
+        ProtocolError::InvalidData(message.to_string())
+    }
+}
+
+//Reads anything deserializable from a tag, e.g. a struct from a compound.
+//Bytes can be read as bools, and array tags as sequences.
+pub fn from_tag<T: DeserializeOwned>(tag: Tag) -> Result<T, ProtocolError> {
+    T::deserialize(TagDeserializer(tag))
+}
+
+pub struct TagDeserializer(Tag);
+
+impl<'de> IntoDeserializer<'de, ProtocolError> for Tag {
+    type Deserializer = TagDeserializer;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        TagDeserializer(self)
+    }
+}
+
+impl<'de> Deserializer<'de> for TagDeserializer {
+    type Error = ProtocolError;
+
+    //Array tags show up as a map with a single token key, so deserializing a Tag can tell them
+    //apart from lists
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Tag::Byte(value) => visitor.visit_i8(value),
+            Tag::Short(value) => visitor.visit_i16(value),
+            Tag::Int(value) => visitor.visit_i32(value),
+            Tag::Long(value) => visitor.visit_i64(value),
+            Tag::Float(value) => visitor.visit_f32(value),
+            Tag::Double(value) => visitor.visit_f64(value),
+            Tag::ByteArray(values) => {
+                visitor.visit_map(MapDeserializer::new(iter::once((BYTE_ARRAY_TOKEN, values))))
+            }
+            Tag::String(value) => visitor.visit_string(value),
+            Tag::List(values) => visitor.visit_seq(SeqDeserializer::new(values.into_iter())),
+            Tag::Compound(compound) => {
+                visitor.visit_map(MapDeserializer::new(compound.into_iter()))
+            }
+            Tag::IntArray(values) => {
+                visitor.visit_map(MapDeserializer::new(iter::once((INT_ARRAY_TOKEN, values))))
+            }
+            Tag::LongArray(values) => {
+                visitor.visit_map(MapDeserializer::new(iter::once((LONG_ARRAY_TOKEN, values))))
+            }
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Tag::Byte(value) => visitor.visit_bool(value != 0),
+            tag => TagDeserializer(tag).deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Tag::ByteArray(values) => {
+                visitor.visit_byte_buf(values.into_iter().map(|value| value as u8).collect())
+            }
+            tag => TagDeserializer(tag).deserialize_seq(visitor),
+        }
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_bytes(visitor)
+    }
+
+    //Missing fields are already None, so anything that's there is Some
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Tag::ByteArray(values) => visitor.visit_seq(SeqDeserializer::new(values.into_iter())),
+            Tag::IntArray(values) => visitor.visit_seq(SeqDeserializer::new(values.into_iter())),
+            Tag::LongArray(values) => visitor.visit_seq(SeqDeserializer::new(values.into_iter())),
+            tag => TagDeserializer(tag).deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _length: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _length: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    //A string for unit variants, or a compound with the variant as its only key
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Tag::String(variant) => visitor.visit_enum(variant.into_deserializer()),
+            Tag::Compound(compound) if compound.len() == 1 => {
+                let (variant, value) = compound.into_iter().next().expect("Checked length");
+                visitor.visit_enum(VariantDeserializer { variant, value })
+            }
+            tag => Err(ProtocolError::InvalidData(format!(
+                "Expected an enum but found tag {}",
+                tag.id()
+            ))),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        map struct identifier ignored_any
+    }
+}
+
+struct VariantDeserializer {
+    variant: String,
+    value: Tag,
+}
+
+impl<'de> EnumAccess<'de> for VariantDeserializer {
+    type Error = ProtocolError;
+    type Variant = TagDeserializer;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self::Variant), Self::Error> {
+        let variant: StringDeserializer<ProtocolError> = self.variant.into_deserializer();
+        Ok((seed.deserialize(variant)?, TagDeserializer(self.value)))
+    }
+}
+
+impl<'de> VariantAccess<'de> for TagDeserializer {
+    type Error = ProtocolError;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, Self::Error> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _length: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_any(visitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for Tag {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(TagVisitor)
+    }
+}
+
+struct TagVisitor;
+
+impl<'de> Visitor<'de> for TagVisitor {
+    type Value = Tag;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an NBT tag")
+    }
+
+    fn visit_bool<E: de::Error>(self, value: bool) -> Result<Tag, E> {
+        Ok(Tag::from(value))
+    }
+
+    fn visit_i8<E: de::Error>(self, value: i8) -> Result<Tag, E> {
+        Ok(Tag::Byte(value))
+    }
+
+    fn visit_i16<E: de::Error>(self, value: i16) -> Result<Tag, E> {
+        Ok(Tag::Short(value))
+    }
+
+    fn visit_i32<E: de::Error>(self, value: i32) -> Result<Tag, E> {
+        Ok(Tag::Int(value))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Tag, E> {
+        Ok(Tag::Long(value))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Tag, E> {
+        i64::try_from(value)
+            .map(Tag::Long)
+            .map_err(|_| E::custom("NBT can't represent u64 above i64::MAX"))
+    }
+
+    fn visit_f32<E: de::Error>(self, value: f32) -> Result<Tag, E> {
+        Ok(Tag::Float(value))
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<Tag, E> {
+        Ok(Tag::Double(value))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Tag, E> {
+        Ok(Tag::from(value))
+    }
+
+    fn visit_string<E: de::Error>(self, value: String) -> Result<Tag, E> {
+        Ok(Tag::String(value))
+    }
+
+    fn visit_bytes<E: de::Error>(self, value: &[u8]) -> Result<Tag, E> {
+        Ok(Tag::ByteArray(
+            value.iter().map(|&byte| byte as i8).collect(),
+        ))
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Tag, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, deserializer: D) -> Result<Tag, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Tag, A::Error> {
+        let mut values = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(value) = seq.next_element()? {
+            values.push(value);
+        }
+
+        Ok(Tag::List(values))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Tag, A::Error> {
+        let mut compound = Compound::new();
+
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                BYTE_ARRAY_TOKEN => return Ok(Tag::ByteArray(map.next_value()?)),
+                INT_ARRAY_TOKEN => return Ok(Tag::IntArray(map.next_value()?)),
+                LONG_ARRAY_TOKEN => return Ok(Tag::LongArray(map.next_value()?)),
+                _ => {
+                    let value = map.next_value()?;
+                    compound.insert(key, value);
+                }
+            }
+        }
+
+        Ok(Tag::Compound(compound))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nbt::ser::long_array;
+    use crate::nbt::tests::tag;
+    use crate::nbt::to_tag;
+    use proptest::prelude::*;
+    use serde::Serialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    enum Shape {
+        Cube,
+        Sphere { radius: f64 },
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Section {
+        y: i8,
+        visible: bool,
+        name: Option<String>,
+        #[serde(serialize_with = "long_array")]
+        data: Vec<i64>,
+        palette: Vec<String>,
+        shape: Shape,
+        shapes: Vec<Shape>,
+        extra: Option<Tag>,
+    }
+
+    fn section() -> Section {
+        Section {
+            y: -4,
+            visible: true,
+            name: None,
+            data: vec![1, 2, 3],
+            palette: vec!["minecraft:air".to_owned(), "minecraft:stone".to_owned()],
+            shape: Shape::Cube,
+            shapes: vec![Shape::Sphere { radius: 1.5 }],
+            extra: Some(Tag::IntArray(vec![7])),
+        }
+    }
+
+    #[test]
+    fn test_struct_to_compound() {
+        let tag = to_tag(&section()).unwrap();
+        let compound = tag.as_compound().unwrap();
+
+        assert_eq!(compound["y"], Tag::Byte(-4));
+        assert_eq!(compound["visible"], Tag::Byte(1));
+        assert!(!compound.contains_key("name"));
+        assert_eq!(compound["data"], Tag::LongArray(vec![1, 2, 3]));
+        assert_eq!(compound["palette"].as_list().unwrap().len(), 2);
+        assert_eq!(compound["shape"], Tag::from("cube"));
+        assert_eq!(compound["shapes"].to_string(), "[{sphere:{radius:1.5d}}]");
+        assert_eq!(compound["extra"], Tag::IntArray(vec![7]));
+
+        assert_eq!(from_tag::<Section>(tag).unwrap(), section());
+    }
+
+    #[test]
+    fn test_mixed_list() {
+        assert!(to_tag(&(1, "two")).is_err());
+        assert!(to_tag(&None::<i32>).is_err());
+    }
+
+    #[test]
+    fn test_wrong_type() {
+        let tag = Tag::Compound(Compound::from([("y".to_owned(), Tag::from("up"))]));
+
+        assert!(from_tag::<Section>(tag).is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn test_tag_round_trip(tag in tag()) {
+            prop_assert_eq!(to_tag(&tag).unwrap(), tag.clone());
+            prop_assert_eq!(from_tag::<Tag>(tag.clone()).unwrap(), tag);
+        }
+    }
+}
@@ -0,0 +1,443 @@
+use std::fmt::Display;
+
+use serde::ser::{self, Serialize, Serializer};
+
+use crate::nbt::{Compound, Tag};
+use crate::reader::ProtocolError;
+
+//Newtype struct names that turn a sequence into an array tag instead of a list,
+//see byte_array, int_array and long_array
+pub(crate) const BYTE_ARRAY_TOKEN: &str = "__nbt_byte_array";
+pub(crate) const INT_ARRAY_TOKEN: &str = "__nbt_int_array";
+pub(crate) const LONG_ARRAY_TOKEN: &str = "__nbt_long_array";
+
+impl ser::Error for ProtocolError {
+    fn custom<T: Display>(message: T) -> Self {
+        ProtocolError::InvalidData(message.to_string())
+    }
+}
+
+//Turns anything serializable into a tag, e.g. a struct into a compound.
+//Fields that are None are left out like vanilla does.
+pub fn to_tag<T: Serialize + ?Sized>(value: &T) -> Result<Tag, ProtocolError> {
+    value
+        .serialize(TagSerializer)?
+        .ok_or_else(|| ProtocolError::InvalidData("NBT has no null value".to_owned()))
+}
+
+//For #[serde(serialize_with)] on fields that should be array tags rather than lists
+pub fn byte_array<S: Serializer>(values: &[i8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_newtype_struct(BYTE_ARRAY_TOKEN, values)
+}
+
+pub fn int_array<S: Serializer>(values: &[i32], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_newtype_struct(INT_ARRAY_TOKEN, values)
+}
+
+pub fn long_array<S: Serializer>(values: &[i64], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_newtype_struct(LONG_ARRAY_TOKEN, values)
+}
+
+impl Serialize for Tag {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Tag::Byte(value) => serializer.serialize_i8(*value),
+            Tag::Short(value) => serializer.serialize_i16(*value),
+            Tag::Int(value) => serializer.serialize_i32(*value),
+            Tag::Long(value) => serializer.serialize_i64(*value),
+            Tag::Float(value) => serializer.serialize_f32(*value),
+            Tag::Double(value) => serializer.serialize_f64(*value),
+            Tag::ByteArray(values) => byte_array(values, serializer),
+            Tag::String(value) => serializer.serialize_str(value),
+            Tag::List(values) => serializer.collect_seq(values),
+            Tag::Compound(compound) => serializer.collect_map(compound),
+            Tag::IntArray(values) => int_array(values, serializer),
+            Tag::LongArray(values) => long_array(values, serializer),
+        }
+    }
+}
+
+fn unsupported(what: &str) -> ProtocolError {
+    ProtocolError::InvalidData(format!("NBT can't represent {}", what))
+}
+
+//Serializes to a tag, or to nothing for None and () so compounds can leave them out
+struct TagSerializer;
+
+impl Serializer for TagSerializer {
+    type Ok = Option<Tag>;
+    type Error = ProtocolError;
+    type SerializeSeq = ListSerializer;
+    type SerializeTuple = ListSerializer;
+    type SerializeTupleStruct = ListSerializer;
+    type SerializeTupleVariant = VariantSerializer<ListSerializer>;
+    type SerializeMap = CompoundSerializer;
+    type SerializeStruct = CompoundSerializer;
+    type SerializeStructVariant = VariantSerializer<CompoundSerializer>;
+
+    fn serialize_bool(self, value: bool) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Tag::from(value)))
+    }
+
+    fn serialize_i8(self, value: i8) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Tag::Byte(value)))
+    }
+
+    fn serialize_i16(self, value: i16) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Tag::Short(value)))
+    }
+
+    fn serialize_i32(self, value: i32) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Tag::Int(value)))
+    }
+
+    fn serialize_i64(self, value: i64) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Tag::Long(value)))
+    }
+
+    //NBT only has signed numbers, so unsigned ones take up the next larger tag
+    fn serialize_u8(self, value: u8) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Tag::Short(value as i16)))
+    }
+
+    fn serialize_u16(self, value: u16) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Tag::Int(value as i32)))
+    }
+
+    fn serialize_u32(self, value: u32) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Tag::Long(value as i64)))
+    }
+
+    fn serialize_u64(self, value: u64) -> Result<Self::Ok, Self::Error> {
+        let value = i64::try_from(value).map_err(|_| unsupported("u64 above i64::MAX"))?;
+        Ok(Some(Tag::Long(value)))
+    }
+
+    fn serialize_f32(self, value: f32) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Tag::Float(value)))
+    }
+
+    fn serialize_f64(self, value: f64) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Tag::Double(value)))
+    }
+
+    fn serialize_char(self, value: char) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Tag::String(value.to_string())))
+    }
+
+    fn serialize_str(self, value: &str) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Tag::from(value)))
+    }
+
+    fn serialize_bytes(self, value: &[u8]) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Tag::ByteArray(
+            value.iter().map(|&byte| byte as i8).collect(),
+        )))
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        Ok(None)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Self::Ok, Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+        Ok(None)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Tag::Compound(Compound::new())))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Tag::from(variant)))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        let tag = value.serialize(self)?;
+        let values = match (name, tag) {
+            (BYTE_ARRAY_TOKEN | INT_ARRAY_TOKEN | LONG_ARRAY_TOKEN, Some(Tag::List(values))) => {
+                values
+            }
+            (_, tag) => return Ok(tag),
+        };
+
+        let array = match name {
+            BYTE_ARRAY_TOKEN => values
+                .into_iter()
+                .map(|value| match value {
+                    Tag::Byte(value) => Ok(value),
+                    _ => Err(unsupported("a byte array of anything but bytes")),
+                })
+                .collect::<Result<_, _>>()
+                .map(Tag::ByteArray),
+            INT_ARRAY_TOKEN => values
+                .into_iter()
+                .map(|value| match value {
+                    Tag::Int(value) => Ok(value),
+                    _ => Err(unsupported("an int array of anything but ints")),
+                })
+                .collect::<Result<_, _>>()
+                .map(Tag::IntArray),
+            _ => values
+                .into_iter()
+                .map(|value| match value {
+                    Tag::Long(value) => Ok(value),
+                    _ => Err(unsupported("a long array of anything but longs")),
+                })
+                .collect::<Result<_, _>>()
+                .map(Tag::LongArray),
+        };
+
+        array.map(Some)
+    }
+
+    //Like serde's default for externally tagged enums, a compound with the variant as its only key
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        let mut compound = Compound::new();
+        compound.insert(variant.to_owned(), to_tag(value)?);
+        Ok(Some(Tag::Compound(compound)))
+    }
+
+    fn serialize_seq(self, length: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Ok(ListSerializer {
+            values: Vec::with_capacity(length.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_tuple(self, length: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        self.serialize_seq(Some(length))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        length: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        self.serialize_seq(Some(length))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        length: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        Ok(VariantSerializer {
+            variant,
+            inner: self.serialize_seq(Some(length))?,
+        })
+    }
+
+    fn serialize_map(self, _length: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Ok(CompoundSerializer {
+            compound: Compound::new(),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        length: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        self.serialize_map(Some(length))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        length: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        Ok(VariantSerializer {
+            variant,
+            inner: self.serialize_map(Some(length))?,
+        })
+    }
+}
+
+struct ListSerializer {
+    values: Vec<Tag>,
+}
+
+impl ListSerializer {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ProtocolError> {
+        let value = to_tag(value)?;
+        if let Some(first) = self.values.first() {
+            if first.id() != value.id() {
+                return Err(unsupported("a list holding different types"));
+            }
+        }
+
+        self.values.push(value);
+        Ok(())
+    }
+}
+
+impl ser::SerializeSeq for ListSerializer {
+    type Ok = Option<Tag>;
+    type Error = ProtocolError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Tag::List(self.values)))
+    }
+}
+
+impl ser::SerializeTuple for ListSerializer {
+    type Ok = Option<Tag>;
+    type Error = ProtocolError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for ListSerializer {
+    type Ok = Option<Tag>;
+    type Error = ProtocolError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+struct CompoundSerializer {
+    compound: Compound,
+    //The key of the next value when keys and values are serialized separately
+    key: Option<String>,
+}
+
+impl CompoundSerializer {
+    fn insert<T: Serialize + ?Sized>(
+        &mut self,
+        key: String,
+        value: &T,
+    ) -> Result<(), ProtocolError> {
+        if let Some(value) = value.serialize(TagSerializer)? {
+            self.compound.insert(key, value);
+        }
+
+        Ok(())
+    }
+}
+
+impl ser::SerializeMap for CompoundSerializer {
+    type Ok = Option<Tag>;
+    type Error = ProtocolError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Self::Error> {
+        match to_tag(key)? {
+            Tag::String(key) => {
+                self.key = Some(key);
+                Ok(())
+            }
+            _ => Err(unsupported("compound keys that aren't strings")),
+        }
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        let key = self
+            .key
+            .take()
+            .expect("serialize_key is always called before serialize_value");
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Tag::Compound(self.compound)))
+    }
+}
+
+impl ser::SerializeStruct for CompoundSerializer {
+    type Ok = Option<Tag>;
+    type Error = ProtocolError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        self.insert(key.to_owned(), value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        ser::SerializeMap::end(self)
+    }
+}
+
+struct VariantSerializer<S> {
+    variant: &'static str,
+    inner: S,
+}
+
+impl<S> VariantSerializer<S> {
+    fn wrap(variant: &str, value: Option<Tag>) -> Option<Tag> {
+        let mut compound = Compound::new();
+        compound.insert(variant.to_owned(), value?);
+        Some(Tag::Compound(compound))
+    }
+}
+
+impl ser::SerializeTupleVariant for VariantSerializer<ListSerializer> {
+    type Ok = Option<Tag>;
+    type Error = ProtocolError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.inner.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        let value = ser::SerializeSeq::end(self.inner)?;
+        Ok(Self::wrap(self.variant, value))
+    }
+}
+
+impl ser::SerializeStructVariant for VariantSerializer<CompoundSerializer> {
+    type Ok = Option<Tag>;
+    type Error = ProtocolError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        ser::SerializeStruct::serialize_field(&mut self.inner, key, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        let value = ser::SerializeMap::end(self.inner)?;
+        Ok(Self::wrap(self.variant, value))
+    }
+}
@@ -0,0 +1,464 @@
+use std::fmt::{self, Write};
+use std::str::FromStr;
+
+use crate::nbt::{Compound, NbtLimits, Tag};
+use crate::reader::ProtocolError;
+
+//Stringified NBT, as used by commands and data packs, e.g. {name:"Steve",pos:[I;1,2,3]}
+
+//Characters that can appear in strings and keys without quotes
+fn is_unquoted(char: char) -> bool {
+    char.is_ascii_alphanumeric() || matches!(char, '_' | '-' | '.' | '+')
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, value: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for char in value.chars() {
+        if matches!(char, '"' | '\\') {
+            f.write_char('\\')?;
+        }
+        f.write_char(char)?;
+    }
+    f.write_char('"')
+}
+
+fn write_values<T>(
+    f: &mut fmt::Formatter<'_>,
+    prefix: &str,
+    values: &[T],
+    mut write: impl FnMut(&mut fmt::Formatter<'_>, &T) -> fmt::Result,
+) -> fmt::Result {
+    f.write_str(prefix)?;
+    for (index, value) in values.iter().enumerate() {
+        if index > 0 {
+            f.write_char(',')?;
+        }
+        write(f, value)?;
+    }
+    f.write_char(']')
+}
+
+impl fmt::Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Tag::Byte(value) => write!(f, "{}b", value),
+            Tag::Short(value) => write!(f, "{}s", value),
+            Tag::Int(value) => write!(f, "{}", value),
+            Tag::Long(value) => write!(f, "{}L", value),
+            Tag::Float(value) => write!(f, "{}f", value),
+            Tag::Double(value) => write!(f, "{}d", value),
+            Tag::ByteArray(values) => {
+                write_values(f, "[B;", values, |f, value| write!(f, "{}b", value))
+            }
+            Tag::String(value) => write_string(f, value),
+            Tag::List(values) => write_values(f, "[", values, |f, value| value.fmt(f)),
+            Tag::Compound(compound) => {
+                f.write_char('{')?;
+                for (index, (key, value)) in compound.iter().enumerate() {
+                    if index > 0 {
+                        f.write_char(',')?;
+                    }
+
+                    if !key.is_empty() && key.chars().all(is_unquoted) {
+                        f.write_str(key)?;
+                    } else {
+                        write_string(f, key)?;
+                    }
+                    write!(f, ":{}", value)?;
+                }
+                f.write_char('}')
+            }
+            Tag::IntArray(values) => {
+                write_values(f, "[I;", values, |f, value| write!(f, "{}", value))
+            }
+            Tag::LongArray(values) => {
+                write_values(f, "[L;", values, |f, value| write!(f, "{}L", value))
+            }
+        }
+    }
+}
+
+impl FromStr for Tag {
+    type Err = ProtocolError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        parse(value, NbtLimits::NETWORK.max_depth)
+    }
+}
+
+//Parses SNBT, failing on anything nested deeper than max_depth
+pub fn parse(value: &str, max_depth: usize) -> Result<Tag, ProtocolError> {
+    let mut parser = Parser {
+        input: value,
+        position: 0,
+        max_depth,
+    };
+
+    let tag = parser.read_value(0)?;
+    parser.skip_whitespace();
+    if parser.position < value.len() {
+        return Err(parser.error("Trailing data"));
+    }
+
+    Ok(tag)
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    //In bytes
+    position: usize,
+    max_depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: &str) -> ProtocolError {
+        ProtocolError::InvalidData(format!("{} at position {} of SNBT", message, self.position))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.position..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(char) = self.peek().filter(|char| char.is_whitespace()) {
+            self.position += char.len_utf8();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ProtocolError> {
+        self.skip_whitespace();
+        if self.peek() != Some(expected) {
+            return Err(self.error(&format!("Expected '{}'", expected)));
+        }
+
+        self.position += 1;
+        Ok(())
+    }
+
+    //Moves past a separating comma, returning whether there's another value before the closing char
+    fn next_value(&mut self, close: char) -> Result<bool, ProtocolError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(',') => {
+                self.position += 1;
+                Ok(true)
+            }
+            Some(char) if char == close => {
+                self.position += 1;
+                Ok(false)
+            }
+            _ => Err(self.error(&format!("Expected ',' or '{}'", close))),
+        }
+    }
+
+    fn read_value(&mut self, depth: usize) -> Result<Tag, ProtocolError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.read_compound(depth),
+            Some('[') => self.read_list(depth),
+            Some('"' | '\'') => Ok(Tag::String(self.read_quoted()?)),
+            _ => {
+                let token = self.read_unquoted()?;
+                Ok(parse_literal(token))
+            }
+        }
+    }
+
+    fn check_depth(&self, depth: usize) -> Result<(), ProtocolError> {
+        if depth > self.max_depth {
+            return Err(ProtocolError::NbtTooDeep {
+                max: self.max_depth,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn read_compound(&mut self, depth: usize) -> Result<Tag, ProtocolError> {
+        self.check_depth(depth)?;
+        self.expect('{')?;
+
+        let mut compound = Compound::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.position += 1;
+            return Ok(Tag::Compound(compound));
+        }
+
+        loop {
+            self.skip_whitespace();
+            let key = match self.peek() {
+                Some('"' | '\'') => self.read_quoted()?,
+                _ => self.read_unquoted()?.to_owned(),
+            };
+            self.expect(':')?;
+
+            let value = self.read_value(depth + 1)?;
+            compound.insert(key, value);
+
+            if !self.next_value('}')? {
+                return Ok(Tag::Compound(compound));
+            }
+        }
+    }
+
+    fn read_list(&mut self, depth: usize) -> Result<Tag, ProtocolError> {
+        self.check_depth(depth)?;
+        self.expect('[')?;
+
+        let rest = &self.input[self.position..];
+        let array = ["B;", "I;", "L;"]
+            .into_iter()
+            .find(|prefix| rest.starts_with(prefix));
+        if let Some(prefix) = array {
+            self.position += prefix.len();
+            return self.read_array(prefix);
+        }
+
+        let mut values: Vec<Tag> = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.position += 1;
+            return Ok(Tag::List(values));
+        }
+
+        loop {
+            let value = self.read_value(depth + 1)?;
+            if values.first().is_some_and(|first| first.id() != value.id()) {
+                return Err(self.error("Lists can only hold one type of tag"));
+            }
+            values.push(value);
+
+            if !self.next_value(']')? {
+                return Ok(Tag::List(values));
+            }
+        }
+    }
+
+    fn read_array(&mut self, prefix: &str) -> Result<Tag, ProtocolError> {
+        let mut values = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.position += 1;
+        } else {
+            loop {
+                self.skip_whitespace();
+                let token = self.read_unquoted()?;
+                values.push(parse_literal(token));
+
+                if !self.next_value(']')? {
+                    break;
+                }
+            }
+        }
+
+        let invalid = || self.error(&format!("Invalid element in [{} array", prefix));
+        let array = match prefix {
+            "B;" => Tag::ByteArray(
+                values
+                    .iter()
+                    .map(|value| match value {
+                        Tag::Byte(value) => Ok(*value),
+                        _ => Err(invalid()),
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            "I;" => Tag::IntArray(
+                values
+                    .iter()
+                    .map(|value| match value {
+                        Tag::Int(value) => Ok(*value),
+                        _ => Err(invalid()),
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            _ => Tag::LongArray(
+                values
+                    .iter()
+                    .map(|value| match value {
+                        Tag::Long(value) => Ok(*value),
+                        _ => Err(invalid()),
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+        };
+
+        Ok(array)
+    }
+
+    fn read_quoted(&mut self) -> Result<String, ProtocolError> {
+        let quote = self.peek().expect("Only called at a quote");
+        self.position += 1;
+
+        let mut value = String::new();
+        let mut escaped = false;
+        let input = self.input;
+        for char in input[self.position..].chars() {
+            self.position += char.len_utf8();
+
+            if escaped {
+                if char != quote && char != '\\' {
+                    return Err(self.error(&format!("Invalid escape \\{}", char)));
+                }
+                value.push(char);
+                escaped = false;
+            } else if char == '\\' {
+                escaped = true;
+            } else if char == quote {
+                return Ok(value);
+            } else {
+                value.push(char);
+            }
+        }
+
+        Err(self.error("Unterminated string"))
+    }
+
+    fn read_unquoted(&mut self) -> Result<&'a str, ProtocolError> {
+        let start = self.position;
+        while let Some(char) = self.peek().filter(|&char| is_unquoted(char)) {
+            self.position += char.len_utf8();
+        }
+
+        if start == self.position {
+            return Err(self.error("Expected a value"));
+        }
+
+        Ok(&self.input[start..self.position])
+    }
+}
+
+fn is_integer(value: &str) -> bool {
+    let digits = value.strip_prefix(['-', '+']).unwrap_or(value);
+    !digits.is_empty() && digits.bytes().all(|byte| byte.is_ascii_digit())
+}
+
+//Digits with an optional sign, fraction and exponent, but not Rust's inf and NaN
+fn is_float(value: &str) -> bool {
+    let (mantissa, exponent) = match value.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, Some(exponent)),
+        None => (value, None),
+    };
+    let mantissa = mantissa.strip_prefix(['-', '+']).unwrap_or(mantissa);
+    let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+
+    let digits = |part: &str| part.bytes().all(|byte| byte.is_ascii_digit());
+    !(whole.is_empty() && fraction.is_empty())
+        && digits(whole)
+        && digits(fraction)
+        && exponent.is_none_or(is_integer)
+}
+
+//Unquoted values are numbers when they look like one and fit, and strings otherwise
+fn parse_literal(token: &str) -> Tag {
+    match token {
+        "true" => return Tag::Byte(1),
+        "false" => return Tag::Byte(0),
+        _ => {}
+    }
+
+    let (number, suffix) = token.split_at(token.len() - 1);
+    let suffix = suffix.to_ascii_lowercase();
+    let parsed = match suffix.as_str() {
+        "b" if is_integer(number) => number.parse().ok().map(Tag::Byte),
+        "s" if is_integer(number) => number.parse().ok().map(Tag::Short),
+        "l" if is_integer(number) => number.parse().ok().map(Tag::Long),
+        "f" if is_float(number) => number.parse().ok().map(Tag::Float),
+        "d" if is_float(number) => number.parse().ok().map(Tag::Double),
+        _ if is_integer(token) => token.parse().ok().map(Tag::Int),
+        _ if is_float(token) => token.parse().ok().map(Tag::Double),
+        _ => None,
+    };
+
+    parsed.unwrap_or_else(|| Tag::from(token))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nbt::tests::tag;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_parse() {
+        let tag: Tag = r#"{ name: "Steve", 'quote"d': 'it\'s', health: 20.0f, xp: 5, time: 12L,
+            flying: false, pos: [I; 1, -2, 3], tags: [a, "b c"], "": {}, large: 3000000000 }"#
+            .parse()
+            .unwrap();
+        let compound = tag.as_compound().unwrap();
+
+        assert_eq!(compound["name"], Tag::from("Steve"));
+        assert_eq!(compound["quote\"d"], Tag::from("it's"));
+        assert_eq!(compound["health"], Tag::Float(20.0));
+        assert_eq!(compound["xp"], Tag::Int(5));
+        assert_eq!(compound["time"], Tag::Long(12));
+        assert_eq!(compound["flying"], Tag::Byte(0));
+        assert_eq!(compound["pos"], Tag::IntArray(vec![1, -2, 3]));
+        assert_eq!(
+            compound["tags"],
+            Tag::List(vec![Tag::from("a"), Tag::from("b c")])
+        );
+        assert_eq!(compound[""], Tag::Compound(Compound::new()));
+        //Too large for an int, so it's a string like in vanilla
+        assert_eq!(compound["large"], Tag::from("3000000000"));
+    }
+
+    #[test]
+    fn test_literals() {
+        assert_eq!(parse_literal("1b"), Tag::Byte(1));
+        assert_eq!(parse_literal("-3S"), Tag::Short(-3));
+        assert_eq!(parse_literal("1.5"), Tag::Double(1.5));
+        assert_eq!(parse_literal("1e3f"), Tag::Float(1000.0));
+        assert_eq!(parse_literal(".5d"), Tag::Double(0.5));
+        assert_eq!(parse_literal("300b"), Tag::from("300b"));
+        assert_eq!(parse_literal("inff"), Tag::from("inff"));
+        assert_eq!(
+            parse_literal("minecraft.stone"),
+            Tag::from("minecraft.stone")
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        for value in [
+            "{a:1",
+            "[1, a]",
+            "[I; 1b]",
+            "{a 1}",
+            "\"open",
+            "{} {}",
+            "[B; \"x\"]",
+        ] {
+            assert!(value.parse::<Tag>().is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn test_too_deep() {
+        let value = format!("{}{}", "[".repeat(600), "]".repeat(600));
+
+        assert_eq!(
+            value.parse::<Tag>(),
+            Err(ProtocolError::NbtTooDeep { max: 512 })
+        );
+    }
+
+    #[test]
+    fn test_print() {
+        let tag: Tag = "{b:1b,list:[1s,2s],\"a key\":\"say \\\"hi\\\"\",longs:[L;1L]}"
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            tag.to_string(),
+            r#"{"a key":"say \"hi\"",b:1b,list:[1s,2s],longs:[L;1L]}"#
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn test_round_trip(tag in tag()) {
+            prop_assert_eq!(tag.to_string().parse::<Tag>().unwrap(), tag);
+        }
+    }
+}
@@ -1,8 +1,312 @@
 use bytes::BytesMut;
-use crate::reader::ProtocolBufferReaderExt;
+use thiserror::Error;
 
+use crate::auth::AuthError;
+use crate::encryption::EncryptionError;
+use crate::framing::FrameError;
+use crate::reader::ProtocolError;
+
+pub mod configuration;
+pub mod handshaking;
+pub mod login;
+pub mod play;
+pub mod status;
+pub mod version;
+
+use version::ProtocolVersion;
+
+//The version every packet in this module is declared for, see version.rs for the others
+pub const PROTOCOL_VERSION: i32 = 767;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConnectionState {
+    Handshaking,
+    Status,
+    Login,
+    Configuration,
+    Play,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Serverbound,
+    Clientbound,
+}
+
+#[derive(Debug, Error)]
+pub enum PacketError {
+    #[error("Malformed packet: {0}")]
+    Protocol(#[from] ProtocolError),
+    #[error("Unknown packet ID {id:#04X} in state {state:?}")]
+    UnknownPacket { state: ConnectionState, id: i32 },
+    #[error("Packet ID {id:#04X} in state {state:?} has {remaining} bytes left after parsing")]
+    TrailingBytes {
+        state: ConnectionState,
+        id: i32,
+        remaining: usize,
+    },
+    #[error("Invalid next state {0} in handshake")]
+    InvalidNextState(i32),
+    #[error("Outdated client! Please use {0}")]
+    OutdatedClient(String),
+    #[error("Outdated server! I'm still on {0}")]
+    OutdatedServer(String),
+    #[error("Unexpected {0} during login")]
+    UnexpectedLoginPacket(&'static str),
+    #[error("Unexpected {0} during configuration")]
+    UnexpectedConfigurationPacket(&'static str),
+    #[error("Client doesn't know the {0} data pack")]
+    MissingKnownPack(String),
+    #[error("Encryption failed: {0}")]
+    Encryption(#[from] EncryptionError),
+    #[error("Failed to verify username: {0}")]
+    Authentication(#[from] AuthError),
+    #[error("Failed to frame packet: {0}")]
+    Frame(#[from] FrameError),
+    #[error("Client isn't keeping up with its outbound packets")]
+    OutboundQueueFull,
+    #[error("Connection is closed")]
+    ConnectionClosed,
+}
+
+//A packet as it comes off the wire, with its ID already split from the body
 #[derive(Debug)]
-pub struct Packet {
+pub struct RawPacket {
     pub id: i32,
-    pub buffer: BytesMut
-}
\ No newline at end of file
+    pub buffer: BytesMut,
+}
+
+//A typed packet, which knows its own ID and the state and direction it belongs to.
+//Its fields may differ between protocol versions, so it's encoded for a specific one.
+pub trait Packet: Sized {
+    //The ID in PROTOCOL_VERSION, other versions map it to their own
+    const ID: i32;
+    const STATE: ConnectionState;
+    const DIRECTION: Direction;
+
+    fn encode_for(&self, buffer: &mut BytesMut, version: &ProtocolVersion)
+        -> Result<(), ProtocolError>;
+    fn decode_for(buffer: &mut BytesMut, version: &ProtocolVersion) -> Result<Self, ProtocolError>;
+
+    //Serializes the packet body with the ID of the given version, without the length prefix
+    fn to_raw(&self, version: &ProtocolVersion) -> Result<RawPacket, ProtocolError> {
+        let mut buffer = BytesMut::new();
+        self.encode_for(&mut buffer, version)?;
+
+        Ok(RawPacket {
+            id: version.packet_id::<Self>(),
+            buffer,
+        })
+    }
+}
+
+//Declares the packets of a state, generating the structs, their Packet implementations
+//and, for serverbound packets, an enum that decodes a RawPacket into the matching variant.
+//Fields that only exist in some protocol versions are marked with #[versions(range)].
+macro_rules! packets {
+    (
+        serverbound $state:ident => $dispatcher:ident {
+            $($id:literal => $name:ident { $($fields:tt)* }),* $(,)?
+        }
+    ) => {
+        $(
+            $crate::packet::packets!(@packet Serverbound $state $id $name { $($fields)* });
+        )*
+
+        #[derive(Debug, Clone, PartialEq)]
+        pub enum $dispatcher {
+            $($name($name)),*
+        }
+
+        impl $dispatcher {
+            pub fn decode(
+                packet: &mut $crate::packet::RawPacket,
+                version: &$crate::packet::version::ProtocolVersion,
+            ) -> Result<Self, $crate::packet::PacketError> {
+                let state = $crate::packet::ConnectionState::$state;
+                let id = version
+                    .declared_id(state, $crate::packet::Direction::Serverbound, packet.id)
+                    .ok_or($crate::packet::PacketError::UnknownPacket { state, id: packet.id })?;
+
+                let decoded = match id {
+                    $($id => $dispatcher::$name(
+                        <$name as $crate::packet::Packet>::decode_for(&mut packet.buffer, version)?
+                    ),)*
+                    _ => {
+                        return Err($crate::packet::PacketError::UnknownPacket {
+                            state,
+                            id: packet.id,
+                        })
+                    }
+                };
+
+                if !packet.buffer.is_empty() {
+                    return Err($crate::packet::PacketError::TrailingBytes {
+                        state,
+                        id: packet.id,
+                        remaining: packet.buffer.len(),
+                    });
+                }
+
+                Ok(decoded)
+            }
+        }
+    };
+    (
+        clientbound $state:ident {
+            $($id:literal => $name:ident { $($fields:tt)* }),* $(,)?
+        }
+    ) => {
+        $(
+            $crate::packet::packets!(@packet Clientbound $state $id $name { $($fields)* });
+        )*
+    };
+    (
+        @packet $direction:ident $state:ident $id:literal $name:ident {
+            $($(#[versions($versions:expr)])? $field:ident: $ty:ty),* $(,)?
+        }
+    ) => {
+        #[derive(Debug, Clone, PartialEq)]
+        pub struct $name {
+            $(pub $field: $ty),*
+        }
+
+        impl $crate::packet::Packet for $name {
+            const ID: i32 = $id;
+            const STATE: $crate::packet::ConnectionState =
+                $crate::packet::ConnectionState::$state;
+            const DIRECTION: $crate::packet::Direction = $crate::packet::Direction::$direction;
+
+            #[allow(unused_variables)]
+            fn encode_for(
+                &self,
+                buffer: &mut bytes::BytesMut,
+                version: &$crate::packet::version::ProtocolVersion,
+            ) -> Result<(), $crate::reader::ProtocolError> {
+                $($crate::packet::packets!(@encode self.$field, buffer, version $(, $versions)?);)*
+                Ok(())
+            }
+
+            #[allow(unused_variables)]
+            fn decode_for(
+                buffer: &mut bytes::BytesMut,
+                version: &$crate::packet::version::ProtocolVersion,
+            ) -> Result<Self, $crate::reader::ProtocolError> {
+                Ok(Self {
+                    $($field: $crate::packet::packets!(@decode $ty, buffer, version $(, $versions)?)),*
+                })
+            }
+        }
+    };
+    (@encode $value:expr, $buffer:ident, $version:ident) => {
+        $crate::types::Encode::encode(&$value, $buffer)?
+    };
+    (@encode $value:expr, $buffer:ident, $version:ident, $versions:expr) => {
+        if ($versions).contains(&$version.protocol) {
+            $crate::types::Encode::encode(&$value, $buffer)?
+        }
+    };
+    (@decode $ty:ty, $buffer:ident, $version:ident) => {
+        <$ty as $crate::types::Decode>::decode($buffer)?
+    };
+    //Fields missing from the version are left at their default
+    (@decode $ty:ty, $buffer:ident, $version:ident, $versions:expr) => {
+        if ($versions).contains(&$version.protocol) {
+            <$ty as $crate::types::Decode>::decode($buffer)?
+        } else {
+            Default::default()
+        }
+    };
+}
+
+pub(crate) use packets;
+
+#[cfg(test)]
+mod tests {
+    use super::handshaking::{Handshake, ServerboundHandshakingPacket};
+    use super::login::LoginSuccess;
+    use super::play::SystemChatMessage;
+    use super::version::NEXT_VERSION;
+    use super::*;
+    use crate::text::TextComponent;
+    use uuid::Uuid;
+    use crate::types::{BoundedString, VarInt};
+    use crate::writer::ProtocolBufferWriterExt;
+
+    fn handshake() -> Handshake {
+        Handshake {
+            protocol_version: VarInt(PROTOCOL_VERSION),
+            server_address: BoundedString("localhost".to_owned()),
+            port: 25565,
+            next_state: VarInt(1),
+        }
+    }
+
+    #[test]
+    fn test_dispatch_handshake() {
+        let mut raw = handshake().to_raw(&ProtocolVersion::NATIVE).unwrap();
+
+        assert_eq!(raw.id, Handshake::ID);
+        assert_eq!(
+            ServerboundHandshakingPacket::decode(&mut raw, &ProtocolVersion::NATIVE).unwrap(),
+            ServerboundHandshakingPacket::Handshake(handshake())
+        );
+    }
+
+    #[test]
+    fn test_dispatch_unknown_packet() {
+        let mut raw = RawPacket {
+            id: 0x7F,
+            buffer: BytesMut::new(),
+        };
+
+        assert!(matches!(
+            ServerboundHandshakingPacket::decode(&mut raw, &ProtocolVersion::NATIVE),
+            Err(PacketError::UnknownPacket {
+                state: ConnectionState::Handshaking,
+                id: 0x7F
+            })
+        ));
+    }
+
+    #[test]
+    fn test_versioned_fields() {
+        let login_success = LoginSuccess {
+            uuid: Uuid::nil(),
+            username: BoundedString("Steve".to_owned()),
+            properties: Vec::new(),
+            strict_error_handling: true,
+        };
+
+        let native = login_success.to_raw(&ProtocolVersion::NATIVE).unwrap();
+        let newer = login_success.to_raw(&NEXT_VERSION).unwrap();
+        assert_eq!(native.buffer.len(), newer.buffer.len() + 1);
+
+        let mut buffer = newer.buffer;
+        let decoded = LoginSuccess::decode_for(&mut buffer, &NEXT_VERSION).unwrap();
+        assert!(!decoded.strict_error_handling);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_remapped_packet_id() {
+        let message = SystemChatMessage {
+            content: TextComponent::text("Hi"),
+            overlay: false,
+        };
+
+        assert_eq!(message.to_raw(&ProtocolVersion::NATIVE).unwrap().id, 0x6C);
+        assert_eq!(message.to_raw(&NEXT_VERSION).unwrap().id, 0x73);
+    }
+
+    #[test]
+    fn test_dispatch_trailing_bytes() {
+        let mut raw = handshake().to_raw(&ProtocolVersion::NATIVE).unwrap();
+        raw.buffer.write_u8(&0).unwrap();
+
+        assert!(matches!(
+            ServerboundHandshakingPacket::decode(&mut raw, &ProtocolVersion::NATIVE),
+            Err(PacketError::TrailingBytes { remaining: 1, .. })
+        ));
+    }
+}
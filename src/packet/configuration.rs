@@ -0,0 +1,58 @@
+use uuid::Uuid;
+
+use crate::nbt::Tag;
+use crate::packet::packets;
+use crate::text::TextComponent;
+use crate::types::{protocol_struct, BoundedString, RemainingBytes, VarInt};
+
+packets! {
+    serverbound Configuration => ServerboundConfigurationPacket {
+        0x00 => ClientInformation {
+            locale: BoundedString<16>,
+            view_distance: i8,
+            chat_mode: VarInt,
+            chat_colors: bool,
+            displayed_skin_parts: u8,
+            main_hand: VarInt,
+            enable_text_filtering: bool,
+            allow_server_listings: bool,
+            //Added in 1.21.2, all particles are shown before that
+            #[versions(768..)]
+            particle_status: VarInt,
+        },
+        0x01 => CookieResponse { key: String, payload: Option<Vec<u8>> },
+        0x02 => PluginMessage { channel: String, data: RemainingBytes },
+        0x03 => AcknowledgeFinishConfiguration {},
+        0x04 => KeepAlive { keep_alive_id: i64 },
+        0x05 => Pong { id: i32 },
+        0x06 => ResourcePackResponse { uuid: Uuid, result: VarInt },
+        0x07 => KnownPacks { known_packs: Vec<KnownPack> },
+    }
+}
+
+packets! {
+    clientbound Configuration {
+        0x01 => ClientboundPluginMessage { channel: String, data: RemainingBytes },
+        0x02 => Disconnect { reason: TextComponent },
+        0x03 => FinishConfiguration {},
+        0x07 => RegistryData { registry_id: String, entries: Vec<RegistryEntry> },
+        0x0C => FeatureFlags { feature_flags: Vec<String> },
+        0x0E => ClientboundKnownPacks { known_packs: Vec<KnownPack> },
+    }
+}
+
+protocol_struct! {
+    //A single registry entry, its data left out when the client loads it from a known pack
+    RegistryEntry {
+        entry_id: String,
+        data: Option<Tag>,
+    }
+}
+
+protocol_struct! {
+    KnownPack {
+        namespace: String,
+        id: String,
+        version: String,
+    }
+}
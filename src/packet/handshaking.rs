@@ -0,0 +1,13 @@
+use crate::packet::packets;
+use crate::types::{BoundedString, VarInt};
+
+packets! {
+    serverbound Handshaking => ServerboundHandshakingPacket {
+        0x00 => Handshake {
+            protocol_version: VarInt,
+            server_address: BoundedString<255>,
+            port: u16,
+            next_state: VarInt,
+        },
+    }
+}
@@ -0,0 +1,37 @@
+use uuid::Uuid;
+
+use crate::auth::ProfileProperty;
+use crate::packet::packets;
+use crate::types::{BoundedString, RemainingBytes, VarInt};
+
+packets! {
+    serverbound Login => ServerboundLoginPacket {
+        0x00 => LoginStart { name: BoundedString<16>, uuid: Uuid },
+        0x01 => EncryptionResponse { shared_secret: Vec<u8>, verify_token: Vec<u8> },
+        0x02 => LoginPluginResponse { message_id: VarInt, data: Option<RemainingBytes> },
+        0x03 => LoginAcknowledged {},
+        0x04 => CookieResponse { key: String, payload: Option<Vec<u8>> },
+    }
+}
+
+packets! {
+    clientbound Login {
+        //JSON text component, login is the only state that still uses JSON
+        0x00 => Disconnect { reason: String },
+        0x01 => EncryptionRequest {
+            server_id: BoundedString<20>,
+            public_key: Vec<u8>,
+            verify_token: Vec<u8>,
+            should_authenticate: bool,
+        },
+        0x02 => LoginSuccess {
+            uuid: Uuid,
+            username: BoundedString<16>,
+            properties: Vec<ProfileProperty>,
+            //Removed in 1.21.2
+            #[versions(..768)]
+            strict_error_handling: bool,
+        },
+        0x03 => SetCompression { threshold: VarInt },
+    }
+}
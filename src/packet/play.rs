@@ -0,0 +1,49 @@
+use crate::nbt::Tag;
+use crate::packet::packets;
+use crate::text::TextComponent;
+use crate::types::{protocol_struct, BitSet, VarInt};
+
+protocol_struct! {
+    //A chunk's heightmaps for the client, its sections one after another and its block entities
+    ChunkData {
+        heightmaps: Tag,
+        data: Vec<u8>,
+        block_entities: Vec<BlockEntityData>,
+    }
+}
+
+protocol_struct! {
+    //The X and Z within the chunk are packed into a byte, X in the upper four bits
+    BlockEntityData {
+        packed_xz: u8,
+        y: i16,
+        kind: VarInt,
+        data: Tag,
+    }
+}
+
+protocol_struct! {
+    //The masks have a bit per section from the one below the world to the one above it. Sections in
+    //the first two masks are sent as 2048 byte arrays in order, those in the empty masks are all dark.
+    LightData {
+        sky_light_mask: BitSet,
+        block_light_mask: BitSet,
+        empty_sky_light_mask: BitSet,
+        empty_block_light_mask: BitSet,
+        sky_light: Vec<Vec<u8>>,
+        block_light: Vec<Vec<u8>>,
+    }
+}
+
+packets! {
+    clientbound Play {
+        0x1D => Disconnect { reason: TextComponent },
+        0x27 => ChunkDataAndUpdateLight {
+            chunk_x: i32,
+            chunk_z: i32,
+            data: ChunkData,
+            light: LightData,
+        },
+        0x6C => SystemChatMessage { content: TextComponent, overlay: bool },
+    }
+}
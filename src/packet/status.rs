@@ -0,0 +1,15 @@
+use crate::packet::packets;
+
+packets! {
+    serverbound Status => ServerboundStatusPacket {
+        0x00 => StatusRequest {},
+        0x01 => PingRequest { payload: i64 },
+    }
+}
+
+packets! {
+    clientbound Status {
+        0x00 => StatusResponse { json_response: String },
+        0x01 => PongResponse { payload: i64 },
+    }
+}
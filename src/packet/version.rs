@@ -0,0 +1,166 @@
+use crate::packet::{ConnectionState, Direction, Packet, PacketError};
+
+//A packet whose ID in some version differs from the one it's declared with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PacketRemap {
+    state: ConnectionState,
+    direction: Direction,
+    declared_id: i32,
+    id: i32,
+}
+
+//Only the tests declare remapped versions until a second version is mapped
+#[cfg(test)]
+const fn remap<P: Packet>(id: i32) -> PacketRemap {
+    PacketRemap {
+        state: P::STATE,
+        direction: P::DIRECTION,
+        declared_id: P::ID,
+        id,
+    }
+}
+
+//A protocol version clients can join with. Packets are declared with the IDs and fields
+//of PROTOCOL_VERSION, so each version only lists what's different. Fields that were added
+//or removed are marked where the packet is declared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProtocolVersion {
+    pub protocol: i32,
+    //Every release using this protocol, oldest first
+    pub releases: &'static [&'static str],
+    //Every packet that moved, since moving one packet usually shifts the IDs of those after it
+    remapped: &'static [PacketRemap],
+}
+
+pub const V1_21_1: ProtocolVersion = ProtocolVersion {
+    protocol: 767,
+    releases: &["1.21", "1.21.1"],
+    remapped: &[],
+};
+
+//A made-up version after the native one that moves a packet, to test remapping and versioned fields
+#[cfg(test)]
+pub const NEXT_VERSION: ProtocolVersion = ProtocolVersion {
+    protocol: 768,
+    releases: &["next"],
+    remapped: &[remap::<crate::packet::play::SystemChatMessage>(0x73)],
+};
+
+//Oldest first
+pub const SUPPORTED_VERSIONS: &[ProtocolVersion] = &[V1_21_1];
+
+impl ProtocolVersion {
+    //The version packets are declared for, which is also used before the client's is known
+    pub const NATIVE: ProtocolVersion = V1_21_1;
+
+    pub fn find(protocol: i32) -> Option<ProtocolVersion> {
+        SUPPORTED_VERSIONS
+            .iter()
+            .find(|version| version.protocol == protocol)
+            .copied()
+    }
+
+    //Finds the version a client asked for, or the error to disconnect it with
+    pub fn negotiate(protocol: i32) -> Result<ProtocolVersion, PacketError> {
+        if let Some(version) = Self::find(protocol) {
+            return Ok(version);
+        }
+
+        if protocol < SUPPORTED_VERSIONS[SUPPORTED_VERSIONS.len() - 1].protocol {
+            Err(PacketError::OutdatedClient(supported_releases()))
+        } else {
+            Err(PacketError::OutdatedServer(supported_releases()))
+        }
+    }
+
+    //The newest release using this protocol
+    pub fn name(&self) -> &'static str {
+        self.releases[self.releases.len() - 1]
+    }
+
+    //The ID a packet has in this version
+    pub fn packet_id<P: Packet>(&self) -> i32 {
+        self.remapped
+            .iter()
+            .find(|remap| {
+                remap.state == P::STATE
+                    && remap.direction == P::DIRECTION
+                    && remap.declared_id == P::ID
+            })
+            .map_or(P::ID, |remap| remap.id)
+    }
+
+    //The ID a packet received in this version is declared with, if there is such a packet
+    pub fn declared_id(&self, state: ConnectionState, direction: Direction, id: i32) -> Option<i32> {
+        let mut remapped = self
+            .remapped
+            .iter()
+            .filter(|remap| remap.state == state && remap.direction == direction);
+
+        if let Some(remap) = remapped.clone().find(|remap| remap.id == id) {
+            return Some(remap.declared_id);
+        }
+
+        //The declared packet with this ID moved somewhere else in this version
+        if remapped.any(|remap| remap.declared_id == id) {
+            return None;
+        }
+
+        Some(id)
+    }
+}
+
+//Every supported release, e.g. "1.21-1.21.1"
+pub fn supported_releases() -> String {
+    let oldest = SUPPORTED_VERSIONS[0].releases[0];
+    let newest = SUPPORTED_VERSIONS[SUPPORTED_VERSIONS.len() - 1].name();
+
+    if oldest == newest {
+        oldest.to_owned()
+    } else {
+        format!("{}-{}", oldest, newest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::play::{Disconnect, SystemChatMessage};
+    use crate::packet::PROTOCOL_VERSION;
+
+    #[test]
+    fn test_native_version() {
+        assert_eq!(ProtocolVersion::NATIVE.protocol, PROTOCOL_VERSION);
+        assert!(SUPPORTED_VERSIONS
+            .windows(2)
+            .all(|versions| versions[0].protocol < versions[1].protocol));
+    }
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(ProtocolVersion::negotiate(767).unwrap(), V1_21_1);
+        assert_eq!(V1_21_1.name(), "1.21.1");
+
+        let outdated = ProtocolVersion::negotiate(766).unwrap_err();
+        assert_eq!(outdated.to_string(), "Outdated client! Please use 1.21-1.21.1");
+
+        let newer = ProtocolVersion::negotiate(768).unwrap_err();
+        assert_eq!(newer.to_string(), "Outdated server! I'm still on 1.21-1.21.1");
+    }
+
+    #[test]
+    fn test_packet_ids() {
+        assert_eq!(V1_21_1.packet_id::<SystemChatMessage>(), 0x6C);
+        assert_eq!(NEXT_VERSION.packet_id::<SystemChatMessage>(), 0x73);
+        assert_eq!(NEXT_VERSION.packet_id::<Disconnect>(), 0x1D);
+
+        let (state, direction) = (ConnectionState::Play, Direction::Clientbound);
+        assert_eq!(NEXT_VERSION.declared_id(state, direction, 0x73), Some(0x6C));
+        assert_eq!(NEXT_VERSION.declared_id(state, direction, 0x6C), None);
+        assert_eq!(NEXT_VERSION.declared_id(state, direction, 0x1D), Some(0x1D));
+        assert_eq!(
+            NEXT_VERSION.declared_id(ConnectionState::Login, direction, 0x73),
+            Some(0x73)
+        );
+    }
+}
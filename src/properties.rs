@@ -0,0 +1,476 @@
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::{fs, io};
+
+use thiserror::Error;
+
+use crate::server::GlobalConfiguration;
+
+const HEADER: &str = "#Minecraft server properties";
+
+#[derive(Debug, Error)]
+pub enum PropertiesError {
+    #[error("Failed to access server.properties: {0}")]
+    Io(#[from] io::Error),
+    #[error("Invalid value {value:?} for {key}, expected {expected}")]
+    InvalidValue {
+        key: &'static str,
+        value: String,
+        expected: &'static str,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Line {
+    Entry { key: String, value: String },
+    //Comments and blank lines, kept as they are
+    Other(String),
+}
+
+//A Java properties file, keeping unknown keys and comments so it can be written back as it was
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ServerProperties {
+    lines: Vec<Line>,
+}
+
+impl ServerProperties {
+    pub fn parse(input: &str) -> Self {
+        let mut lines = Vec::new();
+        let mut physical = input.lines();
+
+        while let Some(line) = physical.next() {
+            let trimmed = line.trim_start();
+            if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with('!') {
+                lines.push(Line::Other(line.to_owned()));
+                continue;
+            }
+
+            //A line ending in an unescaped backslash continues on the next one
+            let mut logical = trimmed.to_owned();
+            while ends_with_continuation(&logical) {
+                logical.pop();
+                match physical.next() {
+                    Some(next) => logical.push_str(next.trim_start()),
+                    None => break,
+                }
+            }
+
+            let (key, value) = split_entry(&logical);
+            lines.push(Line::Entry {
+                key: unescape(key),
+                value: unescape(value),
+            });
+        }
+
+        Self { lines }
+    }
+
+    //Reads the file as UTF-8, falling back to Latin-1 like vanilla
+    pub fn load(path: &Path) -> Result<Self, PropertiesError> {
+        let bytes = fs::read(path)?;
+        let input = match String::from_utf8(bytes) {
+            Ok(input) => input,
+            Err(error) => error.into_bytes().iter().map(|&byte| byte as char).collect(),
+        };
+
+        Ok(Self::parse(&input))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), PropertiesError> {
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.lines.iter().rev().find_map(|line| match line {
+            Line::Entry { key: k, value } if k == key => Some(value.as_str()),
+            _ => None,
+        })
+    }
+
+    //Updates the key in place, or appends it if it's missing
+    pub fn set(&mut self, key: &str, value: impl Into<String>) {
+        let value = value.into();
+
+        for line in self.lines.iter_mut().rev() {
+            if let Line::Entry { key: k, value: v } = line {
+                if k == key {
+                    *v = value;
+                    return;
+                }
+            }
+        }
+
+        self.lines.push(Line::Entry {
+            key: key.to_owned(),
+            value,
+        });
+    }
+
+    fn parse_value<T: FromStr>(
+        &self,
+        key: &'static str,
+        default: T,
+        expected: &'static str,
+    ) -> Result<T, PropertiesError> {
+        match self.get(key) {
+            None => Ok(default),
+            Some(value) => value.trim().parse().map_err(|_| PropertiesError::InvalidValue {
+                key,
+                value: value.to_owned(),
+                expected,
+            }),
+        }
+    }
+
+    fn parse_range<T: FromStr + PartialOrd + fmt::Display>(
+        &self,
+        key: &'static str,
+        default: T,
+        range: std::ops::RangeInclusive<T>,
+        expected: &'static str,
+    ) -> Result<T, PropertiesError> {
+        let value = self.parse_value(key, default, expected)?;
+
+        if !range.contains(&value) {
+            return Err(PropertiesError::InvalidValue {
+                key,
+                value: value.to_string(),
+                expected,
+            });
+        }
+
+        Ok(value)
+    }
+}
+
+impl fmt::Display for ServerProperties {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            match line {
+                Line::Entry { key, value } => {
+                    writeln!(f, "{}={}", escape(key, true), escape(value, false))?
+                }
+                Line::Other(line) => writeln!(f, "{}", line)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn ends_with_continuation(line: &str) -> bool {
+    line.chars().rev().take_while(|&c| c == '\\').count() % 2 == 1
+}
+
+//Splits at the first unescaped '=', ':' or whitespace, which can be surrounded by whitespace
+fn split_entry(line: &str) -> (&str, &str) {
+    let mut escaped = false;
+    let mut key_end = line.len();
+
+    for (index, c) in line.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == '=' || c == ':' || c.is_whitespace() {
+            key_end = index;
+            break;
+        }
+    }
+
+    let mut rest = line[key_end..].trim_start();
+    if let Some(stripped) = rest.strip_prefix(['=', ':']) {
+        rest = stripped.trim_start();
+    }
+
+    (&line[..key_end], rest)
+}
+
+fn unescape(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('t') => result.push('\t'),
+            Some('n') => result.push('\n'),
+            Some('r') => result.push('\r'),
+            Some('f') => result.push('\x0C'),
+            Some('u') => {
+                let hex: String = chars.by_ref().take(4).collect();
+                match u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+                    Some(c) => result.push(c),
+                    None => result.push_str(&hex),
+                }
+            }
+            Some(c) => result.push(c),
+            None => {}
+        }
+    }
+
+    result
+}
+
+fn escape(value: &str, is_key: bool) -> String {
+    let mut result = String::with_capacity(value.len());
+
+    for (index, c) in value.chars().enumerate() {
+        match c {
+            '\\' => result.push_str("\\\\"),
+            '\t' => result.push_str("\\t"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\x0C' => result.push_str("\\f"),
+            '=' | ':' | '#' | '!' => {
+                result.push('\\');
+                result.push(c);
+            }
+            ' ' if is_key || index == 0 => result.push_str("\\ "),
+            c => result.push(c),
+        }
+    }
+
+    result
+}
+
+fn parse_bool(
+    properties: &ServerProperties,
+    key: &'static str,
+    default: bool,
+) -> Result<bool, PropertiesError> {
+    properties.parse_value(key, default, "true or false")
+}
+
+impl GlobalConfiguration {
+    //Keys missing from the file keep their defaults
+    pub fn from_properties(properties: &ServerProperties) -> Result<Self, PropertiesError> {
+        let defaults = GlobalConfiguration::default();
+
+        let server_ip = match properties.get("server-ip").map(str::trim) {
+            None | Some("") => None,
+            Some(host) => Some(host.to_owned()),
+        };
+
+        let default_threshold = match defaults.enable_packet_compression {
+            true => defaults.compression_threshold as i32,
+            false => -1,
+        };
+        let compression_threshold = properties.parse_range(
+            "network-compression-threshold",
+            default_threshold,
+            -1..=i32::MAX,
+            "-1 to disable compression, or a packet size in bytes",
+        )?;
+
+        Ok(Self {
+            server_ip,
+            server_port: properties.parse_value(
+                "server-port",
+                defaults.server_port,
+                "a port from 0 to 65535",
+            )?,
+            enable_packet_encryption: parse_bool(
+                properties,
+                "online-mode",
+                defaults.enable_packet_encryption,
+            )?,
+            enable_packet_compression: compression_threshold >= 0,
+            compression_threshold: compression_threshold
+                .try_into()
+                .unwrap_or(defaults.compression_threshold),
+            motd: properties
+                .get("motd")
+                .map(str::to_owned)
+                .unwrap_or(defaults.motd),
+            max_players: properties.parse_value(
+                "max-players",
+                defaults.max_players,
+                "a number of players",
+            )?,
+            view_distance: properties.parse_range(
+                "view-distance",
+                defaults.view_distance,
+                2..=32,
+                "a distance from 2 to 32 chunks",
+            )?,
+            simulation_distance: properties.parse_range(
+                "simulation-distance",
+                defaults.simulation_distance,
+                2..=32,
+                "a distance from 2 to 32 chunks",
+            )?,
+            difficulty: properties.parse_value(
+                "difficulty",
+                defaults.difficulty,
+                "peaceful, easy, normal or hard",
+            )?,
+            gamemode: properties.parse_value(
+                "gamemode",
+                defaults.gamemode,
+                "survival, creative, adventure or spectator",
+            )?,
+            hardcore: parse_bool(properties, "hardcore", defaults.hardcore)?,
+            level_name: properties
+                .get("level-name")
+                .map(str::to_owned)
+                .unwrap_or(defaults.level_name),
+            favicon_path: defaults.favicon_path,
+            blocks_report: defaults.blocks_report,
+        })
+    }
+
+    //Writes every known setting, leaving other keys alone
+    pub fn write_properties(&self, properties: &mut ServerProperties) {
+        let compression_threshold = match self.enable_packet_compression {
+            true => self.compression_threshold as i64,
+            false => -1,
+        };
+
+        properties.set(
+            "server-ip",
+            self.server_ip.clone().unwrap_or_default(),
+        );
+        properties.set("server-port", self.server_port.to_string());
+        properties.set("online-mode", self.enable_packet_encryption.to_string());
+        properties.set(
+            "network-compression-threshold",
+            compression_threshold.to_string(),
+        );
+        properties.set("motd", self.motd.clone());
+        properties.set("max-players", self.max_players.to_string());
+        properties.set("view-distance", self.view_distance.to_string());
+        properties.set("simulation-distance", self.simulation_distance.to_string());
+        properties.set("difficulty", self.difficulty.to_string());
+        properties.set("gamemode", self.gamemode.to_string());
+        properties.set("hardcore", self.hardcore.to_string());
+        properties.set("level-name", self.level_name.clone());
+    }
+
+    //Loads the file, then writes it back with every missing setting filled in like vanilla
+    pub fn load_or_create(path: &Path) -> Result<Self, PropertiesError> {
+        let mut properties = if path.exists() {
+            ServerProperties::load(path)?
+        } else {
+            ServerProperties::parse(HEADER)
+        };
+
+        let config = Self::from_properties(&properties)?;
+        config.write_properties(&mut properties);
+        properties.save(path)?;
+
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::{Difficulty, GameMode};
+
+    #[test]
+    fn test_parse() {
+        let properties = ServerProperties::parse(
+            "#Comment\n\
+             ! Another comment\n\
+             motd=Hello \\u00A7aWorld\n\
+             level-name : my\\ world\n\
+             long-value=a\\\n    b\n\
+             key\\=with\\:separators value\n\
+             empty\n",
+        );
+
+        assert_eq!(properties.get("motd"), Some("Hello \u{A7}aWorld"));
+        assert_eq!(properties.get("level-name"), Some("my world"));
+        assert_eq!(properties.get("long-value"), Some("ab"));
+        assert_eq!(properties.get("key=with:separators"), Some("value"));
+        assert_eq!(properties.get("empty"), Some(""));
+        assert_eq!(properties.get("missing"), None);
+    }
+
+    #[test]
+    fn test_rewrite_keeps_unknown_keys() {
+        let mut properties =
+            ServerProperties::parse("#Header\nmotd=Old\nsome-plugin-setting=42\n");
+
+        let config = GlobalConfiguration {
+            motd: "New: motd".to_owned(),
+            ..Default::default()
+        };
+        config.write_properties(&mut properties);
+
+        let written = properties.to_string();
+        assert!(written.starts_with("#Header\nmotd=New\\: motd\nsome-plugin-setting=42\n"));
+
+        let reparsed = ServerProperties::parse(&written);
+        assert_eq!(reparsed.get("some-plugin-setting"), Some("42"));
+        assert_eq!(
+            GlobalConfiguration::from_properties(&reparsed).unwrap().motd,
+            "New: motd"
+        );
+    }
+
+    #[test]
+    fn test_typed_values() {
+        let properties = ServerProperties::parse(
+            "server-ip=\n\
+             server-port=25566\n\
+             online-mode=true\n\
+             network-compression-threshold=512\n\
+             max-players=100\n\
+             view-distance=12\n\
+             simulation-distance=8\n\
+             difficulty=3\n\
+             gamemode=creative\n\
+             level-name=survival\n",
+        );
+        let config = GlobalConfiguration::from_properties(&properties).unwrap();
+
+        assert_eq!(config.server_ip, None);
+        assert_eq!(config.server_port, 25566);
+        assert!(config.enable_packet_encryption);
+        assert!(config.enable_packet_compression);
+        assert_eq!(config.compression_threshold, 512);
+        assert_eq!(config.max_players, 100);
+        assert_eq!(config.view_distance, 12);
+        assert_eq!(config.simulation_distance, 8);
+        assert_eq!(config.difficulty, Difficulty::Hard);
+        assert_eq!(config.gamemode, GameMode::Creative);
+        assert_eq!(config.level_name, "survival");
+
+        let host = ServerProperties::parse("server-ip=localhost\n");
+        assert_eq!(
+            GlobalConfiguration::from_properties(&host).unwrap().server_ip,
+            Some("localhost".to_owned())
+        );
+
+        let disabled = ServerProperties::parse("network-compression-threshold=-1\n");
+        assert!(!GlobalConfiguration::from_properties(&disabled)
+            .unwrap()
+            .enable_packet_compression);
+    }
+
+    #[test]
+    fn test_invalid_values() {
+        for (input, key) in [
+            ("server-port=70000", "server-port"),
+            ("online-mode=yes", "online-mode"),
+            ("view-distance=1", "view-distance"),
+            ("difficulty=impossible", "difficulty"),
+            ("network-compression-threshold=-2", "network-compression-threshold"),
+        ] {
+            let properties = ServerProperties::parse(input);
+
+            match GlobalConfiguration::from_properties(&properties) {
+                Err(PropertiesError::InvalidValue { key: invalid, .. }) => assert_eq!(invalid, key),
+                result => panic!("{} parsed as {:?}", input, result.map(|_| ())),
+            }
+        }
+    }
+}
@@ -1,157 +1,186 @@
-use bytes::{Buf, BufMut, Bytes, BytesMut};
-use std::error::Error;
+use bytes::{Buf, Bytes};
 use std::str;
+use thiserror::Error;
 use uuid::Uuid;
 
-#[derive(Debug)]
-pub enum BufferError {
-    DeserializerMessage(String),
-    SerializerMesage(String),
+//Everything that can go wrong reading or writing protocol data, none of which should ever
+//take the server down since most of it comes straight from clients
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum ProtocolError {
+    #[error("Unexpected end of data, needed {needed} bytes but only {available} are left")]
+    UnexpectedEof { needed: usize, available: usize },
+    #[error("VarInt is longer than 5 bytes")]
+    VarIntTooLong,
+    #[error("VarLong is longer than 10 bytes")]
+    VarLongTooLong,
+    #[error("String of length {actual} is longer than the maximum of {max}")]
+    StringTooLong { max: usize, actual: usize },
+    #[error("Not enough space left, needed {needed} bytes but only {available} are left")]
+    BufferFull { needed: usize, available: usize },
+    #[error("String isn't valid UTF-8")]
+    InvalidUtf8,
+    #[error("Invalid {name} {value}")]
+    InvalidEnum { name: &'static str, value: i32 },
+    #[error("Invalid length {length}, only {max} allowed")]
+    InvalidLength { length: i64, max: usize },
+    #[error("Invalid identifier {0}")]
+    InvalidIdentifier(String),
+    #[error("NBT is nested deeper than the maximum of {max}")]
+    NbtTooDeep { max: usize },
+    #[error("NBT is larger than the maximum of {max} bytes")]
+    NbtTooLarge { max: usize },
+    #[error("{0}")]
+    InvalidData(String),
 }
 
 const SEGMENT_BITS: i32 = 0x7F;
 const CONTINUE_BIT: i32 = 0x80;
+//Strings are limited in UTF-16 code units, which take at most 3 bytes each in UTF-8
+const MAX_BYTES_PER_CHAR: usize = 3;
 
+//Reads protocol types from any buffer, e.g. a BytesMut packet body, plugin message Bytes
+//or a plain &[u8]
 pub trait ProtocolBufferReaderExt {
-    fn read_bool(&mut self) -> Result<bool, BufferError>;
-    fn read_i8(&mut self) -> Result<i8, BufferError>;
-    fn read_u8(&mut self) -> Result<u8, BufferError>;
-    fn read_i16(&mut self) -> Result<i16, BufferError>;
-    fn read_u16(&mut self) -> Result<u16, BufferError>;
-    fn read_i32(&mut self) -> Result<i32, BufferError>;
-    fn read_u32(&mut self) -> Result<u32, BufferError>;
-    fn read_i64(&mut self) -> Result<i64, BufferError>;
-    fn read_u64(&mut self) -> Result<u64, BufferError>;
-    fn read_f32(&mut self) -> Result<f32, BufferError>;
-    fn read_f64(&mut self) -> Result<f64, BufferError>;
-    fn read_var_int(&mut self) -> Result<i32, BufferError>;
-    fn read_var_long(&mut self) -> Result<i64, BufferError>;
-    fn read_string(&mut self, size: i32) -> Result<String, BufferError>;
-    fn read_full_string(&mut self) -> Result<String, BufferError>;
-    fn read_uuid(&mut self) -> Result<Uuid, BufferError>;
+    fn read_bool(&mut self) -> Result<bool, ProtocolError>;
+    fn read_i8(&mut self) -> Result<i8, ProtocolError>;
+    fn read_u8(&mut self) -> Result<u8, ProtocolError>;
+    fn read_i16(&mut self) -> Result<i16, ProtocolError>;
+    fn read_u16(&mut self) -> Result<u16, ProtocolError>;
+    fn read_i32(&mut self) -> Result<i32, ProtocolError>;
+    fn read_u32(&mut self) -> Result<u32, ProtocolError>;
+    fn read_i64(&mut self) -> Result<i64, ProtocolError>;
+    fn read_u64(&mut self) -> Result<u64, ProtocolError>;
+    fn read_f32(&mut self) -> Result<f32, ProtocolError>;
+    fn read_f64(&mut self) -> Result<f64, ProtocolError>;
+    fn read_var_int(&mut self) -> Result<i32, ProtocolError>;
+    fn read_var_long(&mut self) -> Result<i64, ProtocolError>;
+    fn read_string(&mut self, size: i32) -> Result<String, ProtocolError>;
+    fn read_full_string(&mut self) -> Result<String, ProtocolError>;
+    fn read_uuid(&mut self) -> Result<Uuid, ProtocolError>;
+    //Packets decode optional fields through Option's Decode, no hand written reader needs this yet
+    #[allow(dead_code)]
     fn read_option<T>(
         &mut self,
-        function: impl Fn(&mut Self) -> Result<T, BufferError>,
-    ) -> Option<T>;
-    fn read_array<T>(&mut self, function: impl Fn(&mut Self) -> Result<T, BufferError>) -> Vec<T>;
-    fn read_bitset(&mut self, len: usize) -> Result<Bytes, BufferError>;
-    fn read_fixed_bitset(&mut self, len: usize) -> Result<Bytes, BufferError>;
-    fn copy_buffer_to_bytes(&mut self, size: usize) -> Result<Bytes, BufferError>;
-    fn copy_buffer_to_slice(&mut self, dst: &mut [u8]) -> Result<(), BufferError>;
+        function: impl Fn(&mut Self) -> Result<T, ProtocolError>,
+    ) -> Result<Option<T>, ProtocolError>;
+    fn read_array<T>(
+        &mut self,
+        function: impl Fn(&mut Self) -> Result<T, ProtocolError>,
+    ) -> Result<Vec<T>, ProtocolError>;
+    fn read_bitset(&mut self) -> Result<Vec<i64>, ProtocolError>;
+    fn read_fixed_bitset(&mut self, bits: usize) -> Result<Bytes, ProtocolError>;
+    fn copy_buffer_to_bytes(&mut self, size: usize) -> Result<Bytes, ProtocolError>;
+    #[allow(dead_code)]
+    fn copy_buffer_to_slice(&mut self, dst: &mut [u8]) -> Result<(), ProtocolError>;
+}
+
+//Borrowing reads that return slices of the buffer itself instead of copies,
+//for hot paths like chunk data and chat. Neither is read from clients yet.
+#[allow(dead_code)]
+pub trait ProtocolSliceReaderExt<'a> {
+    fn read_bytes_ref(&mut self, size: usize) -> Result<&'a [u8], ProtocolError>;
+    fn read_str_ref(&mut self, size: i32) -> Result<&'a str, ProtocolError>;
 }
 
-impl ProtocolBufferReaderExt for BytesMut {
-    fn read_bool(&mut self) -> Result<bool, BufferError> {
-        return Ok(self.read_u8()? != 0);
+fn ensure_remaining(buffer: &impl Buf, needed: usize) -> Result<(), ProtocolError> {
+    if buffer.remaining() < needed {
+        return Err(ProtocolError::UnexpectedEof {
+            needed,
+            available: buffer.remaining(),
+        });
     }
 
-    fn read_i8(&mut self) -> Result<i8, BufferError> {
-        if self.has_remaining() {
-            Ok(self.get_i8())
-        } else {
-            Err(BufferError::DeserializerMessage(
-                "Failed to read i8!".to_owned(),
-            ))
-        }
+    Ok(())
+}
+
+//Reads the byte length of a string whose maximum size is in UTF-16 code units, like vanilla
+fn read_string_length(buffer: &mut impl Buf, size: i32) -> Result<usize, ProtocolError> {
+    let max = size.max(0) as usize * MAX_BYTES_PER_CHAR;
+    let length = buffer.read_var_int()?;
+
+    if length < 0 || length as usize > max {
+        return Err(ProtocolError::StringTooLong {
+            max,
+            actual: length.max(0) as usize,
+        });
     }
 
-    fn read_u8(&mut self) -> Result<u8, BufferError> {
-        if self.has_remaining() {
-            Ok(self.get_u8())
-        } else {
-            Err(BufferError::DeserializerMessage(
-                "Failed to read u8!".to_owned(),
-            ))
-        }
+    Ok(length as usize)
+}
+
+fn check_string(data: &[u8], size: i32) -> Result<&str, ProtocolError> {
+    let max = size.max(0) as usize;
+    let value = str::from_utf8(data).map_err(|_| ProtocolError::InvalidUtf8)?;
+
+    let actual = value.encode_utf16().count();
+    if actual > max {
+        return Err(ProtocolError::StringTooLong { max, actual });
     }
 
-    fn read_i16(&mut self) -> Result<i16, BufferError> {
-        if self.remaining() >= 2 {
-            Ok(self.get_i16())
-        } else {
-            Err(BufferError::DeserializerMessage(
-                "Failed to read u8!".to_owned(),
-            ))
-        }
+    Ok(value)
+}
+
+impl<B: Buf> ProtocolBufferReaderExt for B {
+    fn read_bool(&mut self) -> Result<bool, ProtocolError> {
+        Ok(self.read_u8()? != 0)
     }
 
-    fn read_u16(&mut self) -> Result<u16, BufferError> {
-        if self.remaining() >= 2 {
-            Ok(self.get_u16())
-        } else {
-            Err(BufferError::DeserializerMessage(
-                "Failed to read u8!".to_owned(),
-            ))
-        }
+    fn read_i8(&mut self) -> Result<i8, ProtocolError> {
+        ensure_remaining(self, 1)?;
+        Ok(self.get_i8())
     }
 
-    fn read_i32(&mut self) -> Result<i32, BufferError> {
-        if self.remaining() >= 4 {
-            Ok(self.get_i32())
-        } else {
-            Err(BufferError::DeserializerMessage(
-                "Failed to read u8!".to_owned(),
-            ))
-        }
+    fn read_u8(&mut self) -> Result<u8, ProtocolError> {
+        ensure_remaining(self, 1)?;
+        Ok(self.get_u8())
     }
 
-    fn read_u32(&mut self) -> Result<u32, BufferError> {
-        if self.remaining() >= 4 {
-            Ok(self.get_u32())
-        } else {
-            Err(BufferError::DeserializerMessage(
-                "Failed to read u8!".to_owned(),
-            ))
-        }
+    fn read_i16(&mut self) -> Result<i16, ProtocolError> {
+        ensure_remaining(self, 2)?;
+        Ok(self.get_i16())
     }
 
-    fn read_i64(&mut self) -> Result<i64, BufferError> {
-        if self.remaining() >= 8 {
-            Ok(self.get_i64())
-        } else {
-            Err(BufferError::DeserializerMessage(
-                "Failed to read u8!".to_owned(),
-            ))
-        }
+    fn read_u16(&mut self) -> Result<u16, ProtocolError> {
+        ensure_remaining(self, 2)?;
+        Ok(self.get_u16())
     }
 
-    fn read_u64(&mut self) -> Result<u64, BufferError> {
-        if self.remaining() >= 8 {
-            Ok(self.get_u64())
-        } else {
-            Err(BufferError::DeserializerMessage(
-                "Failed to read u8!".to_owned(),
-            ))
-        }
+    fn read_i32(&mut self) -> Result<i32, ProtocolError> {
+        ensure_remaining(self, 4)?;
+        Ok(self.get_i32())
     }
 
-    fn read_f32(&mut self) -> Result<f32, BufferError> {
-        if self.remaining() >= 4 {
-            return Ok(self.get_f32());
-        } else {
-            Err(BufferError::DeserializerMessage(
-                "Failed to read f32".to_owned(),
-            ))
-        }
+    fn read_u32(&mut self) -> Result<u32, ProtocolError> {
+        ensure_remaining(self, 4)?;
+        Ok(self.get_u32())
     }
 
-    fn read_f64(&mut self) -> Result<f64, BufferError> {
-        if self.remaining() >= 8 {
-            return Ok(self.get_f64());
-        } else {
-            Err(BufferError::DeserializerMessage(
-                "Failed to read f64".to_owned(),
-            ))
-        }
+    fn read_i64(&mut self) -> Result<i64, ProtocolError> {
+        ensure_remaining(self, 8)?;
+        Ok(self.get_i64())
+    }
+
+    fn read_u64(&mut self) -> Result<u64, ProtocolError> {
+        ensure_remaining(self, 8)?;
+        Ok(self.get_u64())
+    }
+
+    fn read_f32(&mut self) -> Result<f32, ProtocolError> {
+        ensure_remaining(self, 4)?;
+        Ok(self.get_f32())
+    }
+
+    fn read_f64(&mut self) -> Result<f64, ProtocolError> {
+        ensure_remaining(self, 8)?;
+        Ok(self.get_f64())
     }
 
-    fn read_var_int(&mut self) -> Result<i32, BufferError> {
+    fn read_var_int(&mut self) -> Result<i32, ProtocolError> {
         let mut value: i32 = 0;
         let mut position: i32 = 0;
-        let mut current_byte: u8 = 0;
 
         loop {
-            current_byte = self.read_u8()?;
+            let current_byte = self.read_u8()?;
 
             value |= ((current_byte & SEGMENT_BITS as u8) as i32) << position;
 
@@ -162,22 +191,19 @@ impl ProtocolBufferReaderExt for BytesMut {
             position += 7;
 
             if position >= 32 {
-                return Err(BufferError::DeserializerMessage(
-                    "VarInt is too big".to_owned(),
-                ));
+                return Err(ProtocolError::VarIntTooLong);
             }
         }
 
-        return Ok(value);
+        Ok(value)
     }
 
-    fn read_var_long(&mut self) -> Result<i64, BufferError> {
+    fn read_var_long(&mut self) -> Result<i64, ProtocolError> {
         let mut value: i64 = 0;
         let mut position: i32 = 0;
-        let mut current_byte: u8 = 0;
 
         loop {
-            current_byte = self.read_u8()?;
+            let current_byte = self.read_u8()?;
 
             value |= ((current_byte & SEGMENT_BITS as u8) as i64) << position;
 
@@ -188,120 +214,130 @@ impl ProtocolBufferReaderExt for BytesMut {
             position += 7;
 
             if position >= 64 {
-                return Err(BufferError::DeserializerMessage(
-                    "VarLong is too big".to_owned(),
-                ));
+                return Err(ProtocolError::VarLongTooLong);
             }
         }
 
-        return Ok(value);
+        Ok(value)
     }
 
-    fn read_full_string(&mut self) -> Result<String, BufferError> {
+    fn read_full_string(&mut self) -> Result<String, ProtocolError> {
         self.read_string(i16::MAX.into())
     }
 
-    fn read_string(&mut self, size: i32) -> Result<String, BufferError> {
-        let buf = self.read_var_int()?;
+    fn read_string(&mut self, size: i32) -> Result<String, ProtocolError> {
+        let length = read_string_length(self, size)?;
+        let data = self.copy_buffer_to_bytes(length)?;
 
-        if buf > size {
-            return Err(BufferError::DeserializerMessage(
-                "String is bigger than the max size!".to_owned(),
-            ));
-        }
-
-        let data = self.copy_buffer_to_bytes(buf as usize)?;
-
-        if data.len() as i32 > size {
-            panic!("String is bigger than the max size!")
-        }
-
-        return Ok(str::from_utf8(&data)
-            .expect("Failed to convert byte data to utf8 string!")
-            .to_string());
+        Ok(check_string(&data, size)?.to_owned())
     }
 
-    fn read_uuid(&mut self) -> Result<Uuid, BufferError> {
+    //The most significant half first, like Java's getMostSignificantBits
+    fn read_uuid(&mut self) -> Result<Uuid, ProtocolError> {
         let high = self.read_u64()?;
         let low = self.read_u64()?;
 
-        let mut bytes: [u8; 16] = [0u8; 16];
-        bytes[..8].copy_from_slice(&high.to_be_bytes());
-        bytes[8..].copy_from_slice(&low.to_be_bytes());
-
-        match Uuid::from_slice(&bytes) {
-            Ok(value) => return Ok(value),
-            Err(_) => {
-                return Err(BufferError::DeserializerMessage(
-                    "Failed to read UUID".to_owned(),
-                ))
-            }
-        }
+        Ok(Uuid::from_u64_pair(high, low))
     }
 
     fn read_option<T>(
         &mut self,
-        function: impl Fn(&mut Self) -> Result<T, BufferError>,
-    ) -> Option<T> {
-        if self.read_bool().unwrap() {
-            Some(function(self).unwrap())
+        function: impl Fn(&mut Self) -> Result<T, ProtocolError>,
+    ) -> Result<Option<T>, ProtocolError> {
+        if self.read_bool()? {
+            Ok(Some(function(self)?))
         } else {
-            None
+            Ok(None)
         }
     }
 
-    fn read_array<T>(&mut self, function: impl Fn(&mut Self) -> Result<T, BufferError>) -> Vec<T> {
-        let length = self.read_var_int().unwrap();
+    fn read_array<T>(
+        &mut self,
+        function: impl Fn(&mut Self) -> Result<T, ProtocolError>,
+    ) -> Result<Vec<T>, ProtocolError> {
+        let length = self.read_var_int()?;
+
+        //Every element takes at least a byte, so a length past the buffer is always invalid
+        if length < 0 || length as usize > self.remaining() {
+            return Err(ProtocolError::InvalidLength {
+                length: length.into(),
+                max: self.remaining(),
+            });
+        }
 
-        let mut values = Vec::new();
+        let mut values = Vec::with_capacity(length as usize);
 
         for _ in 0..length {
-            values.push(function(self).unwrap());
+            values.push(function(self)?);
         }
 
-        values
+        Ok(values)
     }
 
-    fn read_bitset(&mut self, len: usize) -> Result<Bytes, BufferError> {
-        self.copy_buffer_to_bytes(len)
+    //A VarInt number of longs followed by the longs
+    fn read_bitset(&mut self) -> Result<Vec<i64>, ProtocolError> {
+        let length = self.read_var_int()?;
+
+        if length < 0 || length as usize > self.remaining() / 8 {
+            return Err(ProtocolError::InvalidLength {
+                length: length.into(),
+                max: self.remaining() / 8,
+            });
+        }
+
+        let mut values = Vec::with_capacity(length as usize);
+
+        for _ in 0..length {
+            values.push(self.read_i64()?);
+        }
+
+        Ok(values)
     }
 
-    fn read_fixed_bitset(&mut self, len: usize) -> Result<Bytes, BufferError> {
-        self.copy_buffer_to_bytes(len.div_ceil(8))
+    //Just the bytes, since both sides already know how many bits there are
+    fn read_fixed_bitset(&mut self, bits: usize) -> Result<Bytes, ProtocolError> {
+        self.copy_buffer_to_bytes(bits.div_ceil(8))
     }
 
-    fn copy_buffer_to_bytes(&mut self, size: usize) -> Result<Bytes, BufferError> {
-        if self.len() >= size {
-            return Ok(self.copy_to_bytes(size));
-        } else {
-            Err(BufferError::DeserializerMessage(
-                "Failed to copy bytes!".to_owned(),
-            ))
-        }
+    //Zero-copy when reading from Bytes or BytesMut, which hand out a shared slice
+    fn copy_buffer_to_bytes(&mut self, size: usize) -> Result<Bytes, ProtocolError> {
+        ensure_remaining(self, size)?;
+        Ok(self.copy_to_bytes(size))
     }
 
-    fn copy_buffer_to_slice(&mut self, dst: &mut [u8]) -> Result<(), BufferError> {
-        if self.remaining() >= dst.len() {
-            self.copy_from_slice(dst);
-            Ok(())
-        } else {
-            Err(BufferError::DeserializerMessage(
-                "Failed to copy from slice!".to_owned(),
-            ))
-        }
+    fn copy_buffer_to_slice(&mut self, dst: &mut [u8]) -> Result<(), ProtocolError> {
+        ensure_remaining(self, dst.len())?;
+        self.copy_to_slice(dst);
+        Ok(())
+    }
+}
+
+impl<'a> ProtocolSliceReaderExt<'a> for &'a [u8] {
+    fn read_bytes_ref(&mut self, size: usize) -> Result<&'a [u8], ProtocolError> {
+        ensure_remaining(self, size)?;
+
+        let (data, rest) = self.split_at(size);
+        *self = rest;
+        Ok(data)
+    }
+
+    fn read_str_ref(&mut self, size: i32) -> Result<&'a str, ProtocolError> {
+        let length = read_string_length(self, size)?;
+        let data = self.read_bytes_ref(length)?;
+
+        check_string(data, size)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::str::FromStr;
-
     use super::*;
+    use bytes::BytesMut;
 
     #[test]
     fn test_read_bool() {
         let mut data_buf = BytesMut::from(&[0x01][..]); // true
-        assert_eq!(data_buf.read_bool().unwrap(), true);
+        assert!(data_buf.read_bool().unwrap());
     }
 
     #[test]
@@ -407,11 +443,127 @@ mod tests {
         assert_eq!(data_buf.read_uuid().unwrap(), expected_uuid);
     }
 
+    #[test]
+    fn test_read_past_end() {
+        let mut data_buf = BytesMut::from(&[0x00, 0x01][..]);
+        assert_eq!(
+            data_buf.read_i32(),
+            Err(ProtocolError::UnexpectedEof {
+                needed: 4,
+                available: 2
+            })
+        );
+    }
+
+    #[test]
+    fn test_read_var_int_too_long() {
+        let mut data_buf = BytesMut::from(&[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01][..]);
+        assert_eq!(data_buf.read_var_int(), Err(ProtocolError::VarIntTooLong));
+    }
+
+    #[test]
+    fn test_read_invalid_strings() {
+        let mut data_buf = BytesMut::from(&[0x02, 0xC3, 0x28][..]);
+        assert_eq!(data_buf.read_full_string(), Err(ProtocolError::InvalidUtf8));
+
+        //Too many characters, even though each fits in a byte
+        let mut data_buf = BytesMut::from(&[0x03, b'a', b'b', b'c'][..]);
+        assert_eq!(
+            data_buf.read_string(2),
+            Err(ProtocolError::StringTooLong { max: 2, actual: 3 })
+        );
+
+        //Too many bytes to even be read
+        let mut data_buf = BytesMut::from(&[0x07][..]);
+        assert_eq!(
+            data_buf.read_string(2),
+            Err(ProtocolError::StringTooLong { max: 6, actual: 7 })
+        );
+    }
+
+    #[test]
+    fn test_read_invalid_option_and_array() {
+        let mut data_buf = BytesMut::from(&[0x01][..]);
+        assert!(data_buf.read_option(|buffer| buffer.read_u8()).is_err());
+
+        let mut data_buf = BytesMut::from(&[0x7F, 0x00][..]);
+        assert_eq!(
+            data_buf.read_array(|buffer| buffer.read_u8()),
+            Err(ProtocolError::InvalidLength { length: 127, max: 1 })
+        );
+    }
+
+    #[test]
+    fn test_read_other_buffers() {
+        let mut data_buf = Bytes::from_static(&[0x05, b'H', b'e', b'l', b'l', b'o']);
+        assert_eq!(data_buf.read_string(5).unwrap(), "Hello");
+
+        let mut data_buf = &[0xDD, 0xC7, 0x01][..];
+        assert_eq!(data_buf.read_var_int().unwrap(), 25565);
+        assert!(data_buf.is_empty());
+
+        //A VarInt split across two chunks
+        let mut data_buf = (&[0xDD, 0xC7][..]).chain(&[0x01, 0x2A][..]);
+        assert_eq!(data_buf.read_var_int().unwrap(), 25565);
+        assert_eq!(data_buf.read_u8().unwrap(), 42);
+    }
+
+    #[test]
+    fn test_read_refs() {
+        let data = [0x05, b'H', b'e', b'l', b'l', b'o', 0x01, 0x02, 0x03];
+        let mut data_buf = &data[..];
+
+        let value = data_buf.read_str_ref(5).unwrap();
+        assert_eq!(value, "Hello");
+        assert_eq!(value.as_ptr(), data[1..].as_ptr());
+
+        assert_eq!(data_buf.read_bytes_ref(2).unwrap(), &[0x01, 0x02]);
+        assert_eq!(
+            data_buf.read_bytes_ref(2),
+            Err(ProtocolError::UnexpectedEof {
+                needed: 2,
+                available: 1
+            })
+        );
+        assert_eq!(data_buf, &[0x03]);
+
+        let mut data_buf = &[0x02, 0xC3, 0x28][..];
+        assert_eq!(data_buf.read_str_ref(2), Err(ProtocolError::InvalidUtf8));
+    }
+
     #[test]
     fn test_read_bitset() {
+        let mut data_buf = BytesMut::from(&[0x01, 0, 0, 0, 0, 0, 0, 0, 0b10101010][..]);
+        assert_eq!(data_buf.read_bitset().unwrap(), vec![0b10101010]);
+
+        let mut data_buf = BytesMut::from(&[0x02, 0, 0, 0, 0, 0, 0, 0, 0b10101010][..]);
+        assert_eq!(
+            data_buf.read_bitset(),
+            Err(ProtocolError::InvalidLength { length: 2, max: 1 })
+        );
+    }
+
+    #[test]
+    fn test_copy_buffer_to_slice() {
+        let mut data_buf = BytesMut::from(&[1, 2, 3][..]);
+        let mut data = [0; 2];
+
+        data_buf.copy_buffer_to_slice(&mut data).unwrap();
+        assert_eq!(data, [1, 2]);
+        assert_eq!(
+            data_buf.copy_buffer_to_slice(&mut data),
+            Err(ProtocolError::UnexpectedEof {
+                needed: 2,
+                available: 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_read_fixed_bitset() {
         let mut data_buf = BytesMut::from(&[0b10101010, 0b11110000][..]); // Example bitset data
         assert_eq!(
-            data_buf.read_bitset(2).unwrap(),
+            data_buf.read_fixed_bitset(10).unwrap(),
             Bytes::from(&[0b10101010, 0b11110000][..])
         );
     }
@@ -0,0 +1,819 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use flate2::read::{MultiGzDecoder, ZlibDecoder};
+use flate2::write::{GzEncoder, ZlibEncoder};
+use flate2::Compression;
+use xxhash_rust::xxh32::xxh32;
+
+use crate::nbt::{Compound, NbtBufferReaderExt, NbtBufferWriterExt, NbtLimits, Tag};
+use crate::storage::StorageError;
+
+pub const SECTOR_SIZE: usize = 4096;
+//The chunk locations followed by their timestamps
+const HEADER_SECTORS: usize = 2;
+const REGION_CHUNKS: usize = 32 * 32;
+//The header only has a byte for the sector count, so chunks this large go in their own .mcc file
+const MAX_CHUNK_SECTORS: usize = 256;
+//Set on the compression type of chunks stored in a .mcc file
+const EXTERNAL_FLAG: u8 = 0x80;
+//Like vanilla, since every open region holds on to a file handle
+const MAX_OPEN_REGIONS: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ChunkPos {
+    pub x: i32,
+    pub z: i32,
+}
+
+impl ChunkPos {
+    pub fn new(x: i32, z: i32) -> Self {
+        Self { x, z }
+    }
+
+    //The region file holding this chunk, 32 by 32 chunks each
+    pub fn region(&self) -> (i32, i32) {
+        (self.x >> 5, self.z >> 5)
+    }
+
+    fn region_index(&self) -> usize {
+        ((self.x & 31) + (self.z & 31) * 32) as usize
+    }
+}
+
+//How chunks are compressed in region files, with vanilla's IDs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RegionCompression {
+    Gzip,
+    //What vanilla writes unless region-file-compression says otherwise
+    #[default]
+    Zlib,
+    None,
+    Lz4,
+}
+
+impl RegionCompression {
+    fn id(self) -> u8 {
+        match self {
+            RegionCompression::Gzip => 1,
+            RegionCompression::Zlib => 2,
+            RegionCompression::None => 3,
+            RegionCompression::Lz4 => 4,
+        }
+    }
+
+    fn from_id(id: u8) -> Option<Self> {
+        match id {
+            1 => Some(RegionCompression::Gzip),
+            2 => Some(RegionCompression::Zlib),
+            3 => Some(RegionCompression::None),
+            4 => Some(RegionCompression::Lz4),
+            _ => None,
+        }
+    }
+
+    fn compress(self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            RegionCompression::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            RegionCompression::Zlib => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            RegionCompression::None => Ok(data.to_vec()),
+            RegionCompression::Lz4 => Ok(lz4_block_compress(data)),
+        }
+    }
+
+    fn decompress(self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut decompressed = Vec::new();
+        match self {
+            RegionCompression::Gzip => {
+                MultiGzDecoder::new(data).read_to_end(&mut decompressed)?;
+            }
+            RegionCompression::Zlib => {
+                ZlibDecoder::new(data).read_to_end(&mut decompressed)?;
+            }
+            RegionCompression::None => decompressed.extend_from_slice(data),
+            RegionCompression::Lz4 => decompressed = lz4_block_decompress(data)?,
+        }
+
+        Ok(decompressed)
+    }
+}
+
+//Vanilla's LZ4 chunks use lz4-java's block stream format rather than the standard frame format.
+//Every block is a header followed by its LZ4 compressed or raw data, and an empty block ends the stream.
+const LZ4_MAGIC: &[u8; 8] = b"LZ4Block";
+const LZ4_HEADER_SIZE: usize = LZ4_MAGIC.len() + 1 + 4 + 4 + 4;
+const LZ4_METHOD_RAW: u8 = 0x10;
+const LZ4_METHOD_LZ4: u8 = 0x20;
+//Block sizes are powers of two, stored as an offset from 2^10
+const LZ4_BLOCK_SIZE_BASE: u32 = 10;
+const LZ4_BLOCK_SIZE: usize = 1 << 16;
+const LZ4_CHECKSUM_SEED: u32 = 0x9747B28C;
+
+//lz4-java only keeps the lowest 28 bits of the xxHash
+fn lz4_checksum(data: &[u8]) -> u32 {
+    xxh32(data, LZ4_CHECKSUM_SEED) & 0x0FFF_FFFF
+}
+
+fn lz4_block_compress(data: &[u8]) -> Vec<u8> {
+    let level = (LZ4_BLOCK_SIZE.trailing_zeros() - LZ4_BLOCK_SIZE_BASE) as u8;
+    let mut output = Vec::with_capacity(data.len() / 2 + LZ4_HEADER_SIZE);
+
+    let mut write_block = |method: u8, payload: &[u8], original: &[u8]| {
+        output.extend_from_slice(LZ4_MAGIC);
+        output.push(method | level);
+        output.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        output.extend_from_slice(&(original.len() as u32).to_le_bytes());
+        let checksum = if original.is_empty() {
+            0
+        } else {
+            lz4_checksum(original)
+        };
+        output.extend_from_slice(&checksum.to_le_bytes());
+        output.extend_from_slice(payload);
+    };
+
+    for block in data.chunks(LZ4_BLOCK_SIZE) {
+        let compressed = lz4_flex::block::compress(block);
+        if compressed.len() < block.len() {
+            write_block(LZ4_METHOD_LZ4, &compressed, block);
+        } else {
+            write_block(LZ4_METHOD_RAW, block, block);
+        }
+    }
+    write_block(LZ4_METHOD_RAW, &[], &[]);
+
+    output
+}
+
+fn lz4_block_decompress(mut data: &[u8]) -> io::Result<Vec<u8>> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_owned());
+    let read_u32 = |bytes: &[u8]| u32::from_le_bytes(bytes.try_into().expect("Four bytes"));
+    let mut output = Vec::new();
+
+    //lz4-java also stops at the end of the data when the end block is missing
+    while !data.is_empty() {
+        if data.len() < LZ4_HEADER_SIZE || !data.starts_with(LZ4_MAGIC) {
+            return Err(invalid("Invalid LZ4 block header"));
+        }
+
+        let token = data[8];
+        let max_size = 1usize << (LZ4_BLOCK_SIZE_BASE + (token & 0x0F) as u32);
+        let compressed_size = read_u32(&data[9..13]) as usize;
+        let original_size = read_u32(&data[13..17]) as usize;
+        let checksum = read_u32(&data[17..21]);
+        data = &data[LZ4_HEADER_SIZE..];
+
+        if original_size == 0 && compressed_size == 0 {
+            return match checksum {
+                0 => Ok(output),
+                _ => Err(invalid("Invalid LZ4 end block")),
+            };
+        }
+        if original_size > max_size || compressed_size > data.len() {
+            return Err(invalid("Invalid LZ4 block size"));
+        }
+
+        let (payload, rest) = data.split_at(compressed_size);
+        data = rest;
+
+        let block = match token & 0xF0 {
+            LZ4_METHOD_RAW if compressed_size == original_size => payload.to_vec(),
+            LZ4_METHOD_LZ4 => lz4_flex::block::decompress(payload, original_size)
+                .ok()
+                .filter(|block| block.len() == original_size)
+                .ok_or_else(|| invalid("Invalid LZ4 block"))?,
+            _ => return Err(invalid("Invalid LZ4 block method")),
+        };
+
+        if lz4_checksum(&block) != checksum {
+            return Err(invalid("LZ4 block checksum mismatch"));
+        }
+        output.extend_from_slice(&block);
+    }
+
+    Ok(output)
+}
+
+//A single .mca file holding up to 32 by 32 chunks, each stored in whole 4 KiB sectors.
+//A chunk is always written to free sectors before the header points at it and its old sectors
+//are freed, so a crash at any point leaves either the old or the new chunk behind.
+pub struct RegionFile {
+    path: PathBuf,
+    file: File,
+    //The first sector in the upper three bytes and the sector count in the lowest
+    locations: Vec<u32>,
+    //When each chunk was last saved, in seconds since the epoch
+    timestamps: Vec<u32>,
+    //Which sectors are taken by the header or a chunk
+    used: Vec<bool>,
+    compression: RegionCompression,
+}
+
+impl RegionFile {
+    //Opens the file, creating it when it doesn't exist yet
+    pub fn open(path: &Path, compression: RegionCompression) -> Result<Self, StorageError> {
+        let io = StorageError::io(path);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .map_err(io)?;
+
+        //New and truncated files get an empty header, and a partial last sector is padded
+        let length = file.metadata().map_err(StorageError::io(path))?.len() as usize;
+        let sectors = length.div_ceil(SECTOR_SIZE).max(HEADER_SECTORS);
+        if length < sectors * SECTOR_SIZE {
+            file.set_len((sectors * SECTOR_SIZE) as u64)
+                .map_err(StorageError::io(path))?;
+        }
+
+        let mut header = vec![0; HEADER_SECTORS * SECTOR_SIZE];
+        file.read_exact(&mut header)
+            .map_err(StorageError::io(path))?;
+        let entries = header
+            .chunks_exact(4)
+            .map(|entry| u32::from_be_bytes(entry.try_into().expect("Four bytes")));
+        let mut locations: Vec<u32> = entries.clone().take(REGION_CHUNKS).collect();
+        let timestamps = entries.skip(REGION_CHUNKS).collect();
+
+        let mut used = vec![false; sectors];
+        used[..HEADER_SECTORS].fill(true);
+
+        //Chunks pointing outside the file are left out like vanilla does, until they're written again
+        for (index, location) in locations.iter_mut().enumerate() {
+            if *location == 0 {
+                continue;
+            }
+
+            let (offset, count) = split_location(*location);
+            if offset < HEADER_SECTORS || count == 0 || offset + count > sectors {
+                println!(
+                    "Ignoring chunk {} of {} with invalid sectors {}..{}",
+                    index,
+                    path.display(),
+                    offset,
+                    offset + count
+                );
+                *location = 0;
+                continue;
+            }
+
+            used[offset..offset + count].fill(true);
+        }
+
+        Ok(Self {
+            path: path.to_owned(),
+            file,
+            locations,
+            timestamps,
+            used,
+            compression,
+        })
+    }
+
+    pub fn has_chunk(&self, pos: ChunkPos) -> bool {
+        self.locations[pos.region_index()] != 0
+    }
+
+    //When the chunk was last saved, in seconds since the epoch
+    pub fn timestamp(&self, pos: ChunkPos) -> Option<u32> {
+        self.has_chunk(pos)
+            .then(|| self.timestamps[pos.region_index()])
+    }
+
+    fn external_path(&self, pos: ChunkPos) -> PathBuf {
+        self.path
+            .with_file_name(format!("c.{}.{}.mcc", pos.x, pos.z))
+    }
+
+    fn damaged(&self, pos: ChunkPos, reason: impl Into<String>) -> StorageError {
+        StorageError::DamagedRegion {
+            path: self.path.clone(),
+            reason: format!("Chunk {}, {}: {}", pos.x, pos.z, reason.into()),
+        }
+    }
+
+    pub fn read_chunk(&mut self, pos: ChunkPos) -> Result<Option<Compound>, StorageError> {
+        let location = self.locations[pos.region_index()];
+        if location == 0 {
+            return Ok(None);
+        }
+
+        let (offset, count) = split_location(location);
+        let mut sectors = vec![0; count * SECTOR_SIZE];
+        self.file
+            .seek(SeekFrom::Start((offset * SECTOR_SIZE) as u64))
+            .and_then(|_| self.file.read_exact(&mut sectors))
+            .map_err(StorageError::io(&self.path))?;
+
+        //The length counts the compression type byte but not itself
+        let length = u32::from_be_bytes(sectors[..4].try_into().expect("Four bytes")) as usize;
+        if length == 0 || length > sectors.len() - 4 {
+            return Err(self.damaged(pos, format!("{} bytes don't fit {} sectors", length, count)));
+        }
+
+        let compression_id = sectors[4];
+        let compressed = if compression_id & EXTERNAL_FLAG != 0 {
+            let path = self.external_path(pos);
+            fs::read(&path).map_err(StorageError::io(&path))?
+        } else {
+            sectors.truncate(4 + length);
+            sectors.split_off(5)
+        };
+
+        let compression = RegionCompression::from_id(compression_id & !EXTERNAL_FLAG)
+            .ok_or_else(|| self.damaged(pos, format!("Unknown compression {}", compression_id)))?;
+        let data = compression
+            .decompress(&compressed)
+            .map_err(|error| self.damaged(pos, error.to_string()))?;
+
+        match (&data[..]).read_nbt(NbtLimits::FILE) {
+            Ok((_, Tag::Compound(chunk))) => Ok(Some(chunk)),
+            Ok((_, tag)) => Err(self.damaged(pos, format!("Root is tag {}", tag.id()))),
+            Err(error) => Err(self.damaged(pos, error.to_string())),
+        }
+    }
+
+    pub fn write_chunk(&mut self, pos: ChunkPos, chunk: &Compound) -> Result<(), StorageError> {
+        let mut data = Vec::new();
+        data.write_nbt_compound("", chunk)
+            .map_err(|source| StorageError::Nbt {
+                path: self.path.clone(),
+                source,
+            })?;
+        let compressed = self
+            .compression
+            .compress(&data)
+            .map_err(StorageError::io(&self.path))?;
+
+        let external_path = self.external_path(pos);
+        let compression_id = self.compression.id();
+        let sector_count = (5 + compressed.len()).div_ceil(SECTOR_SIZE);
+
+        let mut sectors = Vec::with_capacity(sector_count * SECTOR_SIZE);
+        let external = sector_count >= MAX_CHUNK_SECTORS;
+        if external {
+            write_atomically(&external_path, &compressed)?;
+            sectors.extend_from_slice(&1u32.to_be_bytes());
+            sectors.push(compression_id | EXTERNAL_FLAG);
+        } else {
+            sectors.extend_from_slice(&(compressed.len() as u32 + 1).to_be_bytes());
+            sectors.push(compression_id);
+            sectors.extend_from_slice(&compressed);
+        }
+        sectors.resize(sectors.len().div_ceil(SECTOR_SIZE) * SECTOR_SIZE, 0);
+
+        let count = sectors.len() / SECTOR_SIZE;
+        let offset = self.allocate(count);
+        self.file
+            .seek(SeekFrom::Start((offset * SECTOR_SIZE) as u64))
+            .and_then(|_| self.file.write_all(&sectors))
+            .and_then(|_| self.file.sync_data())
+            .map_err(StorageError::io(&self.path))?;
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs() as u32);
+        let old = self.locations[pos.region_index()];
+        self.set_location(pos, ((offset as u32) << 8) | count as u32, timestamp)?;
+        self.free(old);
+
+        if !external {
+            remove_if_exists(&external_path)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn delete_chunk(&mut self, pos: ChunkPos) -> Result<(), StorageError> {
+        let old = self.locations[pos.region_index()];
+        self.set_location(pos, 0, 0)?;
+        self.free(old);
+
+        remove_if_exists(&self.external_path(pos))
+    }
+
+    fn set_location(
+        &mut self,
+        pos: ChunkPos,
+        location: u32,
+        timestamp: u32,
+    ) -> Result<(), StorageError> {
+        let index = pos.region_index();
+        self.locations[index] = location;
+        self.timestamps[index] = timestamp;
+
+        let mut write_entry = |position: usize, value: u32| {
+            self.file
+                .seek(SeekFrom::Start(position as u64))
+                .and_then(|_| self.file.write_all(&value.to_be_bytes()))
+        };
+        write_entry(index * 4, location)
+            .and_then(|_| write_entry(SECTOR_SIZE + index * 4, timestamp))
+            .and_then(|_| self.file.sync_data())
+            .map_err(StorageError::io(&self.path))
+    }
+
+    //The first run of free sectors that's long enough, or the end of the file
+    fn allocate(&mut self, count: usize) -> usize {
+        let mut run = 0;
+        for sector in HEADER_SECTORS..self.used.len() {
+            if self.used[sector] {
+                run = 0;
+                continue;
+            }
+
+            run += 1;
+            if run == count {
+                let start = sector + 1 - count;
+                self.used[start..=sector].fill(true);
+                return start;
+            }
+        }
+
+        let start = self.used.len() - run;
+        self.used.resize(start + count, true);
+        self.used[start..].fill(true);
+        start
+    }
+
+    fn free(&mut self, location: u32) {
+        if location != 0 {
+            let (offset, count) = split_location(location);
+            self.used[offset..offset + count].fill(false);
+        }
+    }
+}
+
+fn split_location(location: u32) -> (usize, usize) {
+    ((location >> 8) as usize, (location & 0xFF) as usize)
+}
+
+fn write_atomically(path: &Path, data: &[u8]) -> Result<(), StorageError> {
+    let mut temp_name = path.file_name().unwrap_or_default().to_owned();
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+
+    File::create(&temp_path)
+        .and_then(|mut file| file.write_all(data).and_then(|_| file.sync_all()))
+        .map_err(StorageError::io(&temp_path))?;
+    fs::rename(&temp_path, path).map_err(StorageError::io(path))
+}
+
+fn remove_if_exists(path: &Path) -> Result<(), StorageError> {
+    match fs::remove_file(path) {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => Err(StorageError::io(path)(error)),
+        _ => Ok(()),
+    }
+}
+
+//Empty until the first task to need the region opens it
+type SharedRegion = Arc<Mutex<Option<RegionFile>>>;
+
+//Every region file of a dimension, opened as chunks in them are needed. Each region has its own
+//lock, so tasks working on different regions never wait for each other.
+pub struct RegionStorage {
+    directory: PathBuf,
+    compression: RegionCompression,
+    regions: Mutex<HashMap<(i32, i32), SharedRegion>>,
+}
+
+impl RegionStorage {
+    pub fn new(directory: impl Into<PathBuf>, compression: RegionCompression) -> Self {
+        Self {
+            directory: directory.into(),
+            compression,
+            regions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    //Runs the function on the region holding the chunk. Only creates the region file when it's
+    //going to be written to.
+    fn with_region<T>(
+        &self,
+        pos: ChunkPos,
+        create: bool,
+        function: impl FnOnce(&mut RegionFile) -> Result<T, StorageError>,
+    ) -> Result<Option<T>, StorageError> {
+        let key = pos.region();
+        let path = self.directory.join(format!("r.{}.{}.mca", key.0, key.1));
+
+        let existing = self.regions.lock().unwrap().get(&key).cloned();
+        let region = match existing {
+            Some(region) => region,
+            None => {
+                if !create && !path.exists() {
+                    return Ok(None);
+                }
+                fs::create_dir_all(&self.directory).map_err(StorageError::io(&self.directory))?;
+                self.add_region(key)
+            }
+        };
+
+        //The file is opened under the region's own lock, so only tasks that need the same region
+        //wait for it, and it's never opened twice
+        let mut region = region.lock().unwrap();
+        if region.is_none() {
+            *region = Some(RegionFile::open(&path, self.compression)?);
+        }
+        function(region.as_mut().expect("Opened above")).map(Some)
+    }
+
+    //Another task may have added the region since it was looked up, in which case that one is used
+    fn add_region(&self, key: (i32, i32)) -> SharedRegion {
+        let mut regions = self.regions.lock().unwrap();
+        if let Some(region) = regions.get(&key) {
+            return Arc::clone(region);
+        }
+
+        //Regions still in use by another task are kept open, a second handle could undo their writes
+        if regions.len() >= MAX_OPEN_REGIONS {
+            let unused = regions
+                .iter()
+                .find(|(_, region)| Arc::strong_count(region) == 1)
+                .map(|(&key, _)| key);
+            if let Some(unused) = unused {
+                regions.remove(&unused);
+            }
+        }
+
+        let region = SharedRegion::default();
+        regions.insert(key, Arc::clone(&region));
+        region
+    }
+
+    //Blocks on file IO, async code should use load_chunk instead
+    pub fn read_chunk(&self, pos: ChunkPos) -> Result<Option<Compound>, StorageError> {
+        Ok(self
+            .with_region(pos, false, |region| region.read_chunk(pos))?
+            .flatten())
+    }
+
+    //Blocks on file IO, async code should use save_chunk instead
+    pub fn write_chunk(&self, pos: ChunkPos, chunk: &Compound) -> Result<(), StorageError> {
+        self.with_region(pos, true, |region| region.write_chunk(pos, chunk))
+            .map(drop)
+    }
+
+    pub async fn load_chunk(
+        self: &Arc<Self>,
+        pos: ChunkPos,
+    ) -> Result<Option<Compound>, StorageError> {
+        let storage = Arc::clone(self);
+        tokio::task::spawn_blocking(move || storage.read_chunk(pos))
+            .await
+            .expect("Reading a chunk panicked")
+    }
+
+    pub async fn save_chunk(
+        self: &Arc<Self>,
+        pos: ChunkPos,
+        chunk: Compound,
+    ) -> Result<(), StorageError> {
+        let storage = Arc::clone(self);
+        tokio::task::spawn_blocking(move || storage.write_chunk(pos, &chunk))
+            .await
+            .expect("Writing a chunk panicked")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("copper-region-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        path
+    }
+
+    fn chunk(pos: ChunkPos, size: usize) -> Compound {
+        Compound::from([
+            ("xPos".to_owned(), Tag::Int(pos.x)),
+            ("zPos".to_owned(), Tag::Int(pos.z)),
+            ("Status".to_owned(), Tag::from("minecraft:full")),
+            ("Data".to_owned(), Tag::ByteArray(vec![7; size])),
+        ])
+    }
+
+    #[test]
+    fn test_lz4_block_stream() {
+        let data: Vec<u8> = (0..200_000u32).map(|value| (value % 251) as u8).collect();
+        let compressed = lz4_block_compress(&data);
+
+        assert!(compressed.starts_with(b"LZ4Block\x26"));
+        assert!(compressed.len() < data.len());
+        assert!(compressed.ends_with(b"LZ4Block\x16\0\0\0\0\0\0\0\0\0\0\0\0"));
+        assert_eq!(lz4_block_decompress(&compressed).unwrap(), data);
+
+        let mut damaged = compressed.clone();
+        damaged[LZ4_HEADER_SIZE + 10] ^= 0xFF;
+        assert!(lz4_block_decompress(&damaged).is_err());
+    }
+
+    #[test]
+    fn test_lz4_raw_block() {
+        //Too short to compress, so it's stored as it is
+        let compressed = lz4_block_compress(b"abc");
+
+        assert_eq!(compressed[8], LZ4_METHOD_RAW | 6);
+        assert_eq!(&compressed[LZ4_HEADER_SIZE..LZ4_HEADER_SIZE + 3], b"abc");
+        assert_eq!(lz4_block_decompress(&compressed).unwrap(), b"abc");
+    }
+
+    #[test]
+    fn test_read_write() {
+        let directory = temp_dir("read-write");
+        let path = directory.join("r.-1.0.mca");
+
+        let compressions = [
+            RegionCompression::Gzip,
+            RegionCompression::Zlib,
+            RegionCompression::None,
+            RegionCompression::Lz4,
+        ];
+        for (index, compression) in compressions.into_iter().enumerate() {
+            let pos = ChunkPos::new(-32 + index as i32, 31);
+            let mut region = RegionFile::open(&path, compression).unwrap();
+            assert_eq!(region.read_chunk(pos).unwrap(), None);
+
+            region.write_chunk(pos, &chunk(pos, 100)).unwrap();
+            assert!(region.timestamp(pos).unwrap() > 0);
+        }
+
+        //Each chunk is read with the compression it was written with
+        let mut region = RegionFile::open(&path, RegionCompression::Zlib).unwrap();
+        for index in 0..compressions.len() {
+            let pos = ChunkPos::new(-32 + index as i32, 31);
+            assert_eq!(region.read_chunk(pos).unwrap(), Some(chunk(pos, 100)));
+        }
+        assert_eq!(fs::metadata(&path).unwrap().len() as usize % SECTOR_SIZE, 0);
+
+        region.delete_chunk(ChunkPos::new(-32, 31)).unwrap();
+        let mut region = RegionFile::open(&path, RegionCompression::Zlib).unwrap();
+        assert!(!region.has_chunk(ChunkPos::new(-32, 31)));
+        assert!(region.read_chunk(ChunkPos::new(-31, 31)).unwrap().is_some());
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_sector_reuse() {
+        let directory = temp_dir("sectors");
+        let path = directory.join("r.0.0.mca");
+        let mut region = RegionFile::open(&path, RegionCompression::None).unwrap();
+        let (first, second) = (ChunkPos::new(0, 0), ChunkPos::new(1, 0));
+
+        region.write_chunk(first, &chunk(first, 10_000)).unwrap();
+        region.write_chunk(second, &chunk(second, 100)).unwrap();
+        assert_eq!(split_location(region.locations[0]), (2, 3));
+        assert_eq!(split_location(region.locations[1]), (5, 1));
+
+        //The new copy never overwrites the old one, which is freed afterwards
+        region.write_chunk(first, &chunk(first, 100)).unwrap();
+        assert_eq!(split_location(region.locations[0]), (6, 1));
+
+        region.write_chunk(second, &chunk(second, 5_000)).unwrap();
+        assert_eq!(split_location(region.locations[1]), (2, 2));
+        let third = ChunkPos::new(2, 0);
+        region.write_chunk(third, &chunk(third, 100)).unwrap();
+        assert_eq!(split_location(region.locations[2]), (4, 1));
+        assert_eq!(fs::metadata(&path).unwrap().len() as usize, 7 * SECTOR_SIZE);
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_oversized_chunk() {
+        let directory = temp_dir("oversized");
+        let path = directory.join("r.0.0.mca");
+        let external = directory.join("c.3.4.mcc");
+        let pos = ChunkPos::new(3, 4);
+
+        let mut region = RegionFile::open(&path, RegionCompression::None).unwrap();
+        region.write_chunk(pos, &chunk(pos, 1_100_000)).unwrap();
+        assert!(external.exists());
+        assert_eq!(split_location(region.locations[pos.region_index()]).1, 1);
+
+        let mut region = RegionFile::open(&path, RegionCompression::None).unwrap();
+        assert_eq!(region.read_chunk(pos).unwrap(), Some(chunk(pos, 1_100_000)));
+
+        region.write_chunk(pos, &chunk(pos, 10)).unwrap();
+        assert!(!external.exists());
+        assert_eq!(region.read_chunk(pos).unwrap(), Some(chunk(pos, 10)));
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_damaged_header() {
+        let directory = temp_dir("damaged");
+        let path = directory.join("r.0.0.mca");
+
+        let mut header = vec![0; HEADER_SECTORS * SECTOR_SIZE];
+        //Chunk 0 points into the header and chunk 1 past the end of the file
+        header[..4].copy_from_slice(&0x0101u32.to_be_bytes());
+        header[4..8].copy_from_slice(&0x0901u32.to_be_bytes());
+        //A truncated sector, which gets padded
+        header.extend_from_slice(&[0; 100]);
+        fs::write(&path, header).unwrap();
+
+        let mut region = RegionFile::open(&path, RegionCompression::Zlib).unwrap();
+        assert!(!region.has_chunk(ChunkPos::new(0, 0)));
+        assert!(!region.has_chunk(ChunkPos::new(1, 0)));
+        assert_eq!(region.read_chunk(ChunkPos::new(1, 0)).unwrap(), None);
+        assert_eq!(fs::metadata(&path).unwrap().len() as usize, 3 * SECTOR_SIZE);
+
+        //A length that doesn't fit the chunk's sectors
+        region
+            .write_chunk(ChunkPos::new(2, 0), &chunk(ChunkPos::new(2, 0), 10))
+            .unwrap();
+        let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.seek(SeekFrom::Start(2 * SECTOR_SIZE as u64)).unwrap();
+        file.write_all(&[0xFF; 4]).unwrap();
+        assert!(matches!(
+            region.read_chunk(ChunkPos::new(2, 0)),
+            Err(StorageError::DamagedRegion { .. })
+        ));
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_opening_blocks_only_its_region() {
+        let directory = temp_dir("opening");
+        let storage = Arc::new(RegionStorage::new(&directory, RegionCompression::Zlib));
+
+        //As if another task were still opening region 1,1
+        let opening = storage.add_region((1, 1));
+        let guard = opening.lock().unwrap();
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let other = Arc::clone(&storage);
+        std::thread::spawn(move || {
+            let pos = ChunkPos::new(0, 0);
+            sender
+                .send(other.write_chunk(pos, &chunk(pos, 10)))
+                .unwrap();
+        });
+        receiver
+            .recv_timeout(std::time::Duration::from_secs(10))
+            .unwrap()
+            .unwrap();
+
+        drop(guard);
+        let pos = ChunkPos::new(32, 32);
+        storage.write_chunk(pos, &chunk(pos, 10)).unwrap();
+        assert!(opening.lock().unwrap().is_some());
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_concurrent_access() {
+        let directory = temp_dir("concurrent");
+        let storage = Arc::new(RegionStorage::new(&directory, RegionCompression::Zlib));
+
+        assert_eq!(storage.load_chunk(ChunkPos::new(0, 0)).await.unwrap(), None);
+        assert!(!directory.join("r.0.0.mca").exists());
+
+        let tasks: Vec<_> = (0..64)
+            .map(|index| {
+                let storage = Arc::clone(&storage);
+                let pos = ChunkPos::new(index % 40 - 20, index / 40);
+                tokio::spawn(async move { storage.save_chunk(pos, chunk(pos, 5_000)).await })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+
+        let storage = Arc::new(RegionStorage::new(&directory, RegionCompression::Zlib));
+        for index in 0..64 {
+            let pos = ChunkPos::new(index % 40 - 20, index / 40);
+            assert_eq!(
+                storage.load_chunk(pos).await.unwrap(),
+                Some(chunk(pos, 5_000))
+            );
+        }
+        assert!(directory.join("r.-1.0.mca").exists());
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
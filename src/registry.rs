@@ -0,0 +1,478 @@
+use crate::packet::configuration::{KnownPack, RegistryData, RegistryEntry};
+use crate::packet::version::ProtocolVersion;
+
+//A registry the client needs before it can enter play, with every entry ID in order.
+//The entry data itself comes from the vanilla data pack the client already has.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Registry {
+    pub id: String,
+    pub entries: Vec<String>,
+}
+
+impl Registry {
+    pub fn new(id: &str, entries: &[&str]) -> Self {
+        Self {
+            id: id.to_owned(),
+            entries: entries.iter().map(|entry| entry.to_string()).collect(),
+        }
+    }
+
+    //Registry Data listing every entry, leaving the data out so the client loads it from its known packs
+    pub fn to_packet(&self) -> RegistryData {
+        RegistryData {
+            registry_id: self.id.clone(),
+            entries: self
+                .entries
+                .iter()
+                .map(|entry| RegistryEntry {
+                    entry_id: entry.clone(),
+                    data: None,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn id_of(&self, entry: &str) -> Option<usize> {
+        self.entries.iter().position(|id| id == entry)
+    }
+}
+
+//Every registry synchronized during configuration
+#[derive(Debug, Clone, PartialEq)]
+pub struct Registries {
+    pub registries: Vec<Registry>,
+}
+
+impl Registries {
+    //The data pack every vanilla client ships with, holding the data for each entry.
+    //Every release of the protocol version has its own, any of them will do.
+    pub fn core_packs(version: &ProtocolVersion) -> Vec<KnownPack> {
+        version
+            .releases
+            .iter()
+            .map(|release| KnownPack {
+                namespace: "minecraft".to_owned(),
+                id: "core".to_owned(),
+                version: release.to_string(),
+            })
+            .collect()
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Registry> {
+        self.registries.iter().find(|registry| registry.id == id)
+    }
+
+    pub fn to_packets(&self) -> impl Iterator<Item = RegistryData> + '_ {
+        self.registries.iter().map(Registry::to_packet)
+    }
+
+    pub fn vanilla() -> Self {
+        Self {
+            registries: vec![
+                Registry::new("minecraft:dimension_type", DIMENSION_TYPES),
+                Registry::new("minecraft:worldgen/biome", BIOMES),
+                Registry::new("minecraft:chat_type", CHAT_TYPES),
+                Registry::new("minecraft:trim_pattern", TRIM_PATTERNS),
+                Registry::new("minecraft:trim_material", TRIM_MATERIALS),
+                Registry::new("minecraft:wolf_variant", WOLF_VARIANTS),
+                Registry::new("minecraft:painting_variant", PAINTING_VARIANTS),
+                Registry::new("minecraft:damage_type", DAMAGE_TYPES),
+                Registry::new("minecraft:banner_pattern", BANNER_PATTERNS),
+                Registry::new("minecraft:enchantment", ENCHANTMENTS),
+                Registry::new("minecraft:jukebox_song", JUKEBOX_SONGS),
+            ],
+        }
+    }
+}
+
+impl Default for Registries {
+    fn default() -> Self {
+        Self::vanilla()
+    }
+}
+
+const DIMENSION_TYPES: &[&str] = &[
+    "minecraft:overworld",
+    "minecraft:overworld_caves",
+    "minecraft:the_end",
+    "minecraft:the_nether",
+];
+
+pub(crate) const BIOMES: &[&str] = &[
+    "minecraft:badlands",
+    "minecraft:bamboo_jungle",
+    "minecraft:basalt_deltas",
+    "minecraft:beach",
+    "minecraft:birch_forest",
+    "minecraft:cherry_grove",
+    "minecraft:cold_ocean",
+    "minecraft:crimson_forest",
+    "minecraft:dark_forest",
+    "minecraft:deep_cold_ocean",
+    "minecraft:deep_dark",
+    "minecraft:deep_frozen_ocean",
+    "minecraft:deep_lukewarm_ocean",
+    "minecraft:deep_ocean",
+    "minecraft:desert",
+    "minecraft:dripstone_caves",
+    "minecraft:end_barrens",
+    "minecraft:end_highlands",
+    "minecraft:end_midlands",
+    "minecraft:eroded_badlands",
+    "minecraft:flower_forest",
+    "minecraft:forest",
+    "minecraft:frozen_ocean",
+    "minecraft:frozen_peaks",
+    "minecraft:frozen_river",
+    "minecraft:grove",
+    "minecraft:ice_spikes",
+    "minecraft:jagged_peaks",
+    "minecraft:jungle",
+    "minecraft:lukewarm_ocean",
+    "minecraft:lush_caves",
+    "minecraft:mangrove_swamp",
+    "minecraft:meadow",
+    "minecraft:mushroom_fields",
+    "minecraft:nether_wastes",
+    "minecraft:ocean",
+    "minecraft:old_growth_birch_forest",
+    "minecraft:old_growth_pine_taiga",
+    "minecraft:old_growth_spruce_taiga",
+    "minecraft:plains",
+    "minecraft:river",
+    "minecraft:savanna",
+    "minecraft:savanna_plateau",
+    "minecraft:small_end_islands",
+    "minecraft:snowy_beach",
+    "minecraft:snowy_plains",
+    "minecraft:snowy_slopes",
+    "minecraft:snowy_taiga",
+    "minecraft:soul_sand_valley",
+    "minecraft:sparse_jungle",
+    "minecraft:stony_peaks",
+    "minecraft:stony_shore",
+    "minecraft:sunflower_plains",
+    "minecraft:swamp",
+    "minecraft:taiga",
+    "minecraft:the_end",
+    "minecraft:the_void",
+    "minecraft:warm_ocean",
+    "minecraft:warped_forest",
+    "minecraft:windswept_forest",
+    "minecraft:windswept_gravelly_hills",
+    "minecraft:windswept_hills",
+    "minecraft:windswept_savanna",
+    "minecraft:wooded_badlands",
+];
+
+const CHAT_TYPES: &[&str] = &[
+    "minecraft:chat",
+    "minecraft:emote_command",
+    "minecraft:msg_command_incoming",
+    "minecraft:msg_command_outgoing",
+    "minecraft:say_command",
+    "minecraft:team_msg_command_incoming",
+    "minecraft:team_msg_command_outgoing",
+];
+
+const TRIM_PATTERNS: &[&str] = &[
+    "minecraft:bolt",
+    "minecraft:coast",
+    "minecraft:dune",
+    "minecraft:eye",
+    "minecraft:flow",
+    "minecraft:host",
+    "minecraft:raiser",
+    "minecraft:rib",
+    "minecraft:sentry",
+    "minecraft:shaper",
+    "minecraft:silence",
+    "minecraft:snout",
+    "minecraft:spire",
+    "minecraft:tide",
+    "minecraft:vex",
+    "minecraft:ward",
+    "minecraft:wayfinder",
+    "minecraft:wild",
+];
+
+const TRIM_MATERIALS: &[&str] = &[
+    "minecraft:amethyst",
+    "minecraft:copper",
+    "minecraft:diamond",
+    "minecraft:emerald",
+    "minecraft:gold",
+    "minecraft:iron",
+    "minecraft:lapis",
+    "minecraft:netherite",
+    "minecraft:quartz",
+    "minecraft:redstone",
+];
+
+const WOLF_VARIANTS: &[&str] = &[
+    "minecraft:ashen",
+    "minecraft:black",
+    "minecraft:chestnut",
+    "minecraft:pale",
+    "minecraft:rusty",
+    "minecraft:snowy",
+    "minecraft:spotted",
+    "minecraft:striped",
+    "minecraft:woods",
+];
+
+const PAINTING_VARIANTS: &[&str] = &[
+    "minecraft:alban",
+    "minecraft:aztec",
+    "minecraft:aztec2",
+    "minecraft:backyard",
+    "minecraft:baroque",
+    "minecraft:bomb",
+    "minecraft:bouquet",
+    "minecraft:burning_skull",
+    "minecraft:bust",
+    "minecraft:cavebird",
+    "minecraft:changing",
+    "minecraft:cotan",
+    "minecraft:courbet",
+    "minecraft:creebet",
+    "minecraft:donkey_kong",
+    "minecraft:earth",
+    "minecraft:endboss",
+    "minecraft:fern",
+    "minecraft:fighters",
+    "minecraft:finding",
+    "minecraft:fire",
+    "minecraft:graham",
+    "minecraft:humble",
+    "minecraft:kebab",
+    "minecraft:lowmist",
+    "minecraft:match",
+    "minecraft:meditative",
+    "minecraft:orb",
+    "minecraft:owlemons",
+    "minecraft:passage",
+    "minecraft:pigscene",
+    "minecraft:plant",
+    "minecraft:pointer",
+    "minecraft:pond",
+    "minecraft:pool",
+    "minecraft:prairie_ride",
+    "minecraft:sea",
+    "minecraft:skeleton",
+    "minecraft:skull_and_roses",
+    "minecraft:stage",
+    "minecraft:sunflowers",
+    "minecraft:sunset",
+    "minecraft:tides",
+    "minecraft:unpacked",
+    "minecraft:void",
+    "minecraft:wanderer",
+    "minecraft:wasteland",
+    "minecraft:water",
+    "minecraft:wind",
+    "minecraft:wither",
+];
+
+const DAMAGE_TYPES: &[&str] = &[
+    "minecraft:arrow",
+    "minecraft:bad_respawn_point",
+    "minecraft:cactus",
+    "minecraft:campfire",
+    "minecraft:cramming",
+    "minecraft:dragon_breath",
+    "minecraft:drown",
+    "minecraft:dry_out",
+    "minecraft:explosion",
+    "minecraft:fall",
+    "minecraft:falling_anvil",
+    "minecraft:falling_block",
+    "minecraft:falling_stalactite",
+    "minecraft:fireball",
+    "minecraft:fireworks",
+    "minecraft:fly_into_wall",
+    "minecraft:freeze",
+    "minecraft:generic",
+    "minecraft:generic_kill",
+    "minecraft:hot_floor",
+    "minecraft:in_fire",
+    "minecraft:in_wall",
+    "minecraft:indirect_magic",
+    "minecraft:lava",
+    "minecraft:lightning_bolt",
+    "minecraft:magic",
+    "minecraft:mob_attack",
+    "minecraft:mob_attack_no_aggro",
+    "minecraft:mob_projectile",
+    "minecraft:on_fire",
+    "minecraft:out_of_world",
+    "minecraft:outside_border",
+    "minecraft:player_attack",
+    "minecraft:player_explosion",
+    "minecraft:sonic_boom",
+    "minecraft:spit",
+    "minecraft:stalagmite",
+    "minecraft:starve",
+    "minecraft:sting",
+    "minecraft:sweet_berry_bush",
+    "minecraft:thorns",
+    "minecraft:thrown",
+    "minecraft:trident",
+    "minecraft:unattributed_fireball",
+    "minecraft:wind_charge",
+    "minecraft:wither",
+    "minecraft:wither_skull",
+];
+
+const BANNER_PATTERNS: &[&str] = &[
+    "minecraft:base",
+    "minecraft:border",
+    "minecraft:bricks",
+    "minecraft:circle",
+    "minecraft:creeper",
+    "minecraft:cross",
+    "minecraft:curly_border",
+    "minecraft:diagonal_left",
+    "minecraft:diagonal_right",
+    "minecraft:diagonal_up_left",
+    "minecraft:diagonal_up_right",
+    "minecraft:flow",
+    "minecraft:flower",
+    "minecraft:globe",
+    "minecraft:gradient",
+    "minecraft:gradient_up",
+    "minecraft:guster",
+    "minecraft:half_horizontal",
+    "minecraft:half_horizontal_bottom",
+    "minecraft:half_vertical",
+    "minecraft:half_vertical_right",
+    "minecraft:mojang",
+    "minecraft:piglin",
+    "minecraft:rhombus",
+    "minecraft:skull",
+    "minecraft:small_stripes",
+    "minecraft:square_bottom_left",
+    "minecraft:square_bottom_right",
+    "minecraft:square_top_left",
+    "minecraft:square_top_right",
+    "minecraft:straight_cross",
+    "minecraft:stripe_bottom",
+    "minecraft:stripe_center",
+    "minecraft:stripe_downleft",
+    "minecraft:stripe_downright",
+    "minecraft:stripe_left",
+    "minecraft:stripe_middle",
+    "minecraft:stripe_right",
+    "minecraft:stripe_top",
+    "minecraft:triangle_bottom",
+    "minecraft:triangle_top",
+    "minecraft:triangles_bottom",
+    "minecraft:triangles_top",
+];
+
+const ENCHANTMENTS: &[&str] = &[
+    "minecraft:aqua_affinity",
+    "minecraft:bane_of_arthropods",
+    "minecraft:binding_curse",
+    "minecraft:blast_protection",
+    "minecraft:breach",
+    "minecraft:channeling",
+    "minecraft:density",
+    "minecraft:depth_strider",
+    "minecraft:efficiency",
+    "minecraft:feather_falling",
+    "minecraft:fire_aspect",
+    "minecraft:fire_protection",
+    "minecraft:flame",
+    "minecraft:fortune",
+    "minecraft:frost_walker",
+    "minecraft:impaling",
+    "minecraft:infinity",
+    "minecraft:knockback",
+    "minecraft:looting",
+    "minecraft:loyalty",
+    "minecraft:luck_of_the_sea",
+    "minecraft:lure",
+    "minecraft:mending",
+    "minecraft:multishot",
+    "minecraft:piercing",
+    "minecraft:power",
+    "minecraft:projectile_protection",
+    "minecraft:protection",
+    "minecraft:punch",
+    "minecraft:quick_charge",
+    "minecraft:respiration",
+    "minecraft:riptide",
+    "minecraft:sharpness",
+    "minecraft:silk_touch",
+    "minecraft:smite",
+    "minecraft:soul_speed",
+    "minecraft:sweeping_edge",
+    "minecraft:swift_sneak",
+    "minecraft:thorns",
+    "minecraft:unbreaking",
+    "minecraft:vanishing_curse",
+    "minecraft:wind_burst",
+];
+
+const JUKEBOX_SONGS: &[&str] = &[
+    "minecraft:11",
+    "minecraft:13",
+    "minecraft:5",
+    "minecraft:blocks",
+    "minecraft:cat",
+    "minecraft:chirp",
+    "minecraft:creator",
+    "minecraft:creator_music_box",
+    "minecraft:far",
+    "minecraft:mall",
+    "minecraft:mellohi",
+    "minecraft:otherside",
+    "minecraft:pigstep",
+    "minecraft:precipice",
+    "minecraft:relic",
+    "minecraft:stal",
+    "minecraft:strad",
+    "minecraft:wait",
+    "minecraft:ward",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::Packet;
+    use bytes::BytesMut;
+
+    #[test]
+    fn test_vanilla_registries() {
+        let registries = Registries::vanilla();
+
+        let biomes = registries.get("minecraft:worldgen/biome").unwrap();
+        assert_eq!(biomes.id_of("minecraft:badlands"), Some(0));
+        assert_eq!(biomes.id_of("minecraft:plains"), Some(39));
+
+        for registry in &registries.registries {
+            let mut sorted = registry.entries.clone();
+            sorted.sort();
+            sorted.dedup();
+            assert_eq!(sorted.len(), registry.entries.len(), "{} has duplicates", registry.id);
+        }
+    }
+
+    #[test]
+    fn test_registry_data_round_trip() {
+        let packet = Registries::vanilla()
+            .get("minecraft:dimension_type")
+            .unwrap()
+            .to_packet();
+
+        let mut buffer = BytesMut::new();
+        packet.encode_for(&mut buffer, &ProtocolVersion::NATIVE).unwrap();
+
+        assert_eq!(
+            RegistryData::decode_for(&mut buffer, &ProtocolVersion::NATIVE).unwrap(),
+            packet
+        );
+        assert!(buffer.is_empty());
+    }
+}
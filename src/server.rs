@@ -1,52 +1,974 @@
-use std::{collections::HashMap, net::SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, ToSocketAddrs};
+use std::str::FromStr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use std::{collections::HashMap, fmt, io};
 
 use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio::time::sleep_until;
 use uuid::Uuid;
 
-use crate::client::Client;
+use crate::auth::{Authenticator, HttpAuthenticator, OfflineAuthenticator, PlayerProfile};
+use crate::block::{BlockRegistry, BlockRegistryError};
+use crate::chunk::{ChunkLayout, ContainerKind};
+use crate::client::{run_connection, Client, ClientHandle, ConnectionId, DisconnectReason};
+use crate::encryption::ServerKeyPair;
+use crate::nbt::Tag;
+use crate::packet::play::SystemChatMessage;
+use crate::properties::{PropertiesError, ServerProperties};
+use crate::packet::{Packet, PacketError, RawPacket};
+use crate::registry::Registries;
+use crate::status::{PlayerSample, StatusResponse};
+use crate::storage::{StorageError, WorldStorage};
+use crate::text::TextComponent;
+use crate::tick::{TickMetrics, TickRateManager, TickScheduler};
 
-struct GlobalConfiguration {
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlobalConfiguration {
+    //An IP address or a host name to listen on, every interface when unset
+    pub server_ip: Option<String>,
+    pub server_port: u16,
+    //Also online mode, players are only authenticated when encryption is enabled
     pub enable_packet_encryption: bool,
     pub enable_packet_compression: bool,
+    //Packets of at least this many bytes get compressed once compression is enabled
+    pub compression_threshold: usize,
+    pub motd: String,
+    pub max_players: u32,
+    //In chunks
+    pub view_distance: u8,
+    pub simulation_distance: u8,
+    pub difficulty: Difficulty,
+    //Game mode of new players
+    pub gamemode: GameMode,
+    pub hardcore: bool,
+    pub level_name: String,
+    pub favicon_path: Option<PathBuf>,
+    //A blocks.json report from the data generator to number block states with instead of the built in one
+    pub blocks_report: Option<PathBuf>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Difficulty {
+    Peaceful,
+    Easy,
+    Normal,
+    Hard,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameMode {
+    Survival,
+    Creative,
+    Adventure,
+    Spectator,
+}
+
+//Both accept their name or their legacy numeric ID, like vanilla
+impl FromStr for Difficulty {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "peaceful" | "0" => Ok(Difficulty::Peaceful),
+            "easy" | "1" => Ok(Difficulty::Easy),
+            "normal" | "2" => Ok(Difficulty::Normal),
+            "hard" | "3" => Ok(Difficulty::Hard),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Difficulty::Peaceful => "peaceful",
+            Difficulty::Easy => "easy",
+            Difficulty::Normal => "normal",
+            Difficulty::Hard => "hard",
+        })
+    }
+}
+
+impl FromStr for GameMode {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "survival" | "0" => Ok(GameMode::Survival),
+            "creative" | "1" => Ok(GameMode::Creative),
+            "adventure" | "2" => Ok(GameMode::Adventure),
+            "spectator" | "3" => Ok(GameMode::Spectator),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for GameMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            GameMode::Survival => "survival",
+            GameMode::Creative => "creative",
+            GameMode::Adventure => "adventure",
+            GameMode::Spectator => "spectator",
+        })
+    }
+}
+
+//Most players listed in the server list hover text, same as vanilla
+const MAX_PLAYER_SAMPLE: usize = 12;
+//Events waiting for the game loop before connection tasks have to wait on it
+const EVENT_QUEUE_SIZE: usize = 4096;
+//How often a watched server.properties is checked for changes
+const PROPERTIES_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+//Vanilla's defaults, so a new server.properties matches the one vanilla would write
 impl Default for GlobalConfiguration {
     fn default() -> Self {
+        Self {
+            server_ip: None,
+            server_port: 25565,
+            enable_packet_encryption: true,
+            enable_packet_compression: true,
+            compression_threshold: 256,
+            motd: "A Copper Server".to_owned(),
+            max_players: 20,
+            view_distance: 10,
+            simulation_distance: 10,
+            difficulty: Difficulty::Easy,
+            gamemode: GameMode::Survival,
+            hardcore: false,
+            level_name: "world".to_owned(),
+            favicon_path: Some(PathBuf::from("server-icon.png")),
+            blocks_report: None,
+        }
+    }
+}
+
+//Server-wide state clients need while processing their packets, shared by every connection task
+pub struct ServerContext {
+    //Only settings that are safe to change at runtime are updated on reload
+    pub global_config: RwLock<GlobalConfiguration>,
+    //Kept up to date by the game loop as players join and leave
+    pub status: RwLock<StatusResponse>,
+    //Only generated when encryption is enabled
+    pub key_pair: Option<ServerKeyPair>,
+    pub authenticator: Box<dyn Authenticator>,
+    //Registries synchronized with every client during configuration
+    pub registries: Registries,
+    //The world named by level-name, relative to the working directory like vanilla
+    pub storage: WorldStorage,
+    //Read once chunks are loaded and sent, which the play state doesn't do yet
+    #[allow(dead_code)]
+    pub blocks: BlockRegistry,
+    //The overworld's height, and the palettes sized for the block states and biomes above
+    #[allow(dead_code)]
+    pub chunk_layout: ChunkLayout,
+}
+
+impl GlobalConfiguration {
+    //Host names are resolved like vanilla does, taking the first address they resolve to
+    pub fn bind_address(&self) -> io::Result<SocketAddr> {
+        let host = match &self.server_ip {
+            Some(host) => host,
+            None => {
+                return Ok(SocketAddr::new(
+                    IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                    self.server_port,
+                ))
+            }
+        };
+
+        (host.as_str(), self.server_port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{} has no addresses", host),
+                )
+            })
+    }
+
+    //Offline and uncompressed, so tests can drive a connection by hand
+    #[cfg(test)]
+    pub fn offline() -> Self {
         Self {
             enable_packet_encryption: false,
             enable_packet_compression: false,
+            ..Default::default()
         }
     }
 }
 
+impl ServerContext {
+    pub fn new(global_config: GlobalConfiguration) -> Result<Self, BlockRegistryError> {
+        let status = RwLock::new(StatusResponse::from_config(&global_config));
+
+        //Encryption is what lets the session server vouch for a player, so it doubles as online mode
+        let (key_pair, authenticator): (_, Box<dyn Authenticator>) =
+            if global_config.enable_packet_encryption {
+                (
+                    Some(ServerKeyPair::generate().expect("Failed to generate the server keypair")),
+                    Box::new(HttpAuthenticator::new()),
+                )
+            } else {
+                (None, Box::new(OfflineAuthenticator))
+            };
+
+        let blocks = match &global_config.blocks_report {
+            Some(path) => BlockRegistry::load(path)?,
+            None => BlockRegistry::builtin(),
+        };
+        let registries = Registries::vanilla();
+        let biome_count = registries
+            .get("minecraft:worldgen/biome")
+            .map_or(0, |biomes| biomes.entries.len());
+        let chunk_layout = ChunkLayout {
+            min_y: -64,
+            height: 384,
+            block_states: ContainerKind::block_states(blocks.state_count() as usize),
+            biomes: ContainerKind::biomes(biome_count),
+        };
+
+        Ok(Self {
+            storage: WorldStorage::new(&global_config.level_name),
+            global_config: RwLock::new(global_config),
+            status,
+            key_pair,
+            authenticator,
+            registries,
+            blocks,
+            chunk_layout,
+        })
+    }
+}
+
+//Everything connection tasks report to the game loop
+pub enum ServerEvent {
+    Login { handle: ClientHandle },
+    //The client finished configuration and entered play
+    Joined { id: ConnectionId },
+    Packet { id: ConnectionId, packet: RawPacket },
+    Disconnected { id: ConnectionId, reason: DisconnectReason },
+}
+
+//Runs whenever a logged in player leaves, e.g. to save their data
+pub type LeaveHook = Box<dyn FnMut(&ClientHandle, &DisconnectReason) + Send>;
+
+//Starts connection tasks, so accepting a connection never waits on the game loop
+#[derive(Clone)]
+pub struct ConnectionSpawner {
+    context: Arc<ServerContext>,
+    events: mpsc::Sender<ServerEvent>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl ConnectionSpawner {
+    pub fn spawn(&self, stream: TcpStream) {
+        let peer_addr = match stream.peer_addr() {
+            Ok(peer_addr) => peer_addr,
+            Err(error) => {
+                println!("Dropping connection without a peer address: {}", error);
+                return;
+            }
+        };
+        let id = ConnectionId(self.next_id.fetch_add(1, Ordering::Relaxed));
+        println!("New client {} connected with Address: {:?}", id, peer_addr);
+
+        tokio::spawn(run_connection(
+            Client::new(stream),
+            id,
+            peer_addr,
+            Arc::clone(&self.context),
+            self.events.clone(),
+        ));
+    }
+}
+
+//A server.properties that is reloaded whenever it's saved
+struct PropertiesWatch {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    checked: Instant,
+}
+
+//The game loop's state, only ever touched from the game loop itself
 pub struct Server {
-    pub clients: HashMap<SocketAddr, Client>,
-    pub global_config: GlobalConfiguration,
+    //Logged in players
+    pub clients: HashMap<ConnectionId, ClientHandle>,
+    //Lookups of logged in players, names are case insensitive like vanilla
+    by_uuid: HashMap<Uuid, ConnectionId>,
+    by_name: HashMap<String, ConnectionId>,
+    pub context: Arc<ServerContext>,
+    pub ticks: TickRateManager,
+    pub metrics: TickMetrics,
+    //Ticks the world has run for, which stops while frozen
+    pub game_time: u64,
+    leave_hooks: Vec<LeaveHook>,
+    properties: Option<PropertiesWatch>,
+    events: mpsc::Receiver<ServerEvent>,
+    spawner: ConnectionSpawner,
 }
 
 impl Server {
-    pub fn new() -> Self {
-        Self {
+    pub fn with_context(context: ServerContext) -> Self {
+        let context = Arc::new(context);
+        let (events, receiver) = mpsc::channel(EVENT_QUEUE_SIZE);
+        let storage = context.storage.clone();
+
+        let mut server = Self {
             clients: HashMap::new(),
-            global_config: Default::default(),
+            by_uuid: HashMap::new(),
+            by_name: HashMap::new(),
+            context: Arc::clone(&context),
+            ticks: TickRateManager::default(),
+            metrics: TickMetrics::default(),
+            game_time: 0,
+            leave_hooks: Vec::new(),
+            properties: None,
+            events: receiver,
+            spawner: ConnectionSpawner {
+                context,
+                events,
+                next_id: Arc::new(AtomicU64::new(0)),
+            },
+        };
+
+        server.on_leave(move |client, _| save_player(&storage, client));
+        server
+    }
+
+    pub fn spawner(&self) -> ConnectionSpawner {
+        self.spawner.clone()
+    }
+
+    //Picks up where the world's level.dat left off, a world without one starts from scratch
+    pub fn load_level(&mut self) -> Result<(), StorageError> {
+        let Some(level) = self.context.storage.load_level()? else {
+            return Ok(());
+        };
+        let data = level.get("Data").and_then(Tag::as_compound);
+
+        self.game_time = data
+            .and_then(|data| data.get("Time"))
+            .and_then(Tag::as_i64)
+            .unwrap_or(0)
+            .max(0) as u64;
+
+        println!(
+            "Loaded world {} at tick {}",
+            data.and_then(|data| data.get("LevelName"))
+                .and_then(Tag::as_str)
+                .unwrap_or_default(),
+            self.game_time
+        );
+        Ok(())
+    }
+
+    //Applies the settings of a changed server.properties that are safe to change at runtime.
+    //Returns the keys of changed settings that only apply after a restart.
+    pub fn reload_properties(&mut self, path: &Path) -> Result<Vec<&'static str>, PropertiesError> {
+        let new = GlobalConfiguration::from_properties(&ServerProperties::load(path)?)?;
+        let mut config = self.context.global_config.write().unwrap();
+
+        let restart_only = [
+            ("server-ip", new.server_ip != config.server_ip),
+            ("server-port", new.server_port != config.server_port),
+            ("online-mode", new.enable_packet_encryption != config.enable_packet_encryption),
+            (
+                "network-compression-threshold",
+                new.enable_packet_compression != config.enable_packet_compression
+                    || new.compression_threshold != config.compression_threshold,
+            ),
+            ("hardcore", new.hardcore != config.hardcore),
+            ("level-name", new.level_name != config.level_name),
+        ];
+
+        config.motd = new.motd;
+        config.max_players = new.max_players;
+        config.view_distance = new.view_distance;
+        config.simulation_distance = new.simulation_distance;
+        config.difficulty = new.difficulty;
+        config.gamemode = new.gamemode;
+
+        let mut status = self.context.status.write().unwrap();
+        status.description.text = config.motd.clone();
+        status.players.max = config.max_players;
+
+        Ok(restart_only
+            .into_iter()
+            .filter(|(_, changed)| *changed)
+            .map(|(key, _)| key)
+            .collect())
+    }
+
+    //Reloads the file from the game loop whenever it changes, like editing it and running /reload
+    pub fn watch_properties(&mut self, path: impl Into<PathBuf>) {
+        let path = path.into();
+        self.properties = Some(PropertiesWatch {
+            modified: modified_time(&path),
+            path,
+            checked: Instant::now(),
+        });
+    }
+
+    fn check_properties(&mut self) {
+        match &mut self.properties {
+            Some(watch) if watch.checked.elapsed() >= PROPERTIES_CHECK_INTERVAL => {
+                watch.checked = Instant::now();
+            }
+            _ => return,
+        }
+
+        self.reload_changed_properties();
+    }
+
+    fn reload_changed_properties(&mut self) {
+        let Some(watch) = &mut self.properties else {
+            return;
+        };
+
+        let modified = modified_time(&watch.path);
+        if modified == watch.modified {
+            return;
+        }
+        watch.modified = modified;
+
+        let path = watch.path.clone();
+        match self.reload_properties(&path) {
+            Ok(restart_only) if restart_only.is_empty() => {
+                println!("Reloaded {}", path.display());
+            }
+            Ok(restart_only) => println!(
+                "Reloaded {}, changes to {} only apply after a restart",
+                path.display(),
+                restart_only.join(", ")
+            ),
+            Err(error) => println!("Failed to reload {}: {}", path.display(), error),
         }
     }
 
-    pub fn add_client(&mut self,stream: TcpStream) {
-        println!("New client connected with Address: {:?}", &stream.local_addr().unwrap());
-        self.clients.insert(stream.local_addr().unwrap(), Client::new(stream));
+    pub fn on_leave(&mut self, hook: impl FnMut(&ClientHandle, &DisconnectReason) + Send + 'static) {
+        self.leave_hooks.push(Box::new(hook));
     }
 
-    pub async fn process_clients(&mut self) {
-        let mut disconnected_clients = Vec::new();
+    //Ticks at the tick rate forever, running late ticks back to back to catch up
+    pub async fn run(&mut self) {
+        let mut scheduler = TickScheduler::new(std::time::Instant::now());
 
-        for (addr , client) in self.clients.iter_mut() {
-            if !client.get_incoming_packets().await {
-                disconnected_clients.push(*addr);
-                continue;
-            };
+        loop {
+            if self.ticks.is_sprinting() {
+                //Still give other tasks on this worker a chance to run
+                tokio::task::yield_now().await;
+            } else {
+                sleep_until(scheduler.next_tick().into()).await;
+            }
+
+            let start = std::time::Instant::now();
+            self.tick();
+            self.metrics.record(start.elapsed());
+
+            if let Some(report) = self.ticks.on_tick() {
+                println!(
+                    "Sprint completed with {:.1} ticks per second, or {:.2} ms per tick",
+                    report.tps(),
+                    report.mspt()
+                );
+            }
+
+            let now = std::time::Instant::now();
+            if self.ticks.is_sprinting() {
+                scheduler.reset(now);
+            } else if let Some(behind) = scheduler.advance(now, self.ticks.interval()) {
+                println!(
+                    "Can't keep up! Is the server overloaded? Running {}ms or {} ticks behind",
+                    behind.as_millis(),
+                    behind.as_millis() / self.ticks.interval().as_millis().max(1)
+                );
+            }
+        }
+    }
+
+    //A single game tick, its phases always run in this order
+    pub fn tick(&mut self) {
+        //Network: everything connection tasks sent since the last tick
+        self.drain_events();
+        self.check_properties();
+
+        //Frozen games still handle the network, but the world stands still
+        if self.ticks.runs_normally() {
+            self.tick_world();
+        }
+
+        //Packets queued by this tick are written and flushed by each client's writer task
+    }
+
+    fn drain_events(&mut self) {
+        while let Ok(event) = self.events.try_recv() {
+            self.handle_event(event);
+        }
+    }
+
+    fn tick_world(&mut self) {
+        self.game_time += 1;
+    }
+
+    fn handle_event(&mut self, event: ServerEvent) {
+        match event {
+            ServerEvent::Login { handle } => {
+                //A player logging in again takes over from their older session
+                if let Some(&old) = self.by_uuid.get(&handle.profile.uuid) {
+                    self.kick(old, TextComponent::text("You logged in from another location"));
+                }
+
+                println!("{}[/{}] logged in", handle.profile.name, handle.peer_addr);
+                self.by_uuid.insert(handle.profile.uuid, handle.id);
+                self.by_name.insert(handle.profile.name.to_lowercase(), handle.id);
+                self.clients.insert(handle.id, handle);
+                self.refresh_status_players();
+            }
+            ServerEvent::Joined { id } => {
+                if let Some(client) = self.clients.get_mut(&id) {
+                    client.joined = true;
+                }
+            }
+            ServerEvent::Packet { id, packet } => {
+                println!("Received play packet {:#04X} from {}", packet.id, id);
+            }
+            ServerEvent::Disconnected { id, reason } => {
+                self.remove_client(id, &reason);
+            }
+        }
+    }
+
+    //Lookups and direct sends for commands, which aren't parsed yet
+    #[allow(dead_code)]
+    pub fn player_by_uuid(&self, uuid: &Uuid) -> Option<&ClientHandle> {
+        self.clients.get(self.by_uuid.get(uuid)?)
+    }
+
+    #[allow(dead_code)]
+    pub fn player_by_name(&self, name: &str) -> Option<&ClientHandle> {
+        self.clients.get(self.by_name.get(&name.to_lowercase())?)
+    }
+
+    //Sends a packet to a single logged in client
+    #[allow(dead_code)]
+    pub fn send_to<P: Packet>(&self, id: ConnectionId, packet: &P) -> Result<(), PacketError> {
+        self.clients
+            .get(&id)
+            .ok_or(PacketError::ConnectionClosed)?
+            .send(packet)
+    }
+
+    //Disconnects a logged in player with the given reason
+    pub fn kick(&mut self, id: ConnectionId, reason: TextComponent) {
+        let reason = DisconnectReason::Kicked(reason);
+
+        if let Some(client) = self.remove_client(id, &reason) {
+            if let DisconnectReason::Kicked(reason) = reason {
+                client.kick(reason);
+            }
+        }
+    }
+
+    //Everything that happens when a player leaves, however they left
+    fn remove_client(&mut self, id: ConnectionId, reason: &DisconnectReason) -> Option<ClientHandle> {
+        let client = self.clients.remove(&id)?;
+        println!("{} lost connection: {}", client.profile.name, reason);
+
+        //A newer session of the same player keeps its entries
+        let name = client.profile.name.to_lowercase();
+        if self.by_uuid.get(&client.profile.uuid) == Some(&id) {
+            self.by_uuid.remove(&client.profile.uuid);
+        }
+        if self.by_name.get(&name) == Some(&id) {
+            self.by_name.remove(&name);
+        }
+
+        for hook in &mut self.leave_hooks {
+            hook(&client, reason);
+        }
+
+        if client.joined {
+            self.broadcast(&SystemChatMessage {
+                content: TextComponent::text(format!("{} left the game", client.profile.name))
+                    .color("yellow"),
+                overlay: false,
+            });
+        }
+
+        self.refresh_status_players();
+
+        Some(client)
+    }
+
+    //Sends a play packet to every player in play
+    pub fn broadcast<P: Packet>(&self, packet: &P) {
+        for client in self.clients.values().filter(|client| client.joined) {
+            if let Err(error) = client.send(packet) {
+                println!("Failed to send to {}: {}", client.profile.name, error);
+            }
+        }
+    }
+
+    //Keeps the online count and hover sample of the server list in sync with logged in players
+    fn refresh_status_players(&mut self) {
+        let profiles: Vec<&PlayerProfile> =
+            self.clients.values().map(|client| &client.profile).collect();
+
+        let mut status = self.context.status.write().unwrap();
+        status.players.online = profiles.len() as u32;
+        status.players.sample = profiles
+            .iter()
+            .take(MAX_PLAYER_SAMPLE)
+            .map(|profile| PlayerSample {
+                name: profile.name.clone(),
+                id: profile.uuid,
+            })
+            .collect();
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+//Saved on the game loop like vanilla, so a player rejoining right away loads what was just written
+fn save_player(storage: &WorldStorage, client: &ClientHandle) {
+    if let Err(error) = storage.save_player(client.profile.uuid, &client.player_data) {
+        println!("Failed to save player data of {}: {}", client.profile.name, error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::offline_uuid;
+    use crate::chunk::DATA_VERSION;
+    use crate::framing::FrameCodec;
+    use crate::packet::handshaking::Handshake;
+    use crate::nbt::Compound;
+    use crate::packet::login::{LoginStart, LoginSuccess};
+    use crate::packet::version::ProtocolVersion;
+    use crate::packet::{Packet, PROTOCOL_VERSION};
+    use crate::types::{BoundedString, VarInt};
+    use bytes::BytesMut;
+    use std::sync::Mutex;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio_util::codec::{Decoder, Encoder};
+
+    //A server whose world is in a temporary directory, so leaving players aren't saved to the working directory
+    fn test_server(name: &str) -> Server {
+        let root = std::env::temp_dir().join(format!("copper-server-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+
+        Server::with_context(
+            ServerContext::new(GlobalConfiguration {
+                level_name: root.to_string_lossy().into_owned(),
+                ..GlobalConfiguration::offline()
+            })
+            .unwrap(),
+        )
+    }
+
+    //Online and compressed like vanilla
+    fn default_server() -> Server {
+        Server::with_context(ServerContext::new(GlobalConfiguration::default()).unwrap())
+    }
+
+    //Ticks until the condition holds, failing the test if it takes too long
+    async fn tick_until(server: &mut Server, condition: impl Fn(&Server) -> bool) {
+        for _ in 0..200 {
+            server.tick();
+            if condition(server) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        panic!("Condition not met in time");
+    }
+
+    //Connects a client to the server and has it send Login Start
+    async fn login(server: &Server, listener: &TcpListener, name: &str) -> TcpStream {
+        let mut remote = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        server.spawner().spawn(listener.accept().await.unwrap().0);
+
+        let mut codec = FrameCodec::new();
+        let mut frames = BytesMut::new();
+        let handshake = Handshake {
+            protocol_version: VarInt(PROTOCOL_VERSION),
+            server_address: BoundedString("localhost".to_owned()),
+            port: 25565,
+            next_state: VarInt(2),
+        };
+        let login_start = LoginStart {
+            name: BoundedString(name.to_owned()),
+            uuid: Uuid::nil(),
+        };
+        codec.encode(handshake.to_raw(&ProtocolVersion::NATIVE).unwrap(), &mut frames).unwrap();
+        codec.encode(login_start.to_raw(&ProtocolVersion::NATIVE).unwrap(), &mut frames).unwrap();
+        remote.write_all(&frames).await.unwrap();
+
+        remote
+    }
+
+    #[tokio::test]
+    async fn test_player_indexes() {
+        let mut server = test_server("indexes");
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+
+        let _notch = login(&server, &listener, "Notch").await;
+        let _jeb = login(&server, &listener, "jeb_").await;
+        tick_until(&mut server, |server| server.clients.len() == 2).await;
+
+        let notch = server.player_by_name("notch").unwrap();
+        assert_eq!(notch.profile.name, "Notch");
+        assert_eq!(
+            server.player_by_uuid(&offline_uuid("jeb_")).unwrap().profile.name,
+            "jeb_"
+        );
+        assert_ne!(notch.id, server.player_by_name("jeb_").unwrap().id);
+        assert_ne!(notch.peer_addr, server.player_by_name("jeb_").unwrap().peer_addr);
+
+        //Logging in again kicks the older session, the indexes follow the new one
+        let old_id = notch.id;
+        let _notch_again = login(&server, &listener, "Notch").await;
+        tick_until(&mut server, |server| {
+            server
+                .player_by_name("Notch")
+                .is_some_and(|client| client.id != old_id)
+        })
+        .await;
+
+        assert_eq!(server.clients.len(), 2);
+        assert!(!server.clients.contains_key(&old_id));
+        assert!(server.player_by_uuid(&offline_uuid("Notch")).is_some());
+        let _ = std::fs::remove_dir_all(server.context.storage.root());
+    }
+
+    #[tokio::test]
+    async fn test_leave_hooks() {
+        let mut server = test_server("leave");
+        let left = Arc::new(Mutex::new(Vec::new()));
+        let hook_left = Arc::clone(&left);
+        server.on_leave(move |client, reason| {
+            hook_left.lock().unwrap().push((
+                client.profile.name.clone(),
+                matches!(reason, DisconnectReason::EndOfStream),
+            ));
+        });
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut remote = login(&server, &listener, "Notch").await;
+
+        tick_until(&mut server, |server| server.clients.len() == 1).await;
+        assert_eq!(server.context.status.read().unwrap().players.online, 1);
+
+        remote.shutdown().await.unwrap();
+        tick_until(&mut server, |server| server.clients.is_empty()).await;
+
+        assert_eq!(*left.lock().unwrap(), vec![("Notch".to_owned(), true)]);
+        assert_eq!(server.context.status.read().unwrap().players.online, 0);
+
+        //The default hook saved the player
+        let storage = &server.context.storage;
+        let player_data = storage.load_player(offline_uuid("Notch")).unwrap().unwrap();
+        assert_eq!(player_data["DataVersion"], Tag::Int(DATA_VERSION));
+        std::fs::remove_dir_all(storage.root()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_load_player() {
+        let mut server = test_server("load-player");
+        let player_data = Compound::from([
+            ("DataVersion".to_owned(), Tag::Int(DATA_VERSION)),
+            ("XpLevel".to_owned(), Tag::Int(30)),
+        ]);
+        let storage = server.context.storage.clone();
+        storage.save_player(offline_uuid("Notch"), &player_data).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let _notch = login(&server, &listener, "Notch").await;
+        let _jeb = login(&server, &listener, "jeb_").await;
+        tick_until(&mut server, |server| server.clients.len() == 2).await;
+
+        assert_eq!(server.player_by_name("Notch").unwrap().player_data, player_data);
+        assert_eq!(
+            server.player_by_name("jeb_").unwrap().player_data,
+            Compound::from([("DataVersion".to_owned(), Tag::Int(DATA_VERSION))])
+        );
+        std::fs::remove_dir_all(storage.root()).unwrap();
+    }
+
+    #[test]
+    fn test_load_level() {
+        let mut server = test_server("level");
+        server.load_level().unwrap();
+        assert_eq!(server.game_time, 0);
+
+        let data = Compound::from([
+            ("LevelName".to_owned(), Tag::from("world")),
+            ("Time".to_owned(), Tag::Long(24000)),
+        ]);
+        let storage = server.context.storage.clone();
+        storage
+            .save_level(&Compound::from([("Data".to_owned(), Tag::Compound(data))]))
+            .unwrap();
+
+        server.load_level().unwrap();
+        assert_eq!(server.game_time, 24000);
+        std::fs::remove_dir_all(storage.root()).unwrap();
+    }
+
+    #[test]
+    fn test_chunk_layout() {
+        let context = ServerContext::new(GlobalConfiguration::offline()).unwrap();
+
+        assert_eq!(
+            context.chunk_layout.block_states,
+            ContainerKind::block_states(context.blocks.state_count() as usize)
+        );
+        assert_eq!(context.chunk_layout.section_count(), 24);
+    }
+
+    #[test]
+    fn test_missing_blocks_report() {
+        let result = ServerContext::new(GlobalConfiguration {
+            blocks_report: Some(PathBuf::from("missing/blocks.json")),
+            ..GlobalConfiguration::offline()
+        });
+
+        assert!(matches!(result, Err(BlockRegistryError::Io { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_send_to() {
+        let mut server = test_server("send-to");
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut remote = login(&server, &listener, "Notch").await;
+        tick_until(&mut server, |server| server.clients.len() == 1).await;
+
+        let id = server.player_by_name("Notch").unwrap().id;
+        let message = SystemChatMessage {
+            content: TextComponent::text("Hello"),
+            overlay: false,
+        };
+        server.send_to(id, &message).unwrap();
+        assert!(matches!(
+            server.send_to(ConnectionId(id.0 + 1), &message),
+            Err(PacketError::ConnectionClosed)
+        ));
+
+        //Login Success comes first, then the message
+        let mut codec = FrameCodec::new();
+        let mut buffer = BytesMut::new();
+        let mut ids = Vec::new();
+        while ids.len() < 2 {
+            remote.read_buf(&mut buffer).await.unwrap();
+            while let Some(packet) = codec.decode(&mut buffer).unwrap() {
+                ids.push(packet.id);
+            }
+        }
+
+        let version = ProtocolVersion::NATIVE;
+        assert_eq!(
+            ids,
+            vec![
+                version.packet_id::<LoginSuccess>(),
+                version.packet_id::<SystemChatMessage>()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bind_address() {
+        let mut config = GlobalConfiguration::offline();
+        assert_eq!(
+            config.bind_address().unwrap(),
+            "0.0.0.0:25565".parse().unwrap()
+        );
+
+        config.server_ip = Some("127.0.0.1".to_owned());
+        assert_eq!(
+            config.bind_address().unwrap(),
+            "127.0.0.1:25565".parse().unwrap()
+        );
+
+        config.server_ip = Some("localhost".to_owned());
+        assert!(config.bind_address().unwrap().ip().is_loopback());
+    }
+
+    #[test]
+    fn test_reload_properties() {
+        let path = std::env::temp_dir().join(format!("copper-{}.properties", std::process::id()));
+        std::fs::write(
+            &path,
+            "motd=Reloaded\nmax-players=5\ndifficulty=hard\nserver-port=25566\n",
+        )
+        .unwrap();
+
+        let mut server = default_server();
+        let restart_only = server.reload_properties(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(restart_only.unwrap(), vec!["server-port"]);
+
+        let config = server.context.global_config.read().unwrap();
+        assert_eq!(config.motd, "Reloaded");
+        assert_eq!(config.difficulty, Difficulty::Hard);
+        assert_eq!(config.server_port, 25565);
+
+        let status = server.context.status.read().unwrap();
+        assert_eq!(status.description.text, "Reloaded");
+        assert_eq!(status.players.max, 5);
+    }
+
+    #[test]
+    fn test_watch_properties() {
+        let path = std::env::temp_dir().join(format!("copper-watch-{}.properties", std::process::id()));
+        std::fs::write(&path, "motd=First\n").unwrap();
+
+        let mut server = test_server("watch");
+        server.watch_properties(&path);
+        server.reload_changed_properties();
+        assert_eq!(server.context.global_config.read().unwrap().motd, "A Copper Server");
+
+        //Saved again a moment later, the file system may not tell writes this close apart
+        std::fs::write(&path, "motd=Second\n").unwrap();
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(1)).unwrap();
+        server.reload_changed_properties();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(server.context.global_config.read().unwrap().motd, "Second");
+    }
+
+    #[tokio::test]
+    async fn test_frozen_tick() {
+        let mut server = default_server();
+
+        server.tick();
+        assert_eq!(server.game_time, 1);
+
+        server.ticks.set_frozen(true);
+        server.tick();
+        server.ticks.on_tick();
+        assert_eq!(server.game_time, 1);
 
-            client.process_packets();
+        server.ticks.step(2).unwrap();
+        for _ in 0..3 {
+            server.tick();
+            server.ticks.on_tick();
         }
+        assert_eq!(server.game_time, 3);
     }
 }
@@ -0,0 +1,163 @@
+use std::{fs, io, path::Path};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::packet::version::supported_releases;
+use crate::packet::PROTOCOL_VERSION;
+use crate::server::GlobalConfiguration;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+const FAVICON_SIZE: u32 = 64;
+
+//JSON body of the Status Response packet shown in the multiplayer server list
+#[derive(Debug, Clone, Serialize)]
+pub struct StatusResponse {
+    pub version: StatusVersion,
+    pub players: StatusPlayers,
+    pub description: StatusDescription,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub favicon: Option<String>,
+    #[serde(rename = "enforcesSecureChat")]
+    pub enforces_secure_chat: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StatusVersion {
+    pub name: String,
+    pub protocol: i32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StatusPlayers {
+    pub max: u32,
+    pub online: u32,
+    pub sample: Vec<PlayerSample>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PlayerSample {
+    pub name: String,
+    pub id: Uuid,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StatusDescription {
+    pub text: String,
+}
+
+impl StatusResponse {
+    pub fn from_config(config: &GlobalConfiguration) -> Self {
+        let favicon = match &config.favicon_path {
+            Some(path) if path.exists() => match load_favicon(path) {
+                Ok(favicon) => Some(favicon),
+                Err(e) => {
+                    println!("Failed to load favicon {:?}: {}", path, e);
+                    None
+                }
+            },
+            _ => None,
+        };
+
+        Self {
+            version: StatusVersion {
+                name: supported_releases(),
+                protocol: PROTOCOL_VERSION,
+            },
+            players: StatusPlayers {
+                max: config.max_players,
+                online: 0,
+                sample: Vec::new(),
+            },
+            description: StatusDescription {
+                text: config.motd.clone(),
+            },
+            favicon,
+            enforces_secure_chat: false,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Status response is always serializable")
+    }
+}
+
+//Reads a 64x64 PNG and encodes it as the data URI expected by the status response
+pub fn load_favicon(path: &Path) -> io::Result<String> {
+    let data = fs::read(path)?;
+
+    if data.len() < 24 || data[..8] != PNG_SIGNATURE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Favicon is not a PNG image",
+        ));
+    }
+
+    //The IHDR chunk always comes first, holding the width and height right after its header
+    let width = u32::from_be_bytes([data[16], data[17], data[18], data[19]]);
+    let height = u32::from_be_bytes([data[20], data[21], data[22], data[23]]);
+
+    if width != FAVICON_SIZE || height != FAVICON_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Favicon must be 64x64 pixels, found {}x{}", width, height),
+        ));
+    }
+
+    Ok(format!("data:image/png;base64,{}", STANDARD.encode(data)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png_header(width: u32, height: u32) -> Vec<u8> {
+        let mut data = PNG_SIGNATURE.to_vec();
+        data.extend_from_slice(&13u32.to_be_bytes());
+        data.extend_from_slice(b"IHDR");
+        data.extend_from_slice(&width.to_be_bytes());
+        data.extend_from_slice(&height.to_be_bytes());
+        data
+    }
+
+    #[test]
+    fn test_status_response_json() {
+        //The default server-icon.png may exist wherever the tests run
+        let config = GlobalConfiguration {
+            favicon_path: None,
+            ..Default::default()
+        };
+        let mut status = StatusResponse::from_config(&config);
+        status.players.online = 1;
+        status.players.sample.push(PlayerSample {
+            name: "Notch".to_owned(),
+            id: Uuid::from_u128(0x069a79f444e94726a5befca90e38aaf5),
+        });
+
+        let json: serde_json::Value = serde_json::from_str(&status.to_json()).unwrap();
+
+        assert_eq!(json["version"]["protocol"], PROTOCOL_VERSION);
+        assert_eq!(json["players"]["online"], 1);
+        assert_eq!(
+            json["players"]["sample"][0]["id"],
+            "069a79f4-44e9-4726-a5be-fca90e38aaf5"
+        );
+        assert_eq!(json["description"]["text"], config.motd);
+        assert!(json.get("favicon").is_none());
+    }
+
+    #[test]
+    fn test_load_favicon() {
+        let path = std::env::temp_dir().join("copper_test_favicon.png");
+        fs::write(&path, png_header(64, 64)).unwrap();
+
+        let favicon = load_favicon(&path).unwrap();
+        assert!(favicon.starts_with("data:image/png;base64,iVBORw0KGgo"));
+
+        fs::write(&path, png_header(128, 64)).unwrap();
+        assert!(load_favicon(&path).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,336 @@
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::read::{MultiGzDecoder, ZlibDecoder};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::identifier::Identifier;
+use crate::nbt::{Compound, NbtBufferReaderExt, NbtBufferWriterExt, NbtLimits, Tag};
+use crate::reader::ProtocolError;
+
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+//The CMF byte of a zlib stream using deflate with the default window size
+const ZLIB_MAGIC: u8 = 0x78;
+//Appended to the name of the copy a file is moved to before it's replaced
+const OLD_SUFFIX: &str = "_old";
+
+#[derive(Debug, Error)]
+pub enum StorageError {
+    #[error("Failed to access {path}: {source}")]
+    Io { path: PathBuf, source: io::Error },
+    #[error("Invalid NBT in {path}: {source}")]
+    Nbt {
+        path: PathBuf,
+        source: ProtocolError,
+    },
+    #[error("Damaged region file {path}: {reason}")]
+    DamagedRegion { path: PathBuf, reason: String },
+}
+
+impl StorageError {
+    pub(crate) fn io(path: &Path) -> impl FnOnce(io::Error) -> Self + '_ {
+        move |source| StorageError::Io {
+            path: path.to_owned(),
+            source,
+        }
+    }
+}
+
+//The NBT files of a world in vanilla's layout, so worlds can move between this server and vanilla.
+//Files are gzipped like vanilla writes them, but zlib and uncompressed files can be read too.
+//Everything here blocks, so async code should call it through spawn_blocking.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorldStorage {
+    root: PathBuf,
+}
+
+impl WorldStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    //Only the tests need it, to clean up their worlds
+    #[allow(dead_code)]
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn level_path(&self) -> PathBuf {
+        self.root.join("level.dat")
+    }
+
+    fn player_path(&self, uuid: Uuid) -> PathBuf {
+        self.root.join("playerdata").join(format!("{}.dat", uuid))
+    }
+
+    //Saved data like raids and map items, stored as data/<name>.dat
+    fn data_path(&self, name: &str) -> PathBuf {
+        self.root.join("data").join(format!("{}.dat", name))
+    }
+
+    //Where a dimension keeps its region files, using vanilla's folders for the built in dimensions.
+    //Region storage isn't opened per dimension yet.
+    #[allow(dead_code)]
+    pub fn region_directory(&self, dimension: &Identifier) -> PathBuf {
+        let directory = match (dimension.namespace(), dimension.path()) {
+            ("minecraft", "overworld") => self.root.clone(),
+            ("minecraft", "the_nether") => self.root.join("DIM-1"),
+            ("minecraft", "the_end") => self.root.join("DIM1"),
+            (namespace, path) => self.root.join("dimensions").join(namespace).join(path),
+        };
+        directory.join("region")
+    }
+
+    //The whole level.dat, whose world settings are in its Data compound
+    pub fn load_level(&self) -> Result<Option<Compound>, StorageError> {
+        load_with_backup(&self.level_path())
+    }
+
+    //The level is only read on startup, nothing changes it that would need saving yet
+    #[allow(dead_code)]
+    pub fn save_level(&self, level: &Compound) -> Result<(), StorageError> {
+        save_with_backup(&self.level_path(), level)
+    }
+
+    pub fn load_player(&self, uuid: Uuid) -> Result<Option<Compound>, StorageError> {
+        load_with_backup(&self.player_path(uuid))
+    }
+
+    pub fn save_player(&self, uuid: Uuid, player: &Compound) -> Result<(), StorageError> {
+        save_with_backup(&self.player_path(uuid), player)
+    }
+
+    //For raids, maps and the other saved data, none of which is implemented
+    #[allow(dead_code)]
+    pub fn load_data(&self, name: &str) -> Result<Option<Compound>, StorageError> {
+        load_with_backup(&self.data_path(name))
+    }
+
+    #[allow(dead_code)]
+    pub fn save_data(&self, name: &str, data: &Compound) -> Result<(), StorageError> {
+        save_with_backup(&self.data_path(name), data)
+    }
+}
+
+fn old_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_owned();
+    name.push(OLD_SUFFIX);
+    path.with_file_name(name)
+}
+
+//Falls back to the copy kept by the last save when the file is missing or damaged, like vanilla
+//does after a crash mid-save
+fn load_with_backup(path: &Path) -> Result<Option<Compound>, StorageError> {
+    let error = match read_nbt_file(path) {
+        Ok(compound) => return Ok(Some(compound)),
+        Err(StorageError::Io { source, .. }) if source.kind() == io::ErrorKind::NotFound => None,
+        Err(error) => Some(error),
+    };
+
+    let old_path = old_path(path);
+    match read_nbt_file(&old_path) {
+        Ok(compound) => {
+            match &error {
+                Some(error) => println!("{}, using {} instead", error, old_path.display()),
+                None => println!(
+                    "{} is missing, using {}",
+                    path.display(),
+                    old_path.display()
+                ),
+            }
+            Ok(Some(compound))
+        }
+        Err(StorageError::Io { source, .. }) if source.kind() == io::ErrorKind::NotFound => {
+            error.map_or(Ok(None), Err)
+        }
+        Err(old_error) => Err(error.unwrap_or(old_error)),
+    }
+}
+
+//Writes to a temporary file first so a crash never leaves a half written file behind,
+//then moves the current file to _old before putting the new one in its place.
+//A damaged current file is replaced without keeping it, since _old is then the only good copy.
+fn save_with_backup(path: &Path, compound: &Compound) -> Result<(), StorageError> {
+    let directory = path.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(directory).map_err(StorageError::io(directory))?;
+
+    let mut temp_name = path.file_name().unwrap_or_default().to_owned();
+    temp_name.push(format!(".{:08x}.tmp", rand::random::<u32>()));
+    let temp_path = path.with_file_name(temp_name);
+
+    if let Err(error) = write_nbt_file(&temp_path, compound) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(error);
+    }
+
+    if read_nbt_file(path).is_ok() {
+        fs::rename(path, old_path(path)).map_err(StorageError::io(path))?;
+    }
+
+    fs::rename(&temp_path, path).map_err(StorageError::io(path))
+}
+
+//Reads a file holding a single compound, detecting how it's compressed
+pub fn read_nbt_file(path: &Path) -> Result<Compound, StorageError> {
+    let bytes = fs::read(path).map_err(StorageError::io(path))?;
+
+    let mut data = Vec::new();
+    let decompressed = if bytes.starts_with(&GZIP_MAGIC) {
+        MultiGzDecoder::new(&bytes[..]).read_to_end(&mut data)
+    } else if bytes.first() == Some(&ZLIB_MAGIC) {
+        ZlibDecoder::new(&bytes[..]).read_to_end(&mut data)
+    } else {
+        data = bytes;
+        Ok(0)
+    };
+    decompressed.map_err(StorageError::io(path))?;
+
+    let invalid = |source| StorageError::Nbt {
+        path: path.to_owned(),
+        source,
+    };
+    match (&data[..]).read_nbt(NbtLimits::FILE).map_err(invalid)? {
+        (_, Tag::Compound(compound)) => Ok(compound),
+        (_, tag) => Err(invalid(ProtocolError::InvalidData(format!(
+            "Root tag must be a compound, not tag {}",
+            tag.id()
+        )))),
+    }
+}
+
+//Writes a gzipped compound with an empty root name, like vanilla
+pub fn write_nbt_file(path: &Path, compound: &Compound) -> Result<(), StorageError> {
+    let mut data = Vec::new();
+    data.write_nbt_compound("", compound)
+        .map_err(|source| StorageError::Nbt {
+            path: path.to_owned(),
+            source,
+        })?;
+
+    let file = File::create(path).map_err(StorageError::io(path))?;
+    let mut encoder = GzEncoder::new(file, Compression::default());
+    encoder.write_all(&data).map_err(StorageError::io(path))?;
+    let file = encoder.finish().map_err(StorageError::io(path))?;
+    file.sync_all().map_err(StorageError::io(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+
+    fn temp_world(name: &str) -> WorldStorage {
+        let root = std::env::temp_dir().join(format!("copper-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        WorldStorage::new(root)
+    }
+
+    fn level(name: &str) -> Compound {
+        let data = Compound::from([
+            ("LevelName".to_owned(), Tag::from(name)),
+            ("DataVersion".to_owned(), Tag::Int(3955)),
+            ("RandomSeed".to_owned(), Tag::Long(-4172144997902289642)),
+        ]);
+        Compound::from([("Data".to_owned(), Tag::Compound(data))])
+    }
+
+    #[test]
+    fn test_level_round_trip() {
+        let storage = temp_world("level");
+        assert_eq!(storage.load_level().unwrap(), None);
+
+        storage.save_level(&level("first")).unwrap();
+        let bytes = fs::read(storage.root().join("level.dat")).unwrap();
+        assert!(bytes.starts_with(&GZIP_MAGIC));
+        assert!(!storage.root().join("level.dat_old").exists());
+
+        storage.save_level(&level("second")).unwrap();
+        assert_eq!(storage.load_level().unwrap(), Some(level("second")));
+        assert_eq!(
+            read_nbt_file(&storage.root().join("level.dat_old")).unwrap(),
+            level("first")
+        );
+
+        //Only the file and its backup are left, no temporary files
+        assert_eq!(fs::read_dir(storage.root()).unwrap().count(), 2);
+        fs::remove_dir_all(storage.root()).unwrap();
+    }
+
+    #[test]
+    fn test_data_round_trip() {
+        let storage = temp_world("data");
+        assert_eq!(storage.load_data("raids").unwrap(), None);
+
+        storage.save_data("raids", &level("raids")).unwrap();
+        assert!(storage.root().join("data/raids.dat").exists());
+        assert_eq!(storage.load_data("raids").unwrap(), Some(level("raids")));
+        fs::remove_dir_all(storage.root()).unwrap();
+    }
+
+    #[test]
+    fn test_region_directory() {
+        let storage = WorldStorage::new("world");
+        let directory = |dimension| storage.region_directory(&dimension);
+
+        assert_eq!(directory(Identifier::minecraft("overworld")), Path::new("world/region"));
+        assert_eq!(directory(Identifier::minecraft("the_nether")), Path::new("world/DIM-1/region"));
+        assert_eq!(
+            directory(Identifier::new("copper", "void").unwrap()),
+            Path::new("world/dimensions/copper/void/region")
+        );
+    }
+
+    #[test]
+    fn test_damaged_file_uses_backup() {
+        let storage = temp_world("damaged");
+        let uuid = Uuid::from_u128(0x069a79f444e94726a5befca90e38aaf5);
+
+        storage.save_player(uuid, &level("first")).unwrap();
+        storage.save_player(uuid, &level("second")).unwrap();
+
+        let path = storage
+            .root()
+            .join("playerdata/069a79f4-44e9-4726-a5be-fca90e38aaf5.dat");
+        fs::write(&path, [GZIP_MAGIC[0], GZIP_MAGIC[1], 0]).unwrap();
+        assert_eq!(storage.load_player(uuid).unwrap(), Some(level("first")));
+
+        //Saving again keeps the backup that was loaded instead of replacing it with the damaged file
+        storage.save_player(uuid, &level("third")).unwrap();
+        assert_eq!(read_nbt_file(&old_path(&path)).unwrap(), level("first"));
+        assert_eq!(storage.load_player(uuid).unwrap(), Some(level("third")));
+
+        fs::write(&path, [GZIP_MAGIC[0], GZIP_MAGIC[1], 0]).unwrap();
+        fs::remove_file(old_path(&path)).unwrap();
+        assert!(storage.load_player(uuid).is_err());
+
+        fs::remove_file(&path).unwrap();
+        assert_eq!(storage.load_player(uuid).unwrap(), None);
+        fs::remove_dir_all(storage.root()).unwrap();
+    }
+
+    #[test]
+    fn test_other_compressions() {
+        let storage = temp_world("compression");
+        let mut data = Vec::new();
+        data.write_nbt("", &Tag::Compound(level("raw"))).unwrap();
+        fs::create_dir_all(storage.root().join("data")).unwrap();
+
+        fs::write(storage.root().join("data/raw.dat"), &data).unwrap();
+        assert_eq!(storage.load_data("raw").unwrap(), Some(level("raw")));
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&data).unwrap();
+        fs::write(
+            storage.root().join("data/zlib.dat"),
+            encoder.finish().unwrap(),
+        )
+        .unwrap();
+        assert_eq!(storage.load_data("zlib").unwrap(), Some(level("raw")));
+
+        fs::remove_dir_all(storage.root()).unwrap();
+    }
+}
@@ -0,0 +1,187 @@
+use std::fmt;
+
+use bytes::BytesMut;
+use serde::{Deserialize, Serialize};
+
+use crate::nbt::{self, NbtBufferReaderExt, NbtBufferWriterExt, NbtLimits, Tag};
+use crate::reader::ProtocolError;
+use crate::types::{Decode, Encode};
+
+//Formatted chat text shown to players, e.g. disconnect reasons and chat messages.
+//Sent as JSON during login and as network NBT after it.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct TextComponent {
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bold: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub italic: Option<bool>,
+    //Components appended after this one, inheriting its style
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra: Vec<TextComponent>,
+}
+
+impl TextComponent {
+    pub fn text(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn color(mut self, color: &str) -> Self {
+        self.color = Some(color.to_owned());
+        self
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Text components always serialize")
+    }
+
+    fn is_plain(&self) -> bool {
+        self.color.is_none() && self.bold.is_none() && self.italic.is_none() && self.extra.is_empty()
+    }
+
+    fn to_tag(&self) -> Result<Tag, ProtocolError> {
+        if self.is_plain() {
+            Ok(Tag::from(self.text.as_str()))
+        } else {
+            nbt::to_tag(self)
+        }
+    }
+
+    fn from_tag(tag: Tag) -> Result<Self, ProtocolError> {
+        match tag {
+            Tag::String(text) => Ok(Self::text(text)),
+            Tag::Compound(mut compound) => {
+                //Extra components can be plain strings, which serde would expect to be compounds
+                let extra = compound.remove("extra");
+                let mut component: Self = nbt::from_tag(Tag::Compound(compound))?;
+
+                match extra {
+                    Some(Tag::List(extra)) => {
+                        component.extra = extra
+                            .into_iter()
+                            .map(Self::from_tag)
+                            .collect::<Result<_, _>>()?;
+                    }
+                    Some(tag) => {
+                        return Err(ProtocolError::InvalidData(format!(
+                            "Text component extra can't be tag {}",
+                            tag.id()
+                        )))
+                    }
+                    None => {}
+                }
+
+                Ok(component)
+            }
+            tag => Err(ProtocolError::InvalidData(format!(
+                "Text components can't be tag {}",
+                tag.id()
+            ))),
+        }
+    }
+}
+
+//Plain text, without any formatting
+impl fmt::Display for TextComponent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)?;
+        for extra in &self.extra {
+            extra.fmt(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+//Network NBT, which leaves out the name of the root tag
+impl Encode for TextComponent {
+    fn encode(&self, buffer: &mut BytesMut) -> Result<(), ProtocolError> {
+        buffer.write_network_nbt(&self.to_tag()?)
+    }
+}
+
+impl Decode for TextComponent {
+    fn decode(buffer: &mut BytesMut) -> Result<Self, ProtocolError> {
+        Self::from_tag(buffer.read_network_nbt(NbtLimits::NETWORK)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nbt::{TAG_COMPOUND, TAG_STRING};
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_plain_text_nbt() {
+        let mut buffer = BytesMut::new();
+        TextComponent::text("Hi").encode(&mut buffer).unwrap();
+
+        assert_eq!(&buffer[..], &[TAG_STRING, 0, 2, b'H', b'i']);
+        assert_eq!(
+            TextComponent::decode(&mut buffer).unwrap(),
+            TextComponent::text("Hi")
+        );
+    }
+
+    #[test]
+    fn test_styled_text_nbt_round_trip() {
+        let mut component = TextComponent::text("Kicked: ").color("red");
+        component.bold = Some(true);
+        component.extra.push(TextComponent::text("\0 \u{e9} \u{1F600}"));
+
+        let mut buffer = BytesMut::new();
+        component.encode(&mut buffer).unwrap();
+
+        assert_eq!(buffer[0], TAG_COMPOUND);
+        assert_eq!(TextComponent::decode(&mut buffer).unwrap(), component);
+        assert!(buffer.is_empty());
+    }
+
+    fn text_component() -> impl Strategy<Value = TextComponent> {
+        let leaf = (
+            any::<String>(),
+            prop::option::of("[a-z_]{1,12}"),
+            any::<Option<bool>>(),
+            any::<Option<bool>>(),
+        )
+            .prop_map(|(text, color, bold, italic)| TextComponent {
+                text,
+                color,
+                bold,
+                italic,
+                extra: Vec::new(),
+            });
+
+        leaf.prop_recursive(3, 16, 4, |inner| {
+            (inner.clone(), prop::collection::vec(inner, 1..4)).prop_map(|(mut component, extra)| {
+                component.extra = extra;
+                component
+            })
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn test_nbt_round_trip(component in text_component()) {
+            let mut buffer = BytesMut::new();
+            component.encode(&mut buffer).unwrap();
+
+            prop_assert_eq!(TextComponent::decode(&mut buffer).unwrap(), component);
+            prop_assert!(buffer.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_json() {
+        let component = TextComponent::text("Bye").color("yellow");
+
+        assert_eq!(component.to_json(), r#"{"text":"Bye","color":"yellow"}"#);
+        assert_eq!(component.to_string(), "Bye");
+    }
+}
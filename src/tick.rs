@@ -0,0 +1,378 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+
+pub const DEFAULT_TICK_RATE: f32 = 20.0;
+//Same bounds as vanilla's /tick rate
+pub const MIN_TICK_RATE: f32 = 1.0;
+pub const MAX_TICK_RATE: f32 = 10000.0;
+//Ticks kept for the MSPT and TPS averages, same as vanilla
+const TICK_HISTORY: usize = 100;
+//Falling further behind than this skips the missed ticks instead of running them all back to back
+const MAX_CATCH_UP: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Error, PartialEq)]
+pub enum TickError {
+    #[error("Tick rate {0} is outside of {MIN_TICK_RATE} to {MAX_TICK_RATE}")]
+    InvalidRate(f32),
+    #[error("The game can only be stepped while frozen")]
+    NotFrozen,
+}
+
+//How long a finished sprint took, reported once it's done or stopped
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SprintReport {
+    pub ticks: u64,
+    pub elapsed: Duration,
+}
+
+impl SprintReport {
+    pub fn mspt(&self) -> f64 {
+        if self.ticks == 0 {
+            return 0.0;
+        }
+
+        self.elapsed.as_secs_f64() * 1000.0 / self.ticks as f64
+    }
+
+    pub fn tps(&self) -> f64 {
+        if self.elapsed.is_zero() {
+            return 0.0;
+        }
+
+        self.ticks as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+#[derive(Debug)]
+struct Sprint {
+    remaining: u64,
+    completed: u64,
+    started: Instant,
+    //Sprinting unfreezes the game until it's done
+    was_frozen: bool,
+}
+
+//Vanilla's /tick state: the tick rate, freezing and stepping, and sprinting
+#[derive(Debug)]
+pub struct TickRateManager {
+    tick_rate: f32,
+    frozen: bool,
+    //Ticks left to run while frozen
+    frozen_steps: u32,
+    sprint: Option<Sprint>,
+}
+
+impl Default for TickRateManager {
+    fn default() -> Self {
+        Self {
+            tick_rate: DEFAULT_TICK_RATE,
+            frozen: false,
+            frozen_steps: 0,
+            sprint: None,
+        }
+    }
+}
+
+//Changing the rate, freezing, stepping and sprinting are for /tick, which waits on commands being parsed
+#[allow(dead_code)]
+impl TickRateManager {
+    pub fn tick_rate(&self) -> f32 {
+        self.tick_rate
+    }
+
+    pub fn set_tick_rate(&mut self, tick_rate: f32) -> Result<(), TickError> {
+        if !(MIN_TICK_RATE..=MAX_TICK_RATE).contains(&tick_rate) {
+            return Err(TickError::InvalidRate(tick_rate));
+        }
+
+        self.tick_rate = tick_rate;
+        Ok(())
+    }
+
+    //Time between two ticks, 50ms at the default rate
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.tick_rate as f64)
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    pub fn set_frozen(&mut self, frozen: bool) {
+        self.frozen = frozen;
+        if !frozen {
+            self.frozen_steps = 0;
+        }
+    }
+
+    //Runs the given number of ticks while frozen
+    pub fn step(&mut self, ticks: u32) -> Result<(), TickError> {
+        if !self.frozen {
+            return Err(TickError::NotFrozen);
+        }
+
+        self.frozen_steps = ticks;
+        Ok(())
+    }
+
+    //Returns whether there were steps left to stop
+    pub fn stop_stepping(&mut self) -> bool {
+        std::mem::take(&mut self.frozen_steps) > 0
+    }
+
+    //Runs the given number of ticks as fast as possible, returns the report of a sprint it replaced
+    pub fn sprint(&mut self, ticks: u64) -> Option<SprintReport> {
+        let replaced = self.stop_sprint();
+
+        self.sprint = Some(Sprint {
+            remaining: ticks,
+            completed: 0,
+            started: Instant::now(),
+            was_frozen: self.frozen,
+        });
+        self.frozen = false;
+
+        replaced
+    }
+
+    pub fn stop_sprint(&mut self) -> Option<SprintReport> {
+        let sprint = self.sprint.take()?;
+        self.frozen = sprint.was_frozen;
+
+        Some(SprintReport {
+            ticks: sprint.completed,
+            elapsed: sprint.started.elapsed(),
+        })
+    }
+
+    pub fn is_sprinting(&self) -> bool {
+        self.sprint.is_some()
+    }
+
+    //Whether world, entity and player updates run this tick, which they don't while frozen
+    pub fn runs_normally(&self) -> bool {
+        !self.frozen || self.frozen_steps > 0
+    }
+
+    //Counts a finished tick against steps and sprints, returning the report of a sprint it finished
+    pub fn on_tick(&mut self) -> Option<SprintReport> {
+        if self.frozen && self.frozen_steps > 0 {
+            self.frozen_steps -= 1;
+        }
+
+        let sprint = self.sprint.as_mut()?;
+        sprint.completed += 1;
+        sprint.remaining = sprint.remaining.saturating_sub(1);
+
+        if sprint.remaining == 0 {
+            self.stop_sprint()
+        } else {
+            None
+        }
+    }
+}
+
+//How long recent ticks took, for MSPT and TPS
+#[derive(Debug, Default)]
+pub struct TickMetrics {
+    history: VecDeque<Duration>,
+    tick_count: u64,
+}
+
+//Only recording is wired, the readings are for /tick query and the debug screen
+#[allow(dead_code)]
+impl TickMetrics {
+    pub fn record(&mut self, duration: Duration) {
+        if self.history.len() == TICK_HISTORY {
+            self.history.pop_front();
+        }
+
+        self.history.push_back(duration);
+        self.tick_count += 1;
+    }
+
+    pub fn tick_count(&self) -> u64 {
+        self.tick_count
+    }
+
+    //Durations of the most recent ticks, oldest first
+    pub fn history(&self) -> impl Iterator<Item = Duration> + '_ {
+        self.history.iter().copied()
+    }
+
+    //Average milliseconds per tick
+    pub fn mspt(&self) -> f64 {
+        if self.history.is_empty() {
+            return 0.0;
+        }
+
+        let total: Duration = self.history.iter().sum();
+        total.as_secs_f64() * 1000.0 / self.history.len() as f64
+    }
+
+    //Ticks per second, which can't be faster than the tick rate outside of sprints
+    pub fn tps(&self, tick_rate: f32) -> f64 {
+        let mspt = self.mspt();
+        if mspt == 0.0 {
+            return tick_rate as f64;
+        }
+
+        (1000.0 / mspt).min(tick_rate as f64)
+    }
+}
+
+//Decides when the next tick is due. Overrun ticks are caught up on by running them
+//back to back, unless the server fell too far behind.
+#[derive(Debug)]
+pub struct TickScheduler {
+    next_tick: Instant,
+}
+
+impl TickScheduler {
+    pub fn new(now: Instant) -> Self {
+        Self { next_tick: now }
+    }
+
+    pub fn next_tick(&self) -> Instant {
+        self.next_tick
+    }
+
+    //Moves on to the next tick, returning how far behind the server was if it skipped ticks
+    pub fn advance(&mut self, now: Instant, interval: Duration) -> Option<Duration> {
+        self.next_tick += interval;
+
+        let behind = now.saturating_duration_since(self.next_tick);
+        if behind > MAX_CATCH_UP {
+            self.next_tick = now;
+            return Some(behind);
+        }
+
+        None
+    }
+
+    //Runs the next tick right away, used while sprinting
+    pub fn reset(&mut self, now: Instant) {
+        self.next_tick = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tick_rate() {
+        let mut ticks = TickRateManager::default();
+        assert_eq!(ticks.interval(), Duration::from_millis(50));
+
+        ticks.set_tick_rate(10.0).unwrap();
+        assert_eq!(ticks.interval(), Duration::from_millis(100));
+
+        assert_eq!(ticks.set_tick_rate(0.5), Err(TickError::InvalidRate(0.5)));
+        assert_eq!(ticks.tick_rate(), 10.0);
+    }
+
+    #[test]
+    fn test_freeze_and_step() {
+        let mut ticks = TickRateManager::default();
+        assert_eq!(ticks.step(1), Err(TickError::NotFrozen));
+
+        ticks.set_frozen(true);
+        assert!(!ticks.runs_normally());
+
+        ticks.step(2).unwrap();
+        for _ in 0..2 {
+            assert!(ticks.runs_normally());
+            ticks.on_tick();
+        }
+        assert!(!ticks.runs_normally());
+        assert!(!ticks.stop_stepping());
+
+        ticks.step(5).unwrap();
+        assert!(ticks.stop_stepping());
+        assert!(!ticks.runs_normally());
+
+        ticks.set_frozen(false);
+        assert!(ticks.runs_normally());
+    }
+
+    #[test]
+    fn test_sprint() {
+        let mut ticks = TickRateManager::default();
+        ticks.set_frozen(true);
+
+        assert_eq!(ticks.sprint(3), None);
+        assert!(ticks.runs_normally());
+
+        assert_eq!(ticks.on_tick(), None);
+        assert_eq!(ticks.on_tick(), None);
+        let report = ticks.on_tick().unwrap();
+        assert_eq!(report.ticks, 3);
+
+        //Freezing is restored once the sprint is over
+        assert!(!ticks.is_sprinting());
+        assert!(ticks.is_frozen());
+    }
+
+    #[test]
+    fn test_stop_sprint() {
+        let mut ticks = TickRateManager::default();
+        ticks.sprint(100);
+        ticks.on_tick();
+
+        assert_eq!(ticks.sprint(100).unwrap().ticks, 1);
+        assert_eq!(ticks.stop_sprint().unwrap().ticks, 0);
+        assert_eq!(ticks.stop_sprint(), None);
+    }
+
+    #[test]
+    fn test_metrics() {
+        let mut metrics = TickMetrics::default();
+        assert_eq!(metrics.tps(20.0), 20.0);
+
+        for _ in 0..TICK_HISTORY {
+            metrics.record(Duration::from_millis(10));
+        }
+        assert_eq!(metrics.mspt(), 10.0);
+        assert_eq!(metrics.tps(20.0), 20.0);
+
+        //Only the most recent ticks count
+        for _ in 0..TICK_HISTORY {
+            metrics.record(Duration::from_millis(100));
+        }
+        assert_eq!(metrics.mspt(), 100.0);
+        assert_eq!(metrics.tps(20.0), 10.0);
+        assert_eq!(metrics.history().count(), TICK_HISTORY);
+        assert_eq!(metrics.tick_count(), 2 * TICK_HISTORY as u64);
+    }
+
+    #[test]
+    fn test_scheduler_catches_up() {
+        let start = Instant::now();
+        let interval = Duration::from_millis(50);
+        let mut scheduler = TickScheduler::new(start);
+
+        //A tick that overran by a bit leaves the next one due right away
+        let now = start + Duration::from_millis(120);
+        assert_eq!(scheduler.advance(now, interval), None);
+        assert_eq!(scheduler.next_tick(), start + interval);
+        assert_eq!(scheduler.advance(now, interval), None);
+        assert_eq!(scheduler.next_tick(), start + 2 * interval);
+    }
+
+    #[test]
+    fn test_scheduler_skips() {
+        let start = Instant::now();
+        let interval = Duration::from_millis(50);
+        let mut scheduler = TickScheduler::new(start);
+
+        let now = start + Duration::from_secs(5);
+        assert_eq!(
+            scheduler.advance(now, interval),
+            Some(Duration::from_millis(4950))
+        );
+        assert_eq!(scheduler.next_tick(), now);
+    }
+}
@@ -0,0 +1,1003 @@
+use std::fmt;
+
+use bytes::{Bytes, BytesMut};
+use uuid::Uuid;
+
+use crate::component::DataComponent;
+use crate::identifier::Identifier;
+use crate::reader::{ProtocolBufferReaderExt, ProtocolError};
+use crate::writer::ProtocolBufferWriterExt;
+
+//A value that can be written to a packet buffer
+pub trait Encode {
+    fn encode(&self, buffer: &mut BytesMut) -> Result<(), ProtocolError>;
+}
+
+//A value that can be read from a packet buffer
+pub trait Decode: Sized {
+    fn decode(buffer: &mut BytesMut) -> Result<Self, ProtocolError>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct VarInt(pub i32);
+
+//Only Update Section Blocks sends these, and block updates aren't sent yet
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct VarLong(pub i64);
+
+//String with a maximum length of N, checked on both encode and decode
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct BoundedString<const N: usize>(pub String);
+
+//Every byte left in the packet, used for trailing payloads like plugin messages
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RemainingBytes(pub Bytes);
+
+//Java's BitSet, which grows as needed. Sent as a VarInt number of longs followed by the longs,
+//e.g. for the sections of light data.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct BitSet(pub Vec<i64>);
+
+//A BitSet of exactly N bits, sent as just its bytes since both sides know the size.
+//Chat acknowledgements are the one user, and player chat isn't handled yet.
+#[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FixedBitSet<const N: usize>(Box<[u8]>);
+
+macro_rules! impl_primitive {
+    ($($ty:ty => $read:ident, $write:ident);* $(;)?) => {
+        $(
+            impl Encode for $ty {
+                fn encode(&self, buffer: &mut BytesMut) -> Result<(), ProtocolError> {
+                    buffer.$write(self)
+                }
+            }
+
+            impl Decode for $ty {
+                fn decode(buffer: &mut BytesMut) -> Result<Self, ProtocolError> {
+                    buffer.$read()
+                }
+            }
+        )*
+    };
+}
+
+impl_primitive! {
+    bool => read_bool, write_bool;
+    i8 => read_i8, write_i8;
+    u8 => read_u8, write_u8;
+    i16 => read_i16, write_i16;
+    u16 => read_u16, write_u16;
+    i32 => read_i32, write_i32;
+    u32 => read_u32, write_u32;
+    i64 => read_i64, write_i64;
+    u64 => read_u64, write_u64;
+    f32 => read_f32, write_f32;
+    f64 => read_f64, write_f64;
+    Uuid => read_uuid, write_uuid;
+}
+
+impl Encode for VarInt {
+    fn encode(&self, buffer: &mut BytesMut) -> Result<(), ProtocolError> {
+        buffer.write_var_int(&self.0)
+    }
+}
+
+impl Decode for VarInt {
+    fn decode(buffer: &mut BytesMut) -> Result<Self, ProtocolError> {
+        Ok(VarInt(buffer.read_var_int()?))
+    }
+}
+
+impl Encode for VarLong {
+    fn encode(&self, buffer: &mut BytesMut) -> Result<(), ProtocolError> {
+        buffer.write_var_long(&self.0)
+    }
+}
+
+impl Decode for VarLong {
+    fn decode(buffer: &mut BytesMut) -> Result<Self, ProtocolError> {
+        Ok(VarLong(buffer.read_var_long()?))
+    }
+}
+
+impl Encode for String {
+    fn encode(&self, buffer: &mut BytesMut) -> Result<(), ProtocolError> {
+        buffer.write_full_string(self)
+    }
+}
+
+impl Decode for String {
+    fn decode(buffer: &mut BytesMut) -> Result<Self, ProtocolError> {
+        buffer.read_full_string()
+    }
+}
+
+impl<const N: usize> Encode for BoundedString<N> {
+    fn encode(&self, buffer: &mut BytesMut) -> Result<(), ProtocolError> {
+        buffer.write_string(&self.0, N as i32)
+    }
+}
+
+impl<const N: usize> Decode for BoundedString<N> {
+    fn decode(buffer: &mut BytesMut) -> Result<Self, ProtocolError> {
+        Ok(BoundedString(buffer.read_string(N as i32)?))
+    }
+}
+
+impl Encode for RemainingBytes {
+    fn encode(&self, buffer: &mut BytesMut) -> Result<(), ProtocolError> {
+        buffer.extend_from_slice(&self.0);
+        Ok(())
+    }
+}
+
+impl Decode for RemainingBytes {
+    fn decode(buffer: &mut BytesMut) -> Result<Self, ProtocolError> {
+        Ok(RemainingBytes(buffer.split().freeze()))
+    }
+}
+
+impl BitSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //Light data only sets bits, reading them back is for the tests
+    #[allow(dead_code)]
+    pub fn get(&self, index: usize) -> bool {
+        self.0
+            .get(index / 64)
+            .is_some_and(|long| long & (1 << (index % 64)) != 0)
+    }
+
+    pub fn set(&mut self, index: usize, value: bool) {
+        let long = index / 64;
+        let mask = 1 << (index % 64);
+
+        if value {
+            if long >= self.0.len() {
+                self.0.resize(long + 1, 0);
+            }
+            self.0[long] |= mask;
+        } else if let Some(bits) = self.0.get_mut(long) {
+            *bits &= !mask;
+
+            //Java drops trailing empty longs, so do the same to send identical data
+            while self.0.last() == Some(&0) {
+                self.0.pop();
+            }
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(|long| *long == 0)
+    }
+}
+
+impl Encode for BitSet {
+    fn encode(&self, buffer: &mut BytesMut) -> Result<(), ProtocolError> {
+        buffer.write_bitset(&self.0)
+    }
+}
+
+impl Decode for BitSet {
+    fn decode(buffer: &mut BytesMut) -> Result<Self, ProtocolError> {
+        Ok(BitSet(buffer.read_bitset()?))
+    }
+}
+
+#[allow(dead_code)]
+impl<const N: usize> FixedBitSet<N> {
+    pub fn new() -> Self {
+        Self(vec![0; N.div_ceil(8)].into_boxed_slice())
+    }
+
+    pub fn get(&self, index: usize) -> bool {
+        index < N && self.0[index / 8] & (1 << (index % 8)) != 0
+    }
+
+    pub fn set(&mut self, index: usize, value: bool) {
+        assert!(index < N, "Bit {} is out of range for {} bits", index, N);
+
+        if value {
+            self.0[index / 8] |= 1 << (index % 8);
+        } else {
+            self.0[index / 8] &= !(1 << (index % 8));
+        }
+    }
+}
+
+impl<const N: usize> Default for FixedBitSet<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Encode for FixedBitSet<N> {
+    fn encode(&self, buffer: &mut BytesMut) -> Result<(), ProtocolError> {
+        buffer.write_fixed_bitset(&self.0, N)
+    }
+}
+
+impl<const N: usize> Decode for FixedBitSet<N> {
+    fn decode(buffer: &mut BytesMut) -> Result<Self, ProtocolError> {
+        let mut bytes = buffer.read_fixed_bitset(N)?.to_vec();
+
+        //Bits past the end can't be set, so ignore whatever the last byte had there
+        if !N.is_multiple_of(8) {
+            if let Some(last) = bytes.last_mut() {
+                *last &= (1 << (N % 8)) - 1;
+            }
+        }
+
+        Ok(Self(bytes.into_boxed_slice()))
+    }
+}
+
+//Prefixed optional, a boolean followed by the value when present
+impl<T: Encode> Encode for Option<T> {
+    fn encode(&self, buffer: &mut BytesMut) -> Result<(), ProtocolError> {
+        match self {
+            Some(value) => {
+                buffer.write_bool(&true)?;
+                value.encode(buffer)
+            }
+            None => buffer.write_bool(&false),
+        }
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode(buffer: &mut BytesMut) -> Result<Self, ProtocolError> {
+        if buffer.read_bool()? {
+            Ok(Some(T::decode(buffer)?))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+//Prefixed array, a VarInt length followed by every element
+impl<T: Encode> Encode for Vec<T> {
+    fn encode(&self, buffer: &mut BytesMut) -> Result<(), ProtocolError> {
+        let length = i32::try_from(self.len()).map_err(|_| ProtocolError::InvalidLength {
+            length: self.len() as i64,
+            max: i32::MAX as usize,
+        })?;
+        buffer.write_var_int(&length)?;
+
+        for value in self {
+            value.encode(buffer)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode(buffer: &mut BytesMut) -> Result<Self, ProtocolError> {
+        let length = buffer.read_var_int()?;
+
+        //Every element takes at least a byte, so a length past the buffer is always invalid
+        if length < 0 || length as usize > buffer.len() {
+            return Err(ProtocolError::InvalidLength {
+                length: length as i64,
+                max: buffer.len(),
+            });
+        }
+
+        let mut values = Vec::with_capacity(length as usize);
+
+        for _ in 0..length {
+            values.push(T::decode(buffer)?);
+        }
+
+        Ok(values)
+    }
+}
+
+//Declares a struct whose fields are encoded and decoded in declaration order
+macro_rules! protocol_struct {
+    (
+        $(#[$meta:meta])*
+        $name:ident { $($field:ident: $ty:ty),* $(,)? }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, PartialEq)]
+        pub struct $name {
+            $(pub $field: $ty),*
+        }
+
+        impl $crate::types::Encode for $name {
+            #[allow(unused_variables)]
+            fn encode(
+                &self,
+                buffer: &mut bytes::BytesMut,
+            ) -> Result<(), $crate::reader::ProtocolError> {
+                $($crate::types::Encode::encode(&self.$field, buffer)?;)*
+                Ok(())
+            }
+        }
+
+        impl $crate::types::Decode for $name {
+            #[allow(unused_variables)]
+            fn decode(buffer: &mut bytes::BytesMut) -> Result<Self, $crate::reader::ProtocolError> {
+                Ok(Self {
+                    $($field: <$ty as $crate::types::Decode>::decode(buffer)?),*
+                })
+            }
+        }
+    };
+}
+
+pub(crate) use protocol_struct;
+
+//A block position packed into a long: 26 bits of X, 26 bits of Z and 12 bits of Y.
+//Block updates and digging use them, neither of which the play state handles yet.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Position {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+#[allow(dead_code)]
+impl Position {
+    pub const MAX_HORIZONTAL: i32 = (1 << 25) - 1;
+    pub const MIN_HORIZONTAL: i32 = -(1 << 25);
+    pub const MAX_Y: i32 = (1 << 11) - 1;
+    pub const MIN_Y: i32 = -(1 << 11);
+
+    pub fn new(x: i32, y: i32, z: i32) -> Self {
+        Self { x, y, z }
+    }
+
+    fn is_packable(&self) -> bool {
+        let horizontal = Self::MIN_HORIZONTAL..=Self::MAX_HORIZONTAL;
+        horizontal.contains(&self.x)
+            && horizontal.contains(&self.z)
+            && (Self::MIN_Y..=Self::MAX_Y).contains(&self.y)
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}, {}, {}", self.x, self.y, self.z)
+    }
+}
+
+impl Encode for Position {
+    fn encode(&self, buffer: &mut BytesMut) -> Result<(), ProtocolError> {
+        if !self.is_packable() {
+            return Err(ProtocolError::InvalidData(format!(
+                "Position {} doesn't fit in a long",
+                self
+            )));
+        }
+
+        let packed = ((self.x as i64 & 0x3FFFFFF) << 38)
+            | ((self.z as i64 & 0x3FFFFFF) << 12)
+            | (self.y as i64 & 0xFFF);
+        buffer.write_i64(&packed)
+    }
+}
+
+impl Decode for Position {
+    fn decode(buffer: &mut BytesMut) -> Result<Self, ProtocolError> {
+        let packed = buffer.read_i64()?;
+
+        //Shifting left first makes the right shift sign extend each field
+        Ok(Self {
+            x: (packed >> 38) as i32,
+            y: (packed << 52 >> 52) as i32,
+            z: (packed << 26 >> 38) as i32,
+        })
+    }
+}
+
+//A rotation in steps of 1/256 of a full turn, waiting on entities being spawned for players
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Angle(pub u8);
+
+#[allow(dead_code)]
+impl Angle {
+    pub fn from_degrees(degrees: f32) -> Self {
+        Angle((degrees * 256.0 / 360.0).floor() as i32 as u8)
+    }
+
+    pub fn to_degrees(self) -> f32 {
+        self.0 as f32 * 360.0 / 256.0
+    }
+}
+
+impl Encode for Angle {
+    fn encode(&self, buffer: &mut BytesMut) -> Result<(), ProtocolError> {
+        buffer.write_u8(&self.0)
+    }
+}
+
+impl Decode for Angle {
+    fn decode(buffer: &mut BytesMut) -> Result<Self, ProtocolError> {
+        Ok(Angle(buffer.read_u8()?))
+    }
+}
+
+//A number sent as an int with 5 fractional bits, only needed by particles and explosions
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct FixedPoint(pub i32);
+
+#[allow(dead_code)]
+impl FixedPoint {
+    pub const FRACTION_BITS: u32 = 5;
+
+    pub fn from_f64(value: f64) -> Self {
+        FixedPoint((value * (1 << Self::FRACTION_BITS) as f64) as i32)
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / (1 << Self::FRACTION_BITS) as f64
+    }
+}
+
+impl Encode for FixedPoint {
+    fn encode(&self, buffer: &mut BytesMut) -> Result<(), ProtocolError> {
+        buffer.write_i32(&self.0)
+    }
+}
+
+impl Decode for FixedPoint {
+    fn decode(buffer: &mut BytesMut) -> Result<Self, ProtocolError> {
+        Ok(FixedPoint(buffer.read_i32()?))
+    }
+}
+
+//Either the ID of a registry entry or the entry itself, e.g. for sounds that aren't registered.
+//Sent as the ID plus one, with zero meaning the entry follows inline.
+//Nothing sends sounds yet, so only the tests build one.
+#[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum IdOr<T> {
+    Id(i32),
+    Inline(T),
+}
+
+impl<T: Encode> Encode for IdOr<T> {
+    fn encode(&self, buffer: &mut BytesMut) -> Result<(), ProtocolError> {
+        match self {
+            IdOr::Id(id) => {
+                if *id < 0 || *id == i32::MAX {
+                    return Err(ProtocolError::InvalidData(format!("Invalid registry ID {}", id)));
+                }
+
+                buffer.write_var_int(&(id + 1))
+            }
+            IdOr::Inline(value) => {
+                buffer.write_var_int(&0)?;
+                value.encode(buffer)
+            }
+        }
+    }
+}
+
+impl<T: Decode> Decode for IdOr<T> {
+    fn decode(buffer: &mut BytesMut) -> Result<Self, ProtocolError> {
+        match buffer.read_var_int()? {
+            0 => Ok(IdOr::Inline(T::decode(buffer)?)),
+            id if id > 0 => Ok(IdOr::Id(id - 1)),
+            id => Err(ProtocolError::InvalidData(format!("Invalid registry ID {}", id))),
+        }
+    }
+}
+
+//Registry entries given either by a tag or by their IDs. Sent as zero followed by the tag name,
+//or as the number of IDs plus one followed by the IDs.
+//Item components skip over these in place, so only the tests build one.
+#[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum IdSet {
+    Tag(Identifier),
+    Ids(Vec<i32>),
+}
+
+impl Encode for IdSet {
+    fn encode(&self, buffer: &mut BytesMut) -> Result<(), ProtocolError> {
+        match self {
+            IdSet::Tag(tag) => {
+                buffer.write_var_int(&0)?;
+                tag.encode(buffer)
+            }
+            IdSet::Ids(ids) => {
+                let length = i32::try_from(ids.len())
+                    .ok()
+                    .filter(|length| *length < i32::MAX)
+                    .ok_or(ProtocolError::InvalidLength {
+                        length: ids.len() as i64,
+                        max: i32::MAX as usize - 1,
+                    })?;
+                buffer.write_var_int(&(length + 1))?;
+
+                for id in ids {
+                    buffer.write_var_int(id)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Decode for IdSet {
+    fn decode(buffer: &mut BytesMut) -> Result<Self, ProtocolError> {
+        let length = buffer.read_var_int()?;
+        if length == 0 {
+            return Ok(IdSet::Tag(Identifier::decode(buffer)?));
+        }
+
+        //Every ID takes at least a byte
+        if length < 0 || length as usize - 1 > buffer.len() {
+            return Err(ProtocolError::InvalidLength {
+                length: length as i64 - 1,
+                max: buffer.len(),
+            });
+        }
+
+        let mut ids = Vec::with_capacity(length as usize - 1);
+        for _ in 1..length {
+            ids.push(buffer.read_var_int()?);
+        }
+
+        Ok(IdSet::Ids(ids))
+    }
+}
+
+protocol_struct! {
+    //A sound, heard within a fixed range or within a range based on its volume.
+    //Inline sounds are sent with IdOr, which nothing uses yet.
+    #[allow(dead_code)]
+    SoundEvent {
+        sound_name: Identifier,
+        fixed_range: Option<f32>,
+    }
+}
+
+//Which parts of a teleport are relative to the player's current position and rotation.
+//For Synchronize Player Position, sent once players are placed in the world.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct TeleportFlags(pub u8);
+
+#[allow(dead_code)]
+impl TeleportFlags {
+    pub const X: Self = TeleportFlags(0x01);
+    pub const Y: Self = TeleportFlags(0x02);
+    pub const Z: Self = TeleportFlags(0x04);
+    pub const Y_ROT: Self = TeleportFlags(0x08);
+    pub const X_ROT: Self = TeleportFlags(0x10);
+
+    pub fn contains(self, flags: Self) -> bool {
+        self.0 & flags.0 == flags.0
+    }
+}
+
+impl std::ops::BitOr for TeleportFlags {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        TeleportFlags(self.0 | other.0)
+    }
+}
+
+impl Encode for TeleportFlags {
+    fn encode(&self, buffer: &mut BytesMut) -> Result<(), ProtocolError> {
+        buffer.write_u8(&self.0)
+    }
+}
+
+impl Decode for TeleportFlags {
+    fn decode(buffer: &mut BytesMut) -> Result<Self, ProtocolError> {
+        Ok(TeleportFlags(buffer.read_u8()?))
+    }
+}
+
+//A stack of items in an inventory slot
+#[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ItemStack {
+    pub item_id: i32,
+    pub count: i32,
+    //Data components the stack has on top of the item's defaults, like an enchantment or a name
+    pub added_components: Vec<DataComponent>,
+    //IDs of the item's default data components that this stack doesn't have
+    pub removed_components: Vec<i32>,
+}
+
+//An inventory slot, sent as the item count followed by the item when it isn't empty.
+//Inventories aren't sent yet, so only the tests encode and decode slots.
+#[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Slot(pub Option<ItemStack>);
+
+impl Encode for Slot {
+    fn encode(&self, buffer: &mut BytesMut) -> Result<(), ProtocolError> {
+        let Some(stack) = self.0.as_ref().filter(|stack| stack.count > 0) else {
+            return buffer.write_var_int(&0);
+        };
+
+        buffer.write_var_int(&stack.count)?;
+        buffer.write_var_int(&stack.item_id)?;
+        buffer.write_var_int(&(stack.added_components.len() as i32))?;
+        buffer.write_var_int(&(stack.removed_components.len() as i32))?;
+
+        for component in &stack.added_components {
+            component.write(buffer)?;
+        }
+        for component in &stack.removed_components {
+            buffer.write_var_int(component)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Decode for Slot {
+    fn decode(buffer: &mut BytesMut) -> Result<Self, ProtocolError> {
+        let count = buffer.read_var_int()?;
+        if count <= 0 {
+            return Ok(Slot(None));
+        }
+
+        let item_id = buffer.read_var_int()?;
+        let added = buffer.read_var_int()?;
+        let removed = buffer.read_var_int()?;
+
+        //Every component takes at least a byte for its ID
+        for length in [added, removed] {
+            if length < 0 || length as usize > buffer.len() {
+                return Err(ProtocolError::InvalidLength {
+                    length: length as i64,
+                    max: buffer.len(),
+                });
+            }
+        }
+
+        let mut added_components = Vec::with_capacity(added as usize);
+        for _ in 0..added {
+            added_components.push(DataComponent::read(buffer)?);
+        }
+
+        let mut removed_components = Vec::with_capacity(removed as usize);
+        for _ in 0..removed {
+            removed_components.push(buffer.read_var_int()?);
+        }
+
+        Ok(Slot(Some(ItemStack {
+            item_id,
+            count,
+            added_components,
+            removed_components,
+        })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    protocol_struct! {
+        TestStruct {
+            id: VarInt,
+            name: BoundedString<16>,
+            tags: Vec<String>,
+            extra: Option<u16>,
+        }
+    }
+
+    #[test]
+    fn test_protocol_struct_round_trip() {
+        let value = TestStruct {
+            id: VarInt(300),
+            name: BoundedString("Steve".to_owned()),
+            tags: vec!["a".to_owned(), "b".to_owned()],
+            extra: Some(25565),
+        };
+
+        let mut buffer = BytesMut::new();
+        value.encode(&mut buffer).unwrap();
+
+        assert_eq!(TestStruct::decode(&mut buffer).unwrap(), value);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_bounded_string_too_long() {
+        let mut buffer = BytesMut::new();
+        BoundedString::<32>("ThisNameIsWayTooLong".to_owned()).encode(&mut buffer).unwrap();
+
+        assert!(BoundedString::<16>::decode(&mut buffer).is_err());
+        assert!(matches!(
+            BoundedString::<16>("ThisNameIsWayTooLong".to_owned()).encode(&mut buffer),
+            Err(ProtocolError::StringTooLong { max: 16, actual: 20 })
+        ));
+    }
+
+    #[test]
+    fn test_array_length_past_buffer() {
+        let mut buffer = BytesMut::new();
+        buffer.write_var_int(&1000).unwrap();
+        buffer.write_u8(&1).unwrap();
+
+        assert_eq!(
+            Vec::<u8>::decode(&mut buffer),
+            Err(ProtocolError::InvalidLength {
+                length: 1000,
+                max: 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_bitset() {
+        let mut bits = BitSet::new();
+        bits.set(1, true);
+        bits.set(70, true);
+
+        assert!(bits.get(1) && bits.get(70));
+        assert!(!bits.get(2) && !bits.get(1000));
+        assert_eq!(bits.0, vec![0b10, 0b1000000]);
+
+        bits.set(70, false);
+        assert_eq!(bits.0, vec![0b10]);
+
+        let mut buffer = BytesMut::new();
+        bits.encode(&mut buffer).unwrap();
+        assert_eq!(&buffer[..], &[1, 0, 0, 0, 0, 0, 0, 0, 0b10]);
+        assert_eq!(BitSet::decode(&mut buffer).unwrap(), bits);
+    }
+
+    #[test]
+    fn test_fixed_bitset() {
+        let mut bits = FixedBitSet::<10>::new();
+        bits.set(0, true);
+        bits.set(9, true);
+
+        let mut buffer = BytesMut::new();
+        bits.encode(&mut buffer).unwrap();
+        assert_eq!(&buffer[..], &[0b1, 0b10]);
+
+        //Bits past the size are dropped
+        buffer[1] |= 0b10000000;
+        let decoded = FixedBitSet::<10>::decode(&mut buffer).unwrap();
+        assert_eq!(decoded, bits);
+        assert!(decoded.get(9) && !decoded.get(10));
+    }
+
+    proptest! {
+        #[test]
+        fn test_bitset_round_trip(indexes in prop::collection::vec(0usize..512, 0..32)) {
+            let mut bits = BitSet::new();
+            for index in &indexes {
+                bits.set(*index, true);
+            }
+
+            let mut buffer = BytesMut::new();
+            bits.encode(&mut buffer).unwrap();
+            let decoded = BitSet::decode(&mut buffer).unwrap();
+
+            for index in 0..512 {
+                prop_assert_eq!(decoded.get(index), indexes.contains(&index));
+            }
+        }
+
+        #[test]
+        fn test_fixed_bitset_round_trip(indexes in prop::collection::vec(0usize..20, 0..20)) {
+            let mut bits = FixedBitSet::<20>::new();
+            for index in &indexes {
+                bits.set(*index, true);
+            }
+
+            let mut buffer = BytesMut::new();
+            bits.encode(&mut buffer).unwrap();
+            prop_assert_eq!(buffer.len(), 3);
+            prop_assert_eq!(FixedBitSet::<20>::decode(&mut buffer).unwrap(), bits);
+        }
+    }
+
+    #[test]
+    fn test_position() {
+        //The example from the protocol documentation
+        let position = Position::new(18357644, 831, -20882616);
+        let mut buffer = BytesMut::new();
+        position.encode(&mut buffer).unwrap();
+
+        assert_eq!(&buffer[..], &0x4607632C15B4833Fu64.to_be_bytes());
+        assert_eq!(Position::decode(&mut buffer).unwrap(), position);
+
+        assert!(Position::new(0, 2048, 0).encode(&mut buffer).is_err());
+        assert!(Position::new(1 << 25, 0, 0).encode(&mut buffer).is_err());
+    }
+
+    #[test]
+    fn test_angle_and_fixed_point() {
+        assert_eq!(Angle::from_degrees(90.0), Angle(64));
+        assert_eq!(Angle::from_degrees(-90.0), Angle(192));
+        assert_eq!(Angle(128).to_degrees(), 180.0);
+
+        assert_eq!(FixedPoint::from_f64(-1.5), FixedPoint(-48));
+        assert_eq!(FixedPoint(16).to_f64(), 0.5);
+    }
+
+    #[test]
+    fn test_id_or() {
+        let mut buffer = BytesMut::new();
+        IdOr::<VarInt>::Id(0).encode(&mut buffer).unwrap();
+        IdOr::Inline(VarInt(7)).encode(&mut buffer).unwrap();
+
+        assert_eq!(&buffer[..], &[1, 0, 7]);
+        assert_eq!(IdOr::decode(&mut buffer).unwrap(), IdOr::<VarInt>::Id(0));
+        assert_eq!(IdOr::decode(&mut buffer).unwrap(), IdOr::Inline(VarInt(7)));
+        assert!(IdOr::<VarInt>::Id(-1).encode(&mut buffer).is_err());
+    }
+
+    #[test]
+    fn test_id_set() {
+        let mut buffer = BytesMut::new();
+        IdSet::Ids(vec![3, 4]).encode(&mut buffer).unwrap();
+        assert_eq!(&buffer[..], &[3, 3, 4]);
+        assert_eq!(IdSet::decode(&mut buffer).unwrap(), IdSet::Ids(vec![3, 4]));
+
+        let tag = IdSet::Tag(Identifier::minecraft("logs"));
+        tag.encode(&mut buffer).unwrap();
+        assert_eq!(buffer[0], 0);
+        assert_eq!(IdSet::decode(&mut buffer).unwrap(), tag);
+
+        buffer.write_var_int(&100).unwrap();
+        assert!(IdSet::decode(&mut buffer).is_err());
+    }
+
+    #[test]
+    fn test_teleport_flags() {
+        let flags = TeleportFlags::X | TeleportFlags::Y_ROT;
+
+        assert_eq!(flags, TeleportFlags(0x09));
+        assert!(flags.contains(TeleportFlags::X));
+        assert!(!flags.contains(TeleportFlags::X | TeleportFlags::Z));
+
+        //Every flag is its own bit, in the order vanilla's RelativeMovement lists them
+        let all = TeleportFlags::X
+            | TeleportFlags::Y
+            | TeleportFlags::Z
+            | TeleportFlags::Y_ROT
+            | TeleportFlags::X_ROT;
+        assert_eq!(all, TeleportFlags(0x1F));
+        assert!(all.contains(TeleportFlags::Y) && all.contains(TeleportFlags::X_ROT));
+
+        let mut buffer = BytesMut::new();
+        all.encode(&mut buffer).unwrap();
+        assert_eq!(&buffer[..], &[0x1F]);
+        assert_eq!(TeleportFlags::decode(&mut buffer).unwrap(), all);
+    }
+
+    #[test]
+    fn test_slot() {
+        let mut buffer = BytesMut::new();
+        Slot(None).encode(&mut buffer).unwrap();
+        assert_eq!(&buffer[..], &[0]);
+        assert_eq!(Slot::decode(&mut buffer).unwrap(), Slot(None));
+
+        let slot = Slot(Some(ItemStack {
+            item_id: 1,
+            count: 64,
+            added_components: Vec::new(),
+            removed_components: vec![5],
+        }));
+        slot.encode(&mut buffer).unwrap();
+        assert_eq!(&buffer[..], &[64, 1, 0, 1, 5]);
+        assert_eq!(Slot::decode(&mut buffer).unwrap(), slot);
+
+        //A damaged sword with Sharpness V: damage 10, then one enchantment and its tooltip shown,
+        //followed by the next field of the packet
+        buffer.extend_from_slice(&[1, 0x2a, 2, 0, 3, 10, 9, 1, 13, 5, 1, 0xff]);
+        let Slot(Some(stack)) = Slot::decode(&mut buffer).unwrap() else {
+            panic!("The slot is empty");
+        };
+        assert_eq!(stack.added_components.len(), 2);
+        assert_eq!(&stack.added_components[1].data[..], &[1, 13, 5, 1]);
+        assert_eq!(&buffer[..], &[0xff]);
+
+        Slot(Some(stack)).encode(&mut buffer).unwrap();
+        assert_eq!(&buffer[1..], &[1, 0x2a, 2, 0, 3, 10, 9, 1, 13, 5, 1]);
+        buffer.clear();
+
+        //Unknown component types can't be skipped
+        buffer.extend_from_slice(&[1, 1, 1, 0, 99]);
+        assert!(Slot::decode(&mut buffer).is_err());
+    }
+
+    fn round_trip<T: Encode + Decode + PartialEq + std::fmt::Debug>(
+        value: &T,
+    ) -> Result<(), TestCaseError> {
+        let mut buffer = BytesMut::new();
+        value.encode(&mut buffer).unwrap();
+
+        prop_assert_eq!(&T::decode(&mut buffer).unwrap(), value);
+        prop_assert!(buffer.is_empty());
+        Ok(())
+    }
+
+    fn identifier() -> impl Strategy<Value = Identifier> {
+        ("[a-z0-9_.-]{1,8}", "[a-z0-9_./-]{1,16}")
+            .prop_map(|(namespace, path)| Identifier::new(&namespace, &path).unwrap())
+    }
+
+    proptest! {
+        #[test]
+        fn test_position_round_trip(
+            x in Position::MIN_HORIZONTAL..=Position::MAX_HORIZONTAL,
+            y in Position::MIN_Y..=Position::MAX_Y,
+            z in Position::MIN_HORIZONTAL..=Position::MAX_HORIZONTAL,
+        ) {
+            round_trip(&Position::new(x, y, z))?;
+        }
+
+        #[test]
+        fn test_simple_types_round_trip(value: u8, fixed: i32) {
+            round_trip(&Angle(value))?;
+            round_trip(&TeleportFlags(value))?;
+            round_trip(&FixedPoint(fixed))?;
+        }
+
+        #[test]
+        fn test_var_types_round_trip(int: i32, long: i64) {
+            round_trip(&VarInt(int))?;
+            round_trip(&VarLong(long))?;
+        }
+
+        #[test]
+        fn test_prefixed_types_round_trip(
+            optional: Option<i64>,
+            array in prop::collection::vec(any::<String>(), 0..8),
+        ) {
+            round_trip(&optional)?;
+            round_trip(&array)?;
+        }
+
+        #[test]
+        fn test_id_or_round_trip(id in 0..i32::MAX, sound in identifier(), range: Option<f32>) {
+            round_trip(&IdOr::<SoundEvent>::Id(id))?;
+
+            //NaN ranges wouldn't compare equal
+            let fixed_range = range.filter(|range| !range.is_nan());
+            round_trip(&IdOr::Inline(SoundEvent { sound_name: sound, fixed_range }))?;
+        }
+
+        #[test]
+        fn test_id_set_round_trip(tag in identifier(), ids: Vec<i32>) {
+            round_trip(&IdSet::Tag(tag))?;
+            round_trip(&IdSet::Ids(ids))?;
+        }
+
+        #[test]
+        fn test_slot_round_trip(item_id: i32, count in 1..=i32::MAX, removed_components: Vec<i32>) {
+            let added_components = Vec::new();
+            round_trip(&Slot(Some(ItemStack { item_id, count, added_components, removed_components })))?;
+        }
+    }
+
+    #[test]
+    fn test_remaining_bytes() {
+        let mut buffer = BytesMut::from(&[1u8, 2, 3][..]);
+
+        assert_eq!(
+            RemainingBytes::decode(&mut buffer).unwrap(),
+            RemainingBytes(Bytes::from_static(&[1, 2, 3]))
+        );
+        assert!(buffer.is_empty());
+    }
+}
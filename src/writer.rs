@@ -1,408 +1,658 @@
-use crate::reader::BufferError;
-use bytes::{Buf, BufMut, Bytes, BytesMut};
-use std::error::Error;
-use std::str;
+use crate::reader::ProtocolError;
+use bytes::BufMut;
 use uuid::Uuid;
 
 const SEGMENT_BITS: i32 = 0x7F;
 const CONTINUE_BIT: i32 = 0x80;
 
+//Writes protocol types to any buffer, failing instead of panicking when a fixed size
+//buffer like &mut [u8] runs out of space
 pub trait ProtocolBufferWriterExt {
-    fn write_bool(&mut self, value: &bool);
-    fn write_i8(&mut self, value: &i8);
-    fn write_u8(&mut self, value: &u8);
-    fn write_i16(&mut self, value: &i16);
-    fn write_u16(&mut self, value: &u16);
-    fn write_i32(&mut self, value: &i32);
-    fn write_u32(&mut self, value: &u32);
-    fn write_i64(&mut self, value: &i64);
-    fn write_u64(&mut self, value: &u64);
-    fn write_f32(&mut self, value: &f32);
-    fn write_f64(&mut self, value: &f64);
-    fn write_string(&mut self, value: &str, max_size: i32);
-    fn write_full_string(&mut self, value: &str);
-    fn write_var_int(&mut self, value: &i32);
-    fn write_var_long(&mut self, value: &i64);
-    fn write_uuid(&mut self, value: &Uuid);
-    fn write_option<T>(&mut self, value: Option<T>, function: impl Fn(&mut Self, &T));
-    fn write_array<T>(&mut self, value: Vec<T>, function: impl Fn(&mut Self, &T));
-    fn write_bitset(&mut self, value: &[i64]);
+    fn write_bool(&mut self, value: &bool) -> Result<(), ProtocolError>;
+    fn write_i8(&mut self, value: &i8) -> Result<(), ProtocolError>;
+    fn write_u8(&mut self, value: &u8) -> Result<(), ProtocolError>;
+    fn write_i16(&mut self, value: &i16) -> Result<(), ProtocolError>;
+    fn write_u16(&mut self, value: &u16) -> Result<(), ProtocolError>;
+    fn write_i32(&mut self, value: &i32) -> Result<(), ProtocolError>;
+    fn write_u32(&mut self, value: &u32) -> Result<(), ProtocolError>;
+    fn write_i64(&mut self, value: &i64) -> Result<(), ProtocolError>;
+    fn write_u64(&mut self, value: &u64) -> Result<(), ProtocolError>;
+    fn write_f32(&mut self, value: &f32) -> Result<(), ProtocolError>;
+    fn write_f64(&mut self, value: &f64) -> Result<(), ProtocolError>;
+    fn write_string(&mut self, value: &str, max_size: i32) -> Result<(), ProtocolError>;
+    fn write_full_string(&mut self, value: &str) -> Result<(), ProtocolError>;
+    fn write_var_int(&mut self, value: &i32) -> Result<(), ProtocolError>;
+    fn write_var_long(&mut self, value: &i64) -> Result<(), ProtocolError>;
+    fn write_uuid(&mut self, value: &Uuid) -> Result<(), ProtocolError>;
+    //Packets encode optional fields through Option's Encode, no hand written writer needs this yet
+    #[allow(dead_code)]
+    fn write_option<T>(
+        &mut self,
+        value: Option<T>,
+        function: impl Fn(&mut Self, &T) -> Result<(), ProtocolError>,
+    ) -> Result<(), ProtocolError>;
+    fn write_array<T>(
+        &mut self,
+        value: &[T],
+        function: impl Fn(&mut Self, &T) -> Result<(), ProtocolError>,
+    ) -> Result<(), ProtocolError>;
+    fn write_bitset(&mut self, value: &[i64]) -> Result<(), ProtocolError>;
+    fn write_fixed_bitset(&mut self, value: &[u8], bits: usize) -> Result<(), ProtocolError>;
 }
 
-impl ProtocolBufferWriterExt for BytesMut {
-    fn write_bool(&mut self, value: &bool) {
-        if *value {
-            self.put_u8(1);
-        } else {
-            self.put_u8(0);
-        }
+fn write_length(buffer: &mut impl BufMut, length: usize) -> Result<(), ProtocolError> {
+    let length = i32::try_from(length).map_err(|_| ProtocolError::InvalidLength {
+        length: length as i64,
+        max: i32::MAX as usize,
+    })?;
+
+    buffer.write_var_int(&length)
+}
+
+fn ensure_remaining_mut(buffer: &impl BufMut, needed: usize) -> Result<(), ProtocolError> {
+    if buffer.remaining_mut() < needed {
+        return Err(ProtocolError::BufferFull {
+            needed,
+            available: buffer.remaining_mut(),
+        });
+    }
+
+    Ok(())
+}
+
+impl<B: BufMut> ProtocolBufferWriterExt for B {
+    fn write_bool(&mut self, value: &bool) -> Result<(), ProtocolError> {
+        self.write_u8(&(*value as u8))
     }
 
-    fn write_i8(&mut self, value: &i8) {
+    fn write_i8(&mut self, value: &i8) -> Result<(), ProtocolError> {
+        ensure_remaining_mut(self, 1)?;
         self.put_i8(*value);
+        Ok(())
     }
 
-    fn write_u8(&mut self, value: &u8) {
+    fn write_u8(&mut self, value: &u8) -> Result<(), ProtocolError> {
+        ensure_remaining_mut(self, 1)?;
         self.put_u8(*value);
+        Ok(())
     }
 
-    fn write_i16(&mut self, value: &i16) {
+    fn write_i16(&mut self, value: &i16) -> Result<(), ProtocolError> {
+        ensure_remaining_mut(self, 2)?;
         self.put_i16(*value);
+        Ok(())
     }
 
-    fn write_u16(&mut self, value: &u16) {
+    fn write_u16(&mut self, value: &u16) -> Result<(), ProtocolError> {
+        ensure_remaining_mut(self, 2)?;
         self.put_u16(*value);
+        Ok(())
     }
 
-    fn write_i32(&mut self, value: &i32) {
+    fn write_i32(&mut self, value: &i32) -> Result<(), ProtocolError> {
+        ensure_remaining_mut(self, 4)?;
         self.put_i32(*value);
+        Ok(())
     }
 
-    fn write_u32(&mut self, value: &u32) {
+    fn write_u32(&mut self, value: &u32) -> Result<(), ProtocolError> {
+        ensure_remaining_mut(self, 4)?;
         self.put_u32(*value);
+        Ok(())
     }
 
-    fn write_i64(&mut self, value: &i64) {
+    fn write_i64(&mut self, value: &i64) -> Result<(), ProtocolError> {
+        ensure_remaining_mut(self, 8)?;
         self.put_i64(*value);
+        Ok(())
     }
 
-    fn write_u64(&mut self, value: &u64) {
+    fn write_u64(&mut self, value: &u64) -> Result<(), ProtocolError> {
+        ensure_remaining_mut(self, 8)?;
         self.put_u64(*value);
+        Ok(())
     }
 
-    fn write_f32(&mut self, value: &f32) {
+    fn write_f32(&mut self, value: &f32) -> Result<(), ProtocolError> {
+        ensure_remaining_mut(self, 4)?;
         self.put_f32(*value);
+        Ok(())
     }
 
-    fn write_f64(&mut self, value: &f64) {
+    fn write_f64(&mut self, value: &f64) -> Result<(), ProtocolError> {
+        ensure_remaining_mut(self, 8)?;
         self.put_f64(*value);
+        Ok(())
     }
 
-    fn write_string(&mut self, value: &str, max_size: i32) {
-        if value.len() as i32 > max_size {
-            panic!("String is too big!")
+    //The maximum size is in UTF-16 code units, like vanilla
+    fn write_string(&mut self, value: &str, max_size: i32) -> Result<(), ProtocolError> {
+        let max = max_size.max(0) as usize;
+        let actual = value.encode_utf16().count();
+
+        if actual > max {
+            return Err(ProtocolError::StringTooLong { max, actual });
         }
 
-        self.write_var_int(&(value.len() as i32));
-        self.put(value.as_bytes());
+        self.write_var_int(&(value.len() as i32))?;
+        ensure_remaining_mut(self, value.len())?;
+        self.put_slice(value.as_bytes());
+        Ok(())
     }
 
-    fn write_full_string(&mut self, value: &str) {
-        self.write_string(value, i16::MAX.into());
+    fn write_full_string(&mut self, value: &str) -> Result<(), ProtocolError> {
+        self.write_string(value, i16::MAX.into())
     }
 
-    fn write_var_int(&mut self, mut value: &i32) {
-        let mut final_value = *value;
+    //Negative values are written as their unsigned two's complement, always taking 5 bytes
+    fn write_var_int(&mut self, value: &i32) -> Result<(), ProtocolError> {
+        let mut final_value = *value as u32;
         loop {
-            if (final_value & !SEGMENT_BITS) == 0 {
-                self.write_u8(&(final_value as u8));
-                return;
+            if (final_value & !(SEGMENT_BITS as u32)) == 0 {
+                return self.write_u8(&(final_value as u8));
             }
 
-            self.write_u8(&(((final_value & SEGMENT_BITS) | CONTINUE_BIT) as u8));
+            self.write_u8(&(((final_value & SEGMENT_BITS as u32) | CONTINUE_BIT as u32) as u8))?;
 
             final_value >>= 7;
         }
     }
 
-    fn write_var_long(&mut self, value: &i64) {
-        let mut final_value = *value;
+    fn write_var_long(&mut self, value: &i64) -> Result<(), ProtocolError> {
+        let mut final_value = *value as u64;
         loop {
-            if (final_value & !SEGMENT_BITS as i64) == 0 {
-                self.write_u8(&(final_value as u8));
-                return;
+            if (final_value & !(SEGMENT_BITS as u64)) == 0 {
+                return self.write_u8(&(final_value as u8));
             }
 
-            self.write_u8(&(((final_value & SEGMENT_BITS as i64) | CONTINUE_BIT as i64) as u8));
+            self.write_u8(&(((final_value & SEGMENT_BITS as u64) | CONTINUE_BIT as u64) as u8))?;
 
             final_value >>= 7;
         }
     }
 
-    fn write_uuid(&mut self, value: &Uuid) {
-        let bytes = value.as_bytes();
-
-        let low = u64::from_be_bytes([
-            bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
-        ]);
+    //The most significant half first, like Java's getMostSignificantBits
+    fn write_uuid(&mut self, value: &Uuid) -> Result<(), ProtocolError> {
+        let (high, low) = value.as_u64_pair();
 
-        let high = u64::from_be_bytes([
-            bytes[8], bytes[9], bytes[10], bytes[11], bytes[12], bytes[13], bytes[14], bytes[15],
-        ]);
-
-        self.write_u64(&low);
-        self.write_u64(&high);
+        self.write_u64(&high)?;
+        self.write_u64(&low)
     }
 
-    fn write_option<T>(&mut self, value: Option<T>, function: impl Fn(&mut Self, &T)) {
-        if value.is_none() {
-            self.write_bool(&false);
-        } else {
-            self.write_bool(&true);
-            function(self, &value.unwrap())
+    fn write_option<T>(
+        &mut self,
+        value: Option<T>,
+        function: impl Fn(&mut Self, &T) -> Result<(), ProtocolError>,
+    ) -> Result<(), ProtocolError> {
+        match value {
+            None => self.write_bool(&false),
+            Some(value) => {
+                self.write_bool(&true)?;
+                function(self, &value)
+            }
         }
     }
 
-    fn write_array<T>(&mut self, value: Vec<T>, function: impl Fn(&mut Self, &T)) {
-        for i in 0..value.len() {
-            function(self, &value[i])
+    //A VarInt length followed by every element
+    fn write_array<T>(
+        &mut self,
+        value: &[T],
+        function: impl Fn(&mut Self, &T) -> Result<(), ProtocolError>,
+    ) -> Result<(), ProtocolError> {
+        write_length(self, value.len())?;
+
+        for element in value {
+            function(self, element)?;
         }
+
+        Ok(())
     }
 
-    fn write_bitset(&mut self, value: &[i64]) {
-        self.write_var_int(&value.len().try_into().unwrap());
+    //A VarInt number of longs followed by the longs
+    fn write_bitset(&mut self, value: &[i64]) -> Result<(), ProtocolError> {
+        write_length(self, value.len())?;
 
-        for byte in value {
-            println!("{:X}",*byte);
-            self.write_i64(byte);
+        for long in value {
+            self.write_i64(long)?;
         }
+
+        Ok(())
+    }
+
+    //Just the bytes, which have to be exactly enough for the given number of bits
+    fn write_fixed_bitset(&mut self, value: &[u8], bits: usize) -> Result<(), ProtocolError> {
+        if value.len() != bits.div_ceil(8) {
+            return Err(ProtocolError::InvalidLength {
+                length: value.len() as i64,
+                max: bits.div_ceil(8),
+            });
+        }
+
+        ensure_remaining_mut(self, value.len())?;
+        self.put_slice(value);
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::str::FromStr;
-
     use super::*;
 
+    use bytes::{Bytes, BytesMut};
+    use proptest::prelude::*;
     use rand::RngCore;
     use uuid::{Uuid, Builder, Variant, Version};
     use crate::reader::ProtocolBufferReaderExt;
 
-    #[cfg(test)]
-    mod tests {
-        use super::*;
-        use bytes::BytesMut;
-        use uuid::Uuid;
+    #[test]
+    fn test_write_to_full_slice() {
+        let mut data = [0u8; 3];
+        let mut slice = &mut data[..];
+
+        slice.write_u16(&0x1234).unwrap();
+        assert_eq!(
+            slice.write_i32(&1),
+            Err(ProtocolError::BufferFull {
+                needed: 4,
+                available: 1
+            })
+        );
+        assert_eq!(data, [0x12, 0x34, 0x00]);
+    }
 
-        #[test]
-        fn test_write_bool() {
-            let value = true;
-            let mut buffer = BytesMut::new();
+    #[test]
+    fn test_write_bool() {
+        let value = true;
+        let mut buffer = BytesMut::new();
 
-            // Write the value
-            buffer.write_bool(&value);
+        // Write the value
+        buffer.write_bool(&value).unwrap();
 
-            // Read the value back
-            let result = buffer.read_bool().unwrap();
-            assert_eq!(result, value);
-        }
+        // Read the value back
+        let result = buffer.read_bool().unwrap();
+        assert_eq!(result, value);
+    }
 
-        #[test]
-        fn test_write_i8() {
-            let value: i8 = -123;
-            let mut buffer = BytesMut::new();
+    #[test]
+    fn test_write_i8() {
+        let value: i8 = -123;
+        let mut buffer = BytesMut::new();
 
-            // Write the value
-            buffer.write_i8(&value);
+        // Write the value
+        buffer.write_i8(&value).unwrap();
 
-            // Read the value back
-            let result = buffer.read_i8().unwrap();
-            assert_eq!(result, value);
-        }
+        // Read the value back
+        let result = buffer.read_i8().unwrap();
+        assert_eq!(result, value);
+    }
 
-        #[test]
-        fn test_write_u8() {
-            let value: u8 = 200;
-            let mut buffer = BytesMut::new();
+    #[test]
+    fn test_write_u8() {
+        let value: u8 = 200;
+        let mut buffer = BytesMut::new();
 
-            // Write the value
-            buffer.write_u8(&value);
+        // Write the value
+        buffer.write_u8(&value).unwrap();
 
-            // Read the value back
-            let result = buffer.read_u8().unwrap();
-            assert_eq!(result, value);
-        }
+        // Read the value back
+        let result = buffer.read_u8().unwrap();
+        assert_eq!(result, value);
+    }
 
-        #[test]
-        fn test_write_i16() {
-            let value: i16 = -32000;
-            let mut buffer = BytesMut::new();
+    #[test]
+    fn test_write_i16() {
+        let value: i16 = -32000;
+        let mut buffer = BytesMut::new();
 
-            // Write the value
-            buffer.write_i16(&value);
+        // Write the value
+        buffer.write_i16(&value).unwrap();
 
-            // Read the value back
-            let result = buffer.read_i16().unwrap();
-            assert_eq!(result, value);
-        }
+        // Read the value back
+        let result = buffer.read_i16().unwrap();
+        assert_eq!(result, value);
+    }
 
-        #[test]
-        fn test_write_u16() {
-            let value: u16 = 65000;
-            let mut buffer = BytesMut::new();
+    #[test]
+    fn test_write_u16() {
+        let value: u16 = 65000;
+        let mut buffer = BytesMut::new();
 
-            // Write the value
-            buffer.write_u16(&value);
+        // Write the value
+        buffer.write_u16(&value).unwrap();
 
-            // Read the value back
-            let result = buffer.read_u16().unwrap();
-            assert_eq!(result, value);
-        }
+        // Read the value back
+        let result = buffer.read_u16().unwrap();
+        assert_eq!(result, value);
+    }
 
-        #[test]
-        fn test_write_i32() {
-            let value: i32 = -2000000000;
-            let mut buffer = BytesMut::new();
+    #[test]
+    fn test_write_i32() {
+        let value: i32 = -2000000000;
+        let mut buffer = BytesMut::new();
 
-            // Write the value
-            buffer.write_i32(&value);
+        // Write the value
+        buffer.write_i32(&value).unwrap();
 
-            // Read the value back
-            let result = buffer.read_i32().unwrap();
-            assert_eq!(result, value);
-        }
+        // Read the value back
+        let result = buffer.read_i32().unwrap();
+        assert_eq!(result, value);
+    }
 
-        #[test]
-        fn test_write_u32() {
-            let value: u32 = 4000000000;
-            let mut buffer = BytesMut::new();
+    #[test]
+    fn test_write_u32() {
+        let value: u32 = 4000000000;
+        let mut buffer = BytesMut::new();
 
-            // Write the value
-            buffer.write_u32(&value);
+        // Write the value
+        buffer.write_u32(&value).unwrap();
 
-            // Read the value back
-            let result = buffer.read_u32().unwrap();
-            assert_eq!(result, value);
-        }
+        // Read the value back
+        let result = buffer.read_u32().unwrap();
+        assert_eq!(result, value);
+    }
 
-        #[test]
-        fn test_write_i64() {
-            let value: i64 = -9000000000000000000;
-            let mut buffer = BytesMut::new();
+    #[test]
+    fn test_write_i64() {
+        let value: i64 = -9000000000000000000;
+        let mut buffer = BytesMut::new();
 
-            // Write the value
-            buffer.write_i64(&value);
+        // Write the value
+        buffer.write_i64(&value).unwrap();
 
-            // Read the value back
-            let result = buffer.read_i64().unwrap();
-            assert_eq!(result, value);
-        }
+        // Read the value back
+        let result = buffer.read_i64().unwrap();
+        assert_eq!(result, value);
+    }
 
-        #[test]
-        fn test_write_u64() {
-            let value: u64 = 18000000000000000000;
-            let mut buffer = BytesMut::new();
+    #[test]
+    fn test_write_u64() {
+        let value: u64 = 18000000000000000000;
+        let mut buffer = BytesMut::new();
 
-            // Write the value
-            buffer.write_u64(&value);
+        // Write the value
+        buffer.write_u64(&value).unwrap();
 
-            // Read the value back
-            let result = buffer.read_u64().unwrap();
-            assert_eq!(result, value);
-        }
+        // Read the value back
+        let result = buffer.read_u64().unwrap();
+        assert_eq!(result, value);
+    }
+
+    #[test]
+    fn test_write_f32() {
+        let value: f32 = 1.5;
+        let mut buffer = BytesMut::new();
+
+        // Write the value
+        buffer.write_f32(&value).unwrap();
+
+        // Read the value back
+        let result = buffer.read_f32().unwrap();
+        assert_eq!(result, value);
+    }
+
+    #[test]
+    fn test_write_f64() {
+        let value: f64 = -0.125;
+        let mut buffer = BytesMut::new();
 
+        // Write the value
+        buffer.write_f64(&value).unwrap();
+
+        // Read the value back
+        let result = buffer.read_f64().unwrap();
+        assert_eq!(result, value);
+    }
+
+    #[test]
+    fn test_write_string() {
+        let value = "Hello, world!";
+        let mut buffer = BytesMut::new();
+
+        // Write the value
+        buffer.write_string(value, 256).unwrap();
+
+        // Read the value back
+        let result = buffer.read_string(256).unwrap();
+        assert_eq!(result, value);
+    }
+
+    #[test]
+    fn test_write_full_string() {
+        let value = "Full test string!";
+        let mut buffer = BytesMut::new();
+
+        // Write the value
+        buffer.write_full_string(value).unwrap();
+
+        // Read the value back
+        let result = buffer.read_full_string().unwrap();
+        assert_eq!(result, value);
+    }
+
+    #[test]
+    fn test_write_var_int() {
+        let value: i32 = 123456;
+        let mut buffer = BytesMut::new();
+
+        // Write the value
+        buffer.write_var_int(&value).unwrap();
+
+        // Read the value back
+        let result = buffer.read_var_int().unwrap();
+        assert_eq!(result, value);
+    }
+
+    #[test]
+    fn test_write_var_long() {
+        let value: i64 = 1234567890123456789;
+        let mut buffer = BytesMut::new();
+
+        // Write the value
+        buffer.write_var_long(&value).unwrap();
+
+        // Read the value back
+        let result = buffer.read_var_long().unwrap();
+        assert_eq!(result, value);
+    }
+
+    #[test]
+    fn test_write_uuid() {
+        let mut bytes = [0u8; 16];
+
+        rand::thread_rng().fill_bytes(&mut bytes);
+    
+        let mut binding = Builder::from_bytes(bytes);
+        let value = binding
+            .set_variant(Variant::RFC4122)
+            .set_version(Version::Random).as_uuid();
+
+
+        let mut buffer = BytesMut::new();
+
+        // Write the value
+        buffer.write_uuid(value).unwrap();
+
+        // Read the value back
+        let result = buffer.read_uuid().unwrap();
+        assert_eq!(result, *value);
+    }
+
+    #[test]
+    fn test_write_uuid_byte_order() {
+        let value = Uuid::from_u128(0x069a79f444e94726a5befca90e38aaf5);
+        let mut buffer = BytesMut::new();
+
+        buffer.write_uuid(&value).unwrap();
+        assert_eq!(&buffer[..], &value.as_u128().to_be_bytes());
+    }
+
+    #[test]
+    fn test_write_negative_var_int() {
+        let mut buffer = BytesMut::new();
+
+        buffer.write_var_int(&-1).unwrap();
+        assert_eq!(&buffer[..], &[0xFF, 0xFF, 0xFF, 0xFF, 0x0F]);
+        assert_eq!(buffer.read_var_int().unwrap(), -1);
+
+        buffer.write_var_long(&i64::MIN).unwrap();
+        assert_eq!(buffer.len(), 10);
+        assert_eq!(buffer.read_var_long().unwrap(), i64::MIN);
+    }
+
+    #[test]
+    fn test_write_string_too_long() {
+        let mut buffer = BytesMut::new();
+
+        assert_eq!(
+            buffer.write_string("Hello", 4),
+            Err(ProtocolError::StringTooLong { max: 4, actual: 5 })
+        );
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_write_bitset() {
+        let value: Vec<i64> = vec![0b10101010, 0b11110000];
+        let mut buffer = BytesMut::new();
+
+        // Write the value
+        buffer.write_bitset(&value).unwrap();
+
+        // Read the value back
+        let result = buffer.read_bitset().unwrap();
+        assert_eq!(result, value);
+    }
+
+    #[test]
+    fn test_write_fixed_bitset() {
+        let mut buffer = BytesMut::new();
+
+        buffer.write_fixed_bitset(&[0b10101010, 0b11], 10).unwrap();
+        assert_eq!(
+            buffer.write_fixed_bitset(&[0b10101010], 10),
+            Err(ProtocolError::InvalidLength { length: 1, max: 2 })
+        );
+
+        let result = buffer.read_fixed_bitset(10).unwrap();
+        assert_eq!(result, Bytes::from(&[0b10101010, 0b11][..]));
+    }
+
+    //Every value a writer method accepts should read back unchanged
+    macro_rules! round_trip {
+        ($($name:ident: $ty:ty => $write:ident, $read:ident);* $(;)?) => {
+            proptest! {
+                $(
+                    #[test]
+                    fn $name(value: $ty) {
+                        let mut buffer = BytesMut::new();
+                        buffer.$write(&value).unwrap();
+
+                        prop_assert_eq!(buffer.$read().unwrap(), value);
+                        prop_assert!(buffer.is_empty());
+                    }
+                )*
+            }
+        };
+    }
+
+    round_trip! {
+        test_bool: bool => write_bool, read_bool;
+        test_i8: i8 => write_i8, read_i8;
+        test_u8: u8 => write_u8, read_u8;
+        test_i16: i16 => write_i16, read_i16;
+        test_u16: u16 => write_u16, read_u16;
+        test_i32: i32 => write_i32, read_i32;
+        test_u32: u32 => write_u32, read_u32;
+        test_i64: i64 => write_i64, read_i64;
+        test_u64: u64 => write_u64, read_u64;
+        test_var_int: i32 => write_var_int, read_var_int;
+        test_var_long: i64 => write_var_long, read_var_long;
+    }
+
+    proptest! {
+        //Compared bit for bit so NaNs count too
         #[test]
-        fn test_write_f32() {
-            let value: f32 = 3.14159;
+        fn test_f32(value: f32) {
             let mut buffer = BytesMut::new();
+            buffer.write_f32(&value).unwrap();
 
-            // Write the value
-            buffer.write_f32(&value);
-
-            // Read the value back
-            let result = buffer.read_f32().unwrap();
-            assert_eq!(result, value);
+            prop_assert_eq!(buffer.read_f32().unwrap().to_bits(), value.to_bits());
         }
 
         #[test]
-        fn test_write_f64() {
-            let value: f64 = 2.718281828459045;
+        fn test_f64(value: f64) {
             let mut buffer = BytesMut::new();
+            buffer.write_f64(&value).unwrap();
 
-            // Write the value
-            buffer.write_f64(&value);
-
-            // Read the value back
-            let result = buffer.read_f64().unwrap();
-            assert_eq!(result, value);
+            prop_assert_eq!(buffer.read_f64().unwrap().to_bits(), value.to_bits());
         }
 
         #[test]
-        fn test_write_string() {
-            let value = "Hello, world!";
+        fn test_string(value in "\\PC{0,16}") {
             let mut buffer = BytesMut::new();
+            buffer.write_string(&value, 32).unwrap();
 
-            // Write the value
-            buffer.write_string(&value, 256);
-
-            // Read the value back
-            let result = buffer.read_string(256).unwrap();
-            assert_eq!(result, value);
+            prop_assert_eq!(buffer.read_string(32).unwrap(), value);
+            prop_assert!(buffer.is_empty());
         }
 
         #[test]
-        fn test_write_full_string() {
-            let value = "Full test string!";
+        fn test_full_string(value: String) {
             let mut buffer = BytesMut::new();
+            buffer.write_full_string(&value).unwrap();
 
-            // Write the value
-            buffer.write_full_string(&value);
-
-            // Read the value back
-            let result = buffer.read_full_string().unwrap();
-            assert_eq!(result, value);
+            prop_assert_eq!(buffer.read_full_string().unwrap(), value);
+            prop_assert!(buffer.is_empty());
         }
 
         #[test]
-        fn test_write_var_int() {
-            let value: i32 = 123456;
+        fn test_uuid(value: u128) {
+            let value = Uuid::from_u128(value);
             let mut buffer = BytesMut::new();
+            buffer.write_uuid(&value).unwrap();
 
-            // Write the value
-            buffer.write_var_int(&value);
-
-            // Read the value back
-            let result = buffer.read_var_int().unwrap();
-            assert_eq!(result, value);
+            prop_assert_eq!(buffer.read_uuid().unwrap(), value);
         }
 
         #[test]
-        fn test_write_var_long() {
-            let value: i64 = 1234567890123456789;
+        fn test_option(value: Option<i32>) {
             let mut buffer = BytesMut::new();
+            buffer
+                .write_option(value, |buffer, value| buffer.write_var_int(value))
+                .unwrap();
 
-            // Write the value
-            buffer.write_var_long(&value);
-
-            // Read the value back
-            let result = buffer.read_var_long().unwrap();
-            assert_eq!(result, value);
+            prop_assert_eq!(buffer.read_option(|buffer| buffer.read_var_int()).unwrap(), value);
+            prop_assert!(buffer.is_empty());
         }
 
         #[test]
-        fn test_write_uuid() {
-            let mut bytes = [0u8; 16];
-
-            rand::thread_rng().fill_bytes(&mut bytes);
-        
-            let mut binding = Builder::from_bytes(bytes);
-            let value = binding
-                .set_variant(Variant::RFC4122)
-                .set_version(Version::Random).as_uuid();
+        fn test_array(value: Vec<u16>) {
+            let mut buffer = BytesMut::new();
+            buffer
+                .write_array(&value, |buffer, value| buffer.write_u16(value))
+                .unwrap();
 
+            prop_assert_eq!(buffer.read_array(|buffer| buffer.read_u16()).unwrap(), value);
+            prop_assert!(buffer.is_empty());
+        }
 
+        #[test]
+        fn test_bitset(value: Vec<i64>) {
             let mut buffer = BytesMut::new();
+            buffer.write_bitset(&value).unwrap();
 
-            // Write the value
-            buffer.write_uuid(value);
-
-            // Read the value back
-            let result = buffer.read_uuid().unwrap();
-            assert_eq!(result, *value);
+            prop_assert_eq!(buffer.read_bitset().unwrap(), value);
+            prop_assert!(buffer.is_empty());
         }
 
         #[test]
-        fn test_write_bitset() {
-            let value: Vec<i64> = vec![0b10101010, 0b11110000];
+        fn test_fixed_bitset(value in prop::collection::vec(any::<u8>(), 0..32)) {
+            let bits = value.len() * 8;
             let mut buffer = BytesMut::new();
+            buffer.write_fixed_bitset(&value, bits).unwrap();
 
-            // Write the value
-            buffer.write_bitset(&value);
-
-            // Read the value back
-            let result = buffer.read_fixed_bitset(2).unwrap();
-            assert_eq!(result, Bytes::from(&[0b10101010, 0b11110000][..]));
+            prop_assert_eq!(buffer.read_fixed_bitset(bits).unwrap(), Bytes::from(value));
+            prop_assert!(buffer.is_empty());
         }
     }
 }